@@ -1,160 +1,745 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
+use clap::{crate_version, App, AppSettings, Arg, ArgMatches, SubCommand};
 use dchp_client::dhcp::{
-    Deserialize, DhcpMessageType, DhcpOption, DhcpOptionType::*, DhcpPacket, Serialize,
-    TransactionToken,
+    arp, iaid_from_interface, release_packet, ClientAction, ClientEvent, ClientFqdn,
+    ClientIdentifier, DhcpClient, DhcpPacket, DhcpTransport, Duid, HookEvent, HookReason,
+    HookRunner, Lease, LeaseStore, LongestLease, PreferAddress, PreferServer, RawTransport,
+    ResolvConf, ResolvConfWriter, StoredLease, UdpTransport, VendorRegistry,
 };
-use log::{error, info};
-use mac_address::get_mac_address;
-use rand::random;
+#[cfg(feature = "netlink")]
+use dchp_client::dhcp::{InterfaceConfig, Netlink};
+use log::{info, warn};
+use mac_address::{get_mac_address, MacAddress};
 use simple_logger::SimpleLogger;
-use std::{net::UdpSocket, time::Duration};
+use std::{
+    fs, io,
+    net::Ipv4Addr,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime},
+};
+
+// Leases held on each interface, reloaded at startup
+const LEASE_STORE: &str = "/var/lib/dchp_client/leases";
+// Created on first use and kept, so the host's identity outlives its NICs
+const DUID_FILE: &str = "/var/lib/dchp_client/duid";
+const MACHINE_ID: &str = "/etc/machine-id";
+
+// Which host and link the commands act for, and who to tell about lease changes
+struct Target {
+    mac_address: MacAddress,
+    interface: Option<String>,
+    hook: Option<HookRunner>,
+    resolver: Option<ResolvConfWriter>,
+    client_id: Option<ClientIdentifier>,
+    host_name: Option<String>,
+    fqdn: Option<ClientFqdn>,
+    vendor_class: Option<String>,
+}
+
+impl Target {
+    // Leases are stored by interface, or by mac address when there is none
+    fn get_store_key(&self) -> String {
+        match &self.interface {
+            Some(interface) => interface.clone(),
+            None => self.mac_address.to_string(),
+        }
+    }
+
+    fn new_client(&self) -> Result<DhcpClient> {
+        let mut client = DhcpClient::new(self.mac_address);
+        if let Some(client_id) = &self.client_id {
+            client = client.with_client_id(client_id);
+        }
+        if let Some(host_name) = &self.host_name {
+            client = client
+                .with_host_name(host_name)
+                .context("Invalid --hostname")?;
+        }
+        if let Some(fqdn) = &self.fqdn {
+            client = client.with_fqdn(fqdn);
+        }
+        if let Some(vendor_class) = &self.vendor_class {
+            client = client.with_vendor_class(vendor_class.as_bytes());
+        }
+        Ok(client)
+    }
+}
 
-const BUFFER_SIZE: usize = 1024;
 fn main() -> Result<()> {
     // Setup logging
     SimpleLogger::new().init().unwrap();
 
-    // Collect info
-    let mac_address = get_mac_address().unwrap().unwrap();
-    info!("Using MacAddress {}", mac_address);
-    let transaction_token: TransactionToken = random();
-    info!(
-        "Session transaction token {:#x?}",
-        u32::from_be_bytes(transaction_token)
-    );
-
-    // Setup sockets
-    let (wsock, rsock) = setup_sockets();
-
-    // Send DISCOVERY message
-    let discovery_packet = DhcpPacket::new()
-        .with_transaction(&transaction_token)
-        .with_mac_address(&mac_address)
-        .with_option(DhcpOption::new(
-            MessageType as u8,
-            vec![DhcpMessageType::Discover as u8],
-        ));
-
-    info!("Sending DHCPDISCOVERY packet");
-    wsock
-        .send(&discovery_packet.serialize())
-        .expect("Send discovery packet failed");
-
-    // Wait for OFFER message
-    let offer_packet = loop {
-        info!("Waiting for response...");
-        let mut rbuffer = [0; BUFFER_SIZE];
-        let rsize_result = rsock.recv(&mut rbuffer);
-        if rsize_result.is_err() {
-            return Err(anyhow!("No OFFER message recieved"));
-        }
-        let rsize = rsize_result.unwrap();
-        let rbuffer_sliced = &rbuffer[0..rsize];
-        let rpacket = DhcpPacket::deserialize(rbuffer_sliced).expect("OFFER packet not parseable");
-        let is_correct_packet =
-            rpacket.is_type(DhcpMessageType::Offer) && rpacket.is_transaction(&transaction_token);
-        if is_correct_packet {
-            info!("Received the DHCPOFFER packet");
-            break rpacket;
+    let lease_time = Arg::with_name("lease-time")
+        .long("lease-time")
+        .short("t")
+        .takes_value(true)
+        .value_name("SECONDS")
+        .help("Lease time to ask the server for");
+    let matches = App::new("dhcp-client")
+        .version(crate_version!())
+        .about("Acquires and manages a DHCP lease")
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(
+            Arg::with_name("interface")
+                .long("interface")
+                .short("i")
+                .takes_value(true)
+                .global(true)
+                .help("Network interface to use, found from the mac address by default"),
+        )
+        .arg(
+            Arg::with_name("mac")
+                .long("mac")
+                .short("m")
+                .takes_value(true)
+                .global(true)
+                .help("Hardware address to send as chaddr, the interface's by default"),
+        )
+        .arg(
+            Arg::with_name("script")
+                .long("script")
+                .short("s")
+                .takes_value(true)
+                .global(true)
+                .value_name("PATH")
+                .help("dhclient-script compatible script to run on every lease change"),
+        )
+        .arg(
+            Arg::with_name("resolv-conf")
+                .long("resolv-conf")
+                .short("r")
+                .takes_value(true)
+                .global(true)
+                .value_name("PATH")
+                .help("resolv.conf or resolvconf fragment to write the lease's DNS settings to"),
+        )
+        .arg(
+            Arg::with_name("duid")
+                .long("duid")
+                .short("d")
+                .takes_value(true)
+                .global(true)
+                .value_name("TYPE")
+                .possible_values(&["llt", "ll", "en", "uuid"])
+                .help("Identify with an IAID and a DUID of this type, created once and kept"),
+        )
+        .arg(
+            Arg::with_name("enterprise-number")
+                .long("enterprise-number")
+                .takes_value(true)
+                .global(true)
+                .value_name("NUMBER")
+                .required_if("duid", "en")
+                .help("IANA enterprise number of a DUID-EN"),
+        )
+        .arg(
+            Arg::with_name("duid-identifier")
+                .long("duid-identifier")
+                .takes_value(true)
+                .global(true)
+                .value_name("HEX")
+                .required_if("duid", "en")
+                .help("Identifier of a DUID-EN as colon separated hex, like 0a:1b:2c"),
+        )
+        .arg(
+            Arg::with_name("hostname")
+                .long("hostname")
+                .short("H")
+                .takes_value(true)
+                .global(true)
+                .value_name("NAME")
+                .help("Host name to send to the server"),
+        )
+        .arg(
+            Arg::with_name("fqdn")
+                .long("fqdn")
+                .takes_value(true)
+                .global(true)
+                .value_name("NAME")
+                .conflicts_with("hostname")
+                .help(
+                    "Name the server should register in DNS, fully qualified when it ends in a dot",
+                ),
+        )
+        .arg(
+            Arg::with_name("no-dns-update")
+                .long("no-dns-update")
+                .global(true)
+                .requires("fqdn")
+                .help("Ask the server not to update DNS for the name"),
+        )
+        .arg(
+            Arg::with_name("vendor-class")
+                .long("vendor-class")
+                .takes_value(true)
+                .global(true)
+                .value_name("CLASS")
+                .help("Vendor class identifier the server picks vendor specific options by"),
+        )
+        .subcommand(
+            SubCommand::with_name("acquire")
+                .about("Acquire and keep a lease (default)")
+                .arg(
+                    Arg::with_name("ip")
+                        .long("ip")
+                        .takes_value(true)
+                        .help("Address to ask the server for"),
+                )
+                .arg(lease_time.clone())
+                .arg(
+                    Arg::with_name("offer-window")
+                        .short("w")
+                        .long("offer-window")
+                        .takes_value(true)
+                        .value_name("SECONDS")
+                        .help("How long to collect offers before picking one"),
+                )
+                .arg(
+                    Arg::with_name("prefer-server")
+                        .long("prefer-server")
+                        .takes_value(true)
+                        .value_name("IP")
+                        .conflicts_with("longest-lease")
+                        .help("Take the offer from this server when it makes one"),
+                )
+                .arg(
+                    Arg::with_name("longest-lease")
+                        .long("longest-lease")
+                        .help("Take the offer with the longest lease"),
+                )
+                .arg(
+                    Arg::with_name("rapid-commit")
+                        .long("rapid-commit")
+                        .help("Ask servers to skip the OFFER and REQUEST"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("renew")
+                .about("Extend the currently held lease and exit")
+                .arg(lease_time),
+        )
+        .subcommand(SubCommand::with_name("release").about("Release the currently held lease"))
+        .subcommand(
+            SubCommand::with_name("inform")
+                .about("Fetch network settings for a statically addressed host")
+                .arg(
+                    Arg::with_name("ip")
+                        .help("The static ip of this host")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("probe")
+                .about("Check with ARP whether another host uses an address")
+                .arg(
+                    Arg::with_name("ip")
+                        .help("The address to probe")
+                        .required(true),
+                ),
+        )
+        .get_matches();
+
+    let (command, args) = match matches.subcommand() {
+        (command, Some(args)) => (command, args),
+        _ => ("acquire", &matches),
+    };
+    let target = find_target(args)?;
+    info!("Using MacAddress {}", target.mac_address);
+    let mut store = LeaseStore::open(LEASE_STORE).context("Cannot read the lease store")?;
+
+    match command {
+        "renew" => {
+            let held = held_lease(&store, &target).ok_or_else(|| anyhow!("No lease is held"))?;
+            let lease = held.to_lease((Instant::now(), SystemTime::now()));
+            let mut client = target.new_client()?.with_held_lease(&lease);
+            if let Some(lease_time) = parse_lease_time(args)? {
+                client = client.with_lease_time(lease_time);
+            }
+            run(client, &target, &mut store, true)
+        }
+        "release" => release(&target, &mut store),
+        "inform" => inform(&target, parse_arg(args, "ip")?.unwrap()),
+        "probe" => probe(&target, parse_arg(args, "ip")?.unwrap()),
+        _ => {
+            let mut client = target.new_client()?;
+            let mut wanted_ip = None;
+            // Try to reclaim the address held before a restart
+            if let Some(held) = held_lease(&store, &target) {
+                info!("Found held lease on {}", held.get_client_ip());
+                client = client.with_previous_ip(&held.get_client_ip());
+                wanted_ip = Some(held.get_client_ip());
+            }
+            if let Some(ip) = parse_arg(args, "ip")? {
+                client = client.with_requested_ip(&ip);
+                wanted_ip = Some(ip);
+            }
+            client = client.with_rapid_commit(args.is_present("rapid-commit"));
+            if let Some(window) = parse_arg(args, "offer-window")? {
+                client = client.with_offer_window(Duration::from_secs(window));
+            }
+            if let Some(server_ip) = parse_arg(args, "prefer-server")? {
+                client = client.with_offer_selector(PreferServer::new(&server_ip));
+            } else if args.is_present("longest-lease") {
+                client = client.with_offer_selector(LongestLease);
+            } else if let Some(ip) = wanted_ip {
+                client = client.with_offer_selector(PreferAddress::new(&ip));
+            }
+            if let Some(lease_time) = parse_lease_time(args)? {
+                client = client.with_lease_time(lease_time);
+            }
+            run(client, &target, &mut store, false)
+        }
+    }
+}
+
+fn parse_arg<T: FromStr>(args: &ArgMatches, name: &str) -> Result<Option<T>> {
+    match args.value_of(name) {
+        Some(value) => match value.parse() {
+            Ok(parsed) => Ok(Some(parsed)),
+            Err(_) => Err(anyhow!("{} '{}' is not parseable", name, value)),
+        },
+        None => Ok(None),
+    }
+}
+
+fn parse_lease_time(args: &ArgMatches) -> Result<Option<Duration>> {
+    Ok(parse_arg(args, "lease-time")?.map(Duration::from_secs))
+}
+
+// An explicit mac address wins, then the interface's own, then the first one found
+fn find_target(args: &ArgMatches) -> Result<Target> {
+    let interface: Option<String> = args.value_of("interface").map(String::from);
+    let mac_address = match (parse_arg::<MacAddress>(args, "mac")?, &interface) {
+        (Some(mac_address), _) => mac_address,
+        (None, Some(interface)) => interface_mac_address(interface)?,
+        (None, None) => get_mac_address()
+            .context("Cannot read mac addresses")?
+            .ok_or_else(|| anyhow!("No mac address found, pass one with --mac"))?,
+    };
+    let interface = interface.or_else(|| find_interface(&mac_address));
+    let mut target = Target {
+        mac_address,
+        interface,
+        hook: args.value_of("script").map(HookRunner::new),
+        resolver: args.value_of("resolv-conf").map(ResolvConfWriter::new),
+        client_id: None,
+        host_name: args.value_of("hostname").map(String::from),
+        fqdn: match args.value_of("fqdn") {
+            Some(fqdn) => Some(
+                ClientFqdn::new(fqdn)
+                    .context("Invalid --fqdn")?
+                    .with_no_updates(args.is_present("no-dns-update")),
+            ),
+            None => None,
+        },
+        vendor_class: args.value_of("vendor-class").map(String::from),
+    };
+    if let Some(duid_type) = args.value_of("duid") {
+        let duid = load_duid(duid_type, &mac_address, args)?;
+        info!("Using DUID {}", duid);
+        let iaid = iaid_from_interface(&target.get_store_key());
+        target.client_id = Some(ClientIdentifier::new(iaid, duid));
+    }
+    Ok(target)
+}
+
+// A saved DUID is used whatever its type, changing it would change the host's identity
+fn load_duid(duid_type: &str, mac_address: &MacAddress, args: &ArgMatches) -> Result<Duid> {
+    // Bad arguments are reported even when a saved DUID makes them unused
+    let enterprise = match duid_type {
+        "en" => {
+            let number = parse_arg(args, "enterprise-number")?.unwrap();
+            let identifier = args.value_of("duid-identifier").unwrap();
+            let identifier = parse_hex(identifier)
+                .ok_or_else(|| anyhow!("duid-identifier '{}' is not parseable", identifier))?;
+            Some(Duid::from_enterprise(number, &identifier))
         }
+        _ => None,
     };
+    let duid = Duid::load_or_create(DUID_FILE, || match duid_type {
+        "llt" => Ok(Duid::from_mac_time(mac_address, SystemTime::now())),
+        "ll" => Ok(Duid::from_mac(mac_address)),
+        "en" => Ok(enterprise.unwrap()),
+        _ => machine_id().map(|x| Duid::from_machine_id(&x)),
+    });
+    duid.context("Cannot load or create the DUID")
+}
 
-    // Process OFFER message
-    let offered_ip = offer_packet.get_client_ip();
-    let offered_lease_time = offer_packet
-        .get_lease_time()
-        .expect("Offer message lease time was not parseable")
-        .as_secs();
-    let dhcp_server_ip = offer_packet
-        .get_server_ip()
-        .expect("Offer message dhcp server ip was not parseable");
-    info!(
-        "DHCP Server {} offered ip {} with a lease of {}s",
-        dhcp_server_ip, offered_ip, offered_lease_time
-    );
-
-    // Send REQUEST message
-    let request_packet = DhcpPacket::new()
-        .with_transaction(&transaction_token)
-        .with_mac_address(&mac_address)
-        .with_option(DhcpOption::new(
-            RequestedIp as u8,
-            offered_ip.octets().to_vec(),
-        ))
-        .with_option(DhcpOption::new(
-            MessageType as u8,
-            vec![DhcpMessageType::Request as u8],
-        ))
-        .with_option(DhcpOption::new(
-            DhcpServerIp as u8,
-            dhcp_server_ip.octets().to_vec(),
-        ));
-
-    info!("Sending DHCPREQUEST packet");
-    wsock
-        .send(&request_packet.serialize())
-        .expect("Failed to send request packet");
-
-    // Wait for ACK/NAK message
-    let ack_packet = loop {
-        info!("Waiting for response...");
-        let mut rbuffer = [0; BUFFER_SIZE];
-        let rsize_result = rsock.recv(&mut rbuffer);
-        if rsize_result.is_err() {
-            return Err(anyhow!("No ACK/NAK message recieved"));
-        }
-        let rsize = rsize_result.unwrap();
-        let rbuffer_sliced = &rbuffer[0..rsize];
-        let rpacket =
-            DhcpPacket::deserialize(rbuffer_sliced).expect("ACK/NAK packet not parseable");
-        let from_correct_sender = match rpacket.get_server_ip() {
-            Some(addr) => addr == dhcp_server_ip,
-            None => false,
-        };
-        let is_correct_packet = (rpacket.is_type(DhcpMessageType::Ack)
-            || rpacket.is_type(DhcpMessageType::Nak))
-            && rpacket.is_transaction(&transaction_token)
-            && from_correct_sender;
-        if is_correct_packet {
-            info!("Received the ACK/NAK packet");
-            break rpacket;
+fn parse_hex(value: &str) -> Option<Vec<u8>> {
+    value
+        .split(':')
+        .map(|x| match x.len() {
+            2 => u8::from_str_radix(x, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+// systemd's machine id is 128 bits of hex, stable for the life of the installation.
+// It is only ever sent hashed, see Duid::from_machine_id
+fn machine_id() -> io::Result<[u8; 16]> {
+    let id = fs::read_to_string(MACHINE_ID)?;
+    let id = id.trim();
+    let mut machine_id = [0; 16];
+    for (i, byte) in machine_id.iter_mut().enumerate() {
+        *byte = id
+            .get(i * 2..i * 2 + 2)
+            .and_then(|x| u8::from_str_radix(x, 16).ok())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is not a machine id", MACHINE_ID),
+                )
+            })?;
+    }
+    Ok(machine_id)
+}
+
+// Drive the client state machine, keeping the lease alive until stopped. With `once` it
+// stops at the first lease it gets or extends, and fails if the lease is lost instead
+fn run(mut client: DhcpClient, target: &Target, store: &mut LeaseStore, once: bool) -> Result<()> {
+    let mut transport = setup_transport(target)?;
+    client = client.with_conflict_detection(target.interface.is_some());
+    let mut actions = client.handle(ClientEvent::Start, Instant::now());
+    loop {
+        let mut next_event = None;
+        for action in transport.perform(actions)? {
+            match action {
+                ClientAction::Probe(ip) => {
+                    let interface = target.interface.as_deref().unwrap();
+                    next_event = Some(probe_address(interface, &target.mac_address, &ip));
+                }
+                ClientAction::Bound(lease) => {
+                    info!(
+                        "Bound to {}, renewing in {}s",
+                        lease.get_client_ip(),
+                        lease.get_renewal_time().as_secs()
+                    );
+                    let held_ip = held_lease(store, target).map(|x| x.get_client_ip());
+                    let reason = match held_ip == Some(lease.get_client_ip()) {
+                        true => HookReason::Reboot,
+                        false => HookReason::Bound,
+                    };
+                    log_vendor_options(target, lease.get_packet());
+                    store_lease(store, target, &lease, reason)?;
+                    if once {
+                        return Ok(());
+                    }
+                }
+                ClientAction::Renewed(lease) => {
+                    info!(
+                        "Lease on {} extended by {}s",
+                        lease.get_client_ip(),
+                        lease.get_lease_time().as_secs()
+                    );
+                    store_lease(store, target, &lease, HookReason::Renew)?;
+                    if once {
+                        return Ok(());
+                    }
+                }
+                ClientAction::Rebound(lease) => {
+                    info!(
+                        "Lease on {} extended by {}s from another server",
+                        lease.get_client_ip(),
+                        lease.get_lease_time().as_secs()
+                    );
+                    store_lease(store, target, &lease, HookReason::Rebind)?;
+                    if once {
+                        return Ok(());
+                    }
+                }
+                // Scripts hear about a refused lease as an expired one, like with dhclient
+                ClientAction::Nak(_) => {
+                    forget_lease(store, target, HookReason::Expire)?;
+                    if once {
+                        return Err(anyhow!("DHCP Server refused the lease"));
+                    }
+                    warn!("Restarting after DHCP Server refused the request");
+                }
+                ClientAction::Expired(lease) => {
+                    forget_lease(store, target, HookReason::Expire)?;
+                    if once {
+                        return Err(anyhow!("Lease on {} expired", lease.get_client_ip()));
+                    }
+                    warn!("Lease on {} expired, restarting", lease.get_client_ip());
+                }
+                ClientAction::Released(_) => forget_lease(store, target, HookReason::Release)?,
+                ClientAction::TimedOut(state) => {
+                    run_hook(
+                        target,
+                        HookEvent::new(HookReason::Fail, &target.get_store_key()),
+                    );
+                    return Err(anyhow!("No response received while {:?}", state));
+                }
+                _ => {}
+            }
         }
+
+        let event = match next_event {
+            Some(event) => event,
+            None => transport.wait_for_event(client.get_timeout())?,
+        };
+        actions = client.handle(event, Instant::now());
+    }
+}
+
+// No vendor decoders are built in, so sub-options are shown as hex
+fn log_vendor_options(target: &Target, ack: &DhcpPacket) {
+    let vendor_class = target.vendor_class.as_deref().unwrap_or_default();
+    let sub_options = match VendorRegistry::new().decode_packet(vendor_class.as_bytes(), ack) {
+        Some(sub_options) => sub_options,
+        None => return,
     };
+    for (code, value) in sub_options {
+        info!("Vendor option {}: {}", code, value);
+    }
+}
+
+fn inform(target: &Target, static_ip: Ipv4Addr) -> Result<()> {
+    let mut transport = setup_transport(target)?;
+
+    let mut client = target.new_client()?.with_static_ip(&static_ip);
+    let mut actions = client.handle(ClientEvent::Start, Instant::now());
+    loop {
+        for action in transport.perform(actions)? {
+            match action {
+                ClientAction::Informed(ack) => {
+                    let format_ips = |ips: Vec<Ipv4Addr>| {
+                        ips.iter()
+                            .map(|x| x.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    };
+                    info!("Subnet mask: {:?}", ack.get_subnet());
+                    info!("Router: {:?}", ack.get_router_ip());
+                    info!("DNS servers: {}", format_ips(ack.get_dns_servers()));
+                    info!("NTP servers: {}", format_ips(ack.get_ntp_servers()));
+                    info!("Domain name: {:?}", ack.get_domain_name());
+                    log_vendor_options(target, &ack);
+                    return Ok(());
+                }
+                ClientAction::TimedOut(_) => return Err(anyhow!("No DHCPINFORM answer received")),
+                _ => {}
+            }
+        }
+
+        let event = transport.wait_for_event(client.get_timeout())?;
+        actions = client.handle(event, Instant::now());
+    }
+}
 
-    // Process ACK/NAK message
-    let is_ack_message = ack_packet.get_type().unwrap() == DhcpMessageType::Ack;
-    let offered_ip = ack_packet.get_client_ip();
-    let offered_lease_time = ack_packet.get_lease_time().unwrap().as_secs();
-    let dhcp_server_ip = ack_packet.get_server_ip().unwrap();
-    if is_ack_message {
-        info!(
-            "DHCP Server {} accepted the assigned ip {} with a lease of {}s",
-            dhcp_server_ip, offered_ip, offered_lease_time
-        );
-    } else {
-        error!(
-            "DHCP Server {} refused assigned ip {} with a lease of {}s",
-            dhcp_server_ip, offered_ip, offered_lease_time
-        );
+fn release(target: &Target, store: &mut LeaseStore) -> Result<()> {
+    let held = held_lease(store, target).ok_or_else(|| anyhow!("No lease is held"))?;
+    let (client_ip, server_ip) = (held.get_client_ip(), held.get_server_ip());
+    info!("Releasing {} back to DHCP Server {}", client_ip, server_ip);
+
+    let mut release = release_packet(&target.mac_address, &client_ip, &server_ip);
+    if let Some(client_id) = &target.client_id {
+        release = release.with_option(client_id.get_option());
+    }
+    let mut transport = setup_transport(target)?;
+    transport
+        .send(server_ip, &release)
+        .context("Failed to send release packet")?;
+    forget_lease(store, target, HookReason::Release)?;
+    Ok(())
+}
+
+fn probe(target: &Target, ip: Ipv4Addr) -> Result<()> {
+    let interface = target
+        .interface
+        .as_deref()
+        .ok_or_else(|| anyhow!("No interface found to probe on, pass one with --interface"))?;
+    info!("Probing {} on {}", ip, interface);
+    match arp::probe(interface, &target.mac_address, &ip).context("Probe failed")? {
+        true => info!("{} is in use by another host", ip),
+        false => info!("{} is free", ip),
+    }
+    Ok(())
+}
+
+fn interface_mac_address(interface: &str) -> Result<MacAddress> {
+    let address = fs::read_to_string(format!("/sys/class/net/{}/address", interface))
+        .with_context(|| format!("Unknown interface {}", interface))?;
+    address
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Interface {} has no usable mac address", interface))
+}
+
+// Find the name of the interface the mac address belongs to
+fn find_interface(mac_address: &MacAddress) -> Option<String> {
+    let wanted = mac_address.to_string().to_lowercase();
+    fs::read_dir("/sys/class/net")
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .find(|name| {
+            fs::read_to_string(format!("/sys/class/net/{}/address", name))
+                .map(|address| address.trim() == wanted)
+                .unwrap_or(false)
+        })
+}
+
+fn probe_address(interface: &str, mac_address: &MacAddress, ip: &Ipv4Addr) -> ClientEvent {
+    match arp::probe(interface, mac_address, ip) {
+        Ok(true) => ClientEvent::AddressInUse,
+        Ok(false) => ClientEvent::AddressAvailable,
+        Err(e) => {
+            warn!("Unable to probe {} on {}: {}", ip, interface, e);
+            ClientEvent::AddressAvailable
+        }
+    }
+}
+
+// Expired leases are of no use for reclaiming or releasing the address
+fn held_lease(store: &LeaseStore, target: &Target) -> Option<StoredLease> {
+    store
+        .get_lease(&target.get_store_key())
+        .filter(|x| !x.is_expired(SystemTime::now()))
+        .cloned()
+}
+
+// Also configures the interface and tells the hook script why the lease changed
+fn store_lease(
+    store: &mut LeaseStore,
+    target: &Target,
+    lease: &Lease,
+    reason: HookReason,
+) -> Result<()> {
+    let key = target.get_store_key();
+    let mut event =
+        HookEvent::new(reason, &key).with_new_lease(&lease.get_client_ip(), lease.get_packet());
+    let mut original_mtu = None;
+    if let Some(previous) = store.get_lease(&key) {
+        // A lease on another address, e.g. one that expired while we were not running
+        if previous.get_client_ip() != lease.get_client_ip() {
+            unconfigure_interface(target, previous);
+        } else {
+            original_mtu = previous.get_original_mtu();
+        }
+        event = event.with_old_lease(&previous.get_client_ip(), previous.get_packet());
+    }
+
+    let original_mtu = configure_interface(target, lease, original_mtu);
+    let stored = StoredLease::from_lease(&key, lease, (Instant::now(), SystemTime::now()))
+        .with_original_mtu(original_mtu);
+    store
+        .insert_lease(stored)
+        .context("Cannot write the lease store")?;
+    configure_resolver(target, lease.get_packet());
+    run_hook(target, event);
+    Ok(())
+}
+
+// Also takes the forgotten lease's address and routes off the interface
+fn forget_lease(store: &mut LeaseStore, target: &Target, reason: HookReason) -> Result<()> {
+    let key = target.get_store_key();
+    let removed = store
+        .remove_lease(&key)
+        .context("Cannot write the lease store")?;
+    if let Some(held) = removed {
+        unconfigure_interface(target, &held);
+        restore_resolver(target);
+        let event =
+            HookEvent::new(reason, &key).with_old_lease(&held.get_client_ip(), held.get_packet());
+        run_hook(target, event);
     }
     Ok(())
 }
 
-fn setup_sockets() -> (UdpSocket, UdpSocket) {
-    info!("Setting up sockets...");
-    // ? Should this use the ANY socket? This may pick the wrong interface
-    // Should propably iterate through all interfaces
-    let wsock = UdpSocket::bind("0.0.0.0:67").expect("Cannot bind to outbound UDP socket");
-    wsock
-        .set_broadcast(true)
-        .expect("Unable to set outbound socket broadcast state");
-    wsock
-        .connect("255.255.255.255:67")
-        .expect("Cannot connect to UDB broadcast");
-    let rsock = UdpSocket::bind("0.0.0.0:68").expect("Cannot bind to inbound UDP socket");
-    rsock
-        .set_read_timeout(Some(Duration::from_secs(10)))
-        .unwrap();
-
-    (wsock, rsock)
+// Leases without any DNS settings leave the resolver as it is
+fn configure_resolver(target: &Target, ack: &DhcpPacket) {
+    let conf = ResolvConf::from_packet(ack);
+    if let (Some(resolver), false) = (&target.resolver, conf.is_empty()) {
+        if let Err(e) = resolver.write(&conf) {
+            warn!("Unable to write {}: {}", resolver.get_path().display(), e);
+        }
+    }
+}
+
+fn restore_resolver(target: &Target) {
+    if let Some(resolver) = &target.resolver {
+        if let Err(e) = resolver.restore() {
+            warn!("Unable to restore {}: {}", resolver.get_path().display(), e);
+        }
+    }
+}
+
+// A failing script is reported but does not stop the client
+fn run_hook(target: &Target, event: HookEvent) {
+    if let Some(hook) = &target.hook {
+        let script = hook.get_script().display();
+        match hook.run(&event) {
+            Ok(status) if status.success() => {}
+            Ok(status) => warn!("{} {} exited with {}", script, event.get_reason(), status),
+            Err(e) => warn!("Unable to run {}: {}", script, e),
+        }
+    }
+}
+
+// A failure to configure is logged rather than fatal, the lease is still worth keeping.
+// Returns the MTU to restore on removal, `original_mtu` being the one kept from before
+#[cfg(feature = "netlink")]
+fn configure_interface(target: &Target, lease: &Lease, original_mtu: Option<u16>) -> Option<u16> {
+    let interface = target.interface.as_ref()?;
+    let config = InterfaceConfig::from_lease(interface, lease).with_original_mtu(original_mtu);
+    match Netlink::open().and_then(|netlink| netlink.apply(&config)) {
+        Ok(original_mtu) => {
+            info!(
+                "Configured {}/{} on {}",
+                config.get_address(),
+                config.get_prefix_len(),
+                interface
+            );
+            original_mtu
+        }
+        Err(e) => {
+            warn!("Unable to configure {}: {}", interface, e);
+            original_mtu
+        }
+    }
+}
+
+#[cfg(feature = "netlink")]
+fn unconfigure_interface(target: &Target, held: &StoredLease) {
+    if let Some(interface) = &target.interface {
+        let address = held.get_client_ip();
+        let config = InterfaceConfig::new(interface, &address, held.get_packet())
+            .with_original_mtu(held.get_original_mtu());
+        match Netlink::open().and_then(|netlink| netlink.remove(&config)) {
+            Ok(()) => info!("Removed {} from {}", address, interface),
+            Err(e) => warn!("Unable to remove {} from {}: {}", address, interface, e),
+        }
+    }
+}
+
+#[cfg(not(feature = "netlink"))]
+fn configure_interface(
+    _target: &Target,
+    _lease: &Lease,
+    _original_mtu: Option<u16>,
+) -> Option<u16> {
+    None
+}
+
+#[cfg(not(feature = "netlink"))]
+fn unconfigure_interface(_target: &Target, _held: &StoredLease) {}
+
+// Raw frames reach the server before the interface has an address, plain UDP is the
+// fallback when the interface cannot be found
+fn setup_transport(target: &Target) -> Result<Box<dyn DhcpTransport>> {
+    match target.interface.as_deref() {
+        Some(interface) => {
+            info!("Setting up raw socket on {}...", interface);
+            let transport = RawTransport::open(interface, &target.mac_address)
+                .with_context(|| format!("Cannot open raw socket on {}", interface))?;
+            Ok(Box::new(transport))
+        }
+        None => {
+            info!("Setting up UDP sockets...");
+            let transport = UdpTransport::new().context("Cannot bind to DHCP client port")?;
+            Ok(Box::new(transport))
+        }
+    }
 }
@@ -0,0 +1,278 @@
+use crate::dhcp::{
+    error::DhcpParseError,
+    traits::{Deserialize, Serialize},
+};
+use mac_address::MacAddress;
+use std::{convert::TryInto, net::Ipv4Addr};
+
+// Ethernet/IPv4 ARP packets are always this long
+pub const ARP_PACKET_SIZE: usize = 28;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ArpOperation {
+    Request = 1,
+    Reply = 2,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ArpPacket {
+    htype: [u8; 2],
+    ptype: [u8; 2],
+    hlen: u8,
+    plen: u8,
+    oper: [u8; 2],
+    sha: [u8; 6],
+    spa: [u8; 4],
+    tha: [u8; 6],
+    tpa: [u8; 4],
+}
+
+impl ArpPacket {
+    pub fn new(operation: ArpOperation) -> Self {
+        ArpPacket {
+            htype: [0x00, 0x01],
+            ptype: [0x08, 0x00],
+            hlen: 0x06,
+            plen: 0x04,
+            oper: (operation as u16).to_be_bytes(),
+            sha: [0x00; 6],
+            spa: [0x00; 4],
+            tha: [0x00; 6],
+            tpa: [0x00; 4],
+        }
+    }
+
+    // RFC 5227 probe: a request for the address with an all zero sender ip
+    pub fn new_probe(maddr: &MacAddress, ip: &Ipv4Addr) -> Self {
+        ArpPacket::new(ArpOperation::Request)
+            .with_sender(maddr, &Ipv4Addr::UNSPECIFIED)
+            .with_target_ip(ip)
+    }
+
+    pub fn with_sender(mut self, maddr: &MacAddress, ip: &Ipv4Addr) -> Self {
+        self.sha = maddr.bytes();
+        self.spa = ip.octets();
+        self
+    }
+
+    pub fn with_target_ip(mut self, ip: &Ipv4Addr) -> Self {
+        self.tpa = ip.octets();
+        self
+    }
+
+    pub fn get_sender_mac(&self) -> MacAddress {
+        MacAddress::new(self.sha)
+    }
+
+    pub fn get_sender_ip(&self) -> Ipv4Addr {
+        Ipv4Addr::from(self.spa)
+    }
+
+    pub fn get_target_ip(&self) -> Ipv4Addr {
+        Ipv4Addr::from(self.tpa)
+    }
+
+    // Per RFC 5227 section 2.1.1 another host owns the address if it claims it as sender,
+    // or if it is probing for the same address at the same time
+    pub fn is_conflict(&self, own_mac: &MacAddress, ip: &Ipv4Addr) -> bool {
+        if self.sha == own_mac.bytes() {
+            return false;
+        }
+        let sender_ip = self.get_sender_ip();
+        let is_probe_for_ip = sender_ip.is_unspecified()
+            && self.get_target_ip() == *ip
+            && self.oper == (ArpOperation::Request as u16).to_be_bytes();
+        sender_ip == *ip || is_probe_for_ip
+    }
+}
+
+impl Serialize for ArpPacket {
+    type Out = Vec<u8>;
+    fn serialize(&self) -> Self::Out {
+        let mut buffer = Vec::with_capacity(ARP_PACKET_SIZE);
+
+        buffer.extend_from_slice(&self.htype);
+        buffer.extend_from_slice(&self.ptype);
+        buffer.extend_from_slice(&[self.hlen]);
+        buffer.extend_from_slice(&[self.plen]);
+        buffer.extend_from_slice(&self.oper);
+        buffer.extend_from_slice(&self.sha);
+        buffer.extend_from_slice(&self.spa);
+        buffer.extend_from_slice(&self.tha);
+        buffer.extend_from_slice(&self.tpa);
+
+        buffer
+    }
+}
+
+impl Deserialize for ArpPacket {
+    type Out = Self;
+    type Error = DhcpParseError;
+    fn deserialize(data: &[u8]) -> Result<Self::Out, Self::Error> {
+        if data.len() < ARP_PACKET_SIZE {
+            return Err(DhcpParseError::TruncatedHeader {
+                offset: data.len(),
+                expected: ARP_PACKET_SIZE,
+            });
+        }
+        let packet = ArpPacket {
+            htype: data[0..2].try_into().unwrap(),
+            ptype: data[2..4].try_into().unwrap(),
+            hlen: data[4],
+            plen: data[5],
+            oper: data[6..8].try_into().unwrap(),
+            sha: data[8..14].try_into().unwrap(),
+            spa: data[14..18].try_into().unwrap(),
+            tha: data[18..24].try_into().unwrap(),
+            tpa: data[24..28].try_into().unwrap(),
+        };
+        // Only Ethernet/IPv4 is understood
+        if packet.hlen != 6 {
+            return Err(DhcpParseError::UnsupportedAddressLength {
+                offset: 4,
+                length: packet.hlen,
+            });
+        }
+        if packet.plen != 4 {
+            return Err(DhcpParseError::UnsupportedAddressLength {
+                offset: 5,
+                length: packet.plen,
+            });
+        }
+        Ok(packet)
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use linux::probe;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::*;
+    use crate::dhcp::raw::PacketSocket;
+    use rand::{thread_rng, Rng};
+    use std::{
+        io, thread,
+        time::{Duration, Instant},
+    };
+
+    // Timing constants from RFC 5227 section 1.1
+    const PROBE_WAIT: Duration = Duration::from_secs(1);
+    const PROBE_NUM: u32 = 3;
+    const PROBE_MIN: Duration = Duration::from_secs(1);
+    const PROBE_MAX: Duration = Duration::from_secs(2);
+    const ANNOUNCE_WAIT: Duration = Duration::from_secs(2);
+
+    // Probe the interface for another host using the ip, returns true on a conflict
+    pub fn probe(interface: &str, maddr: &MacAddress, ip: &Ipv4Addr) -> io::Result<bool> {
+        let socket = ArpSocket::open(interface)?;
+        let probe = ArpPacket::new_probe(maddr, ip);
+        let mut rng = thread_rng();
+
+        thread::sleep(rng.gen_range(Duration::from_secs(0)..PROBE_WAIT));
+        for sent in 1..=PROBE_NUM {
+            socket.send(&probe)?;
+            let wait = if sent == PROBE_NUM {
+                ANNOUNCE_WAIT
+            } else {
+                rng.gen_range(PROBE_MIN..PROBE_MAX)
+            };
+            if socket.wait_for_conflict(maddr, ip, Instant::now() + wait)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // Receives ARP bodies without the Ethernet header
+    struct ArpSocket {
+        socket: PacketSocket,
+    }
+
+    impl ArpSocket {
+        fn open(interface: &str) -> io::Result<Self> {
+            let socket = PacketSocket::open(interface, libc::SOCK_DGRAM, libc::ETH_P_ARP as u16)?;
+            Ok(ArpSocket { socket })
+        }
+
+        fn send(&self, packet: &ArpPacket) -> io::Result<()> {
+            self.socket.send_to(&packet.serialize(), [0xff; 6])
+        }
+
+        fn wait_for_conflict(
+            &self,
+            maddr: &MacAddress,
+            ip: &Ipv4Addr,
+            deadline: Instant,
+        ) -> io::Result<bool> {
+            let mut buffer = [0u8; 64];
+            while let Some(size) = self.socket.recv(&mut buffer, Some(deadline))? {
+                if let Ok(packet) = ArpPacket::deserialize(&buffer[..size]) {
+                    if packet.is_conflict(maddr, ip) {
+                        return Ok(true);
+                    }
+                }
+            }
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod arp_packet {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn own_mac() -> MacAddress {
+        MacAddress::new([0x10, 0x7b, 0x44, 0x93, 0xe6, 0xd0])
+    }
+
+    fn other_mac() -> MacAddress {
+        MacAddress::new([0x52, 0x54, 0x00, 0x12, 0x34, 0x56])
+    }
+
+    #[test]
+    fn test_serialize_probe() {
+        let probe = ArpPacket::new_probe(&own_mac(), &Ipv4Addr::new(192, 168, 1, 99));
+
+        assert_eq!(
+            probe.serialize(),
+            vec![
+                0x00, 0x01, 0x08, 0x00, 0x06, 0x04, 0x00, 0x01, 0x10, 0x7b, 0x44, 0x93, 0xe6, 0xd0,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xc0, 0xa8, 0x01, 0x63
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deserialize_packet() {
+        let reply = ArpPacket::new(ArpOperation::Reply)
+            .with_sender(&other_mac(), &Ipv4Addr::new(192, 168, 1, 99))
+            .with_target_ip(&Ipv4Addr::new(192, 168, 1, 1));
+
+        assert_eq!(ArpPacket::deserialize(&reply.serialize()).unwrap(), reply);
+        assert_eq!(
+            ArpPacket::deserialize(&reply.serialize()[..20]),
+            Err(DhcpParseError::TruncatedHeader {
+                offset: 20,
+                expected: ARP_PACKET_SIZE
+            })
+        );
+    }
+
+    #[test]
+    fn test_is_conflict() {
+        let ip = Ipv4Addr::new(192, 168, 1, 99);
+        let reply = ArpPacket::new(ArpOperation::Reply).with_sender(&other_mac(), &ip);
+        let other_probe = ArpPacket::new_probe(&other_mac(), &ip);
+        let own_probe = ArpPacket::new_probe(&own_mac(), &ip);
+        let unrelated = ArpPacket::new(ArpOperation::Request)
+            .with_sender(&other_mac(), &Ipv4Addr::new(192, 168, 1, 1))
+            .with_target_ip(&Ipv4Addr::new(192, 168, 1, 2));
+
+        assert!(reply.is_conflict(&own_mac(), &ip));
+        assert!(other_probe.is_conflict(&own_mac(), &ip));
+        assert!(!own_probe.is_conflict(&own_mac(), &ip));
+        assert!(!unrelated.is_conflict(&own_mac(), &ip));
+    }
+}
@@ -0,0 +1,365 @@
+use crate::dhcp::{
+    client::{ClientAction, ClientEvent, DhcpClient, Lease},
+    packet::DhcpPacket,
+    traits::{Deserialize, Serialize},
+    transport::{CLIENT_PORT, SERVER_PORT},
+};
+use log::warn;
+use std::{
+    future::Future,
+    io,
+    net::{Ipv4Addr, SocketAddrV4},
+};
+use tokio::{
+    net::UdpSocket,
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    time::{self, Instant},
+};
+
+const BUFFER_SIZE: usize = 1500;
+
+// Async counterpart of DhcpTransport, packets addressed to Ipv4Addr::BROADCAST are broadcast
+pub trait AsyncDhcpTransport {
+    fn send(
+        &mut self,
+        destination: Ipv4Addr,
+        packet: &DhcpPacket,
+    ) -> impl Future<Output = io::Result<()>> + Send;
+
+    // Must be cancel safe, it is dropped whenever the client's timer fires first
+    fn receive(&mut self) -> impl Future<Output = io::Result<DhcpPacket>> + Send;
+}
+
+pub struct AsyncUdpTransport {
+    socket: UdpSocket,
+    server_port: u16,
+}
+
+impl AsyncUdpTransport {
+    pub async fn new() -> io::Result<Self> {
+        Self::bind(
+            SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, CLIENT_PORT),
+            SERVER_PORT,
+        )
+        .await
+    }
+
+    pub async fn bind(local: SocketAddrV4, server_port: u16) -> io::Result<Self> {
+        let socket = UdpSocket::bind(local).await?;
+        socket.set_broadcast(true)?;
+        Ok(Self {
+            socket,
+            server_port,
+        })
+    }
+}
+
+impl AsyncDhcpTransport for AsyncUdpTransport {
+    async fn send(&mut self, destination: Ipv4Addr, packet: &DhcpPacket) -> io::Result<()> {
+        self.socket
+            .send_to(&packet.serialize(), (destination, self.server_port))
+            .await?;
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> io::Result<DhcpPacket> {
+        let mut buffer = [0; BUFFER_SIZE];
+        loop {
+            let size = self.socket.recv(&mut buffer).await?;
+            match DhcpPacket::deserialize(&buffer[..size]) {
+                Ok(packet) => return Ok(packet),
+                Err(e) => warn!("Dropping unparseable packet: {}", e),
+            }
+        }
+    }
+}
+
+// One end of an in-process link, see MemoryTransport
+pub struct AsyncMemoryTransport {
+    sender: UnboundedSender<(Ipv4Addr, DhcpPacket)>,
+    receiver: UnboundedReceiver<(Ipv4Addr, DhcpPacket)>,
+}
+
+impl AsyncMemoryTransport {
+    pub fn pair() -> (Self, Self) {
+        let (left_sender, right_receiver) = unbounded_channel();
+        let (right_sender, left_receiver) = unbounded_channel();
+        let left = Self {
+            sender: left_sender,
+            receiver: left_receiver,
+        };
+        let right = Self {
+            sender: right_sender,
+            receiver: right_receiver,
+        };
+        (left, right)
+    }
+
+    // Like receive, but also returns where the peer addressed the packet
+    pub async fn receive_addressed(&mut self) -> io::Result<(Ipv4Addr, DhcpPacket)> {
+        self.receiver
+            .recv()
+            .await
+            .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "peer disconnected"))
+    }
+}
+
+impl AsyncDhcpTransport for AsyncMemoryTransport {
+    async fn send(&mut self, destination: Ipv4Addr, packet: &DhcpPacket) -> io::Result<()> {
+        self.sender
+            .send((destination, packet.clone()))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "peer disconnected"))
+    }
+
+    async fn receive(&mut self) -> io::Result<DhcpPacket> {
+        Ok(self.receive_addressed().await?.1)
+    }
+}
+
+// Drives a DhcpClient from a tokio task, timers follow the runtime clock
+pub struct AsyncDhcpClient<T: AsyncDhcpTransport> {
+    client: DhcpClient,
+    transport: T,
+    interface: Option<String>,
+}
+
+impl<T: AsyncDhcpTransport> AsyncDhcpClient<T> {
+    pub fn new(client: DhcpClient, transport: T) -> Self {
+        AsyncDhcpClient {
+            client,
+            transport,
+            interface: None,
+        }
+    }
+
+    // Conflict detection probes on this interface, without one addresses are assumed free
+    pub fn with_interface(mut self, interface: &str) -> Self {
+        self.interface = Some(interface.to_string());
+        self
+    }
+
+    pub fn get_client(&self) -> &DhcpClient {
+        &self.client
+    }
+
+    // Resolves once a lease is bound, restarting discovery after NAKs and declines
+    pub async fn acquire(&mut self) -> io::Result<Lease> {
+        self.run(ClientEvent::Start, |action| match action {
+            ClientAction::Bound(lease) => Some(Ok(lease.clone())),
+            _ => None,
+        })
+        .await
+    }
+
+    // Resolves once the held lease is extended at T1 or T2.
+    // Fails if it expires or a server refuses it instead, acquire starts over from there
+    pub async fn renew(&mut self) -> io::Result<Lease> {
+        let held_ip = match self.client.get_lease() {
+            Some(lease) => lease.get_client_ip(),
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "no lease is held")),
+        };
+        let event = self.next_event().await?;
+        self.run(event, |action| match action {
+            ClientAction::Renewed(lease) | ClientAction::Rebound(lease) => Some(Ok(lease.clone())),
+            ClientAction::Nak(_) => Some(Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("lease on {} was refused", held_ip),
+            ))),
+            _ => None,
+        })
+        .await
+    }
+
+    pub async fn release(&mut self) -> io::Result<Option<Lease>> {
+        let mut released = None;
+        for action in self.client.release() {
+            match action {
+                ClientAction::Unicast(addr, packet) => self.transport.send(addr, &packet).await?,
+                ClientAction::Released(lease) => released = Some(lease),
+                _ => {}
+            }
+        }
+        Ok(released)
+    }
+
+    // Feeds events to the client until `done` picks a result or an error out of its actions
+    async fn run<R, F>(&mut self, event: ClientEvent, done: F) -> io::Result<R>
+    where
+        F: Fn(&ClientAction) -> Option<io::Result<R>>,
+    {
+        let mut event = event;
+        loop {
+            let now = Instant::now().into_std();
+            let mut next_event = None;
+            for action in self.client.handle(event, now) {
+                if let Some(result) = done(&action) {
+                    return result;
+                }
+                match action {
+                    ClientAction::Broadcast(packet) => {
+                        self.transport.send(Ipv4Addr::BROADCAST, &packet).await?
+                    }
+                    ClientAction::Unicast(addr, packet) => {
+                        self.transport.send(addr, &packet).await?
+                    }
+                    ClientAction::Probe(ip) => next_event = Some(self.probe(ip).await),
+                    ClientAction::Expired(lease) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            format!("lease on {} expired", lease.get_client_ip()),
+                        ))
+                    }
+                    ClientAction::TimedOut(state) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            format!("no response received while {:?}", state),
+                        ))
+                    }
+                    _ => {}
+                }
+            }
+            event = match next_event {
+                Some(event) => event,
+                None => self.next_event().await?,
+            };
+        }
+    }
+
+    async fn next_event(&mut self) -> io::Result<ClientEvent> {
+        let deadline = match self.client.get_timeout() {
+            Some(deadline) => Instant::from_std(deadline),
+            None => return Ok(ClientEvent::PacketReceived(self.transport.receive().await?)),
+        };
+        match time::timeout_at(deadline, self.transport.receive()).await {
+            Ok(packet) => Ok(ClientEvent::PacketReceived(packet?)),
+            Err(_) => Ok(ClientEvent::TimerFired),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn probe(&self, ip: Ipv4Addr) -> ClientEvent {
+        let interface = match &self.interface {
+            Some(interface) => interface.clone(),
+            None => return ClientEvent::AddressAvailable,
+        };
+        let mac_address = self.client.get_mac_address();
+        let probe = move || crate::dhcp::arp::probe(&interface, &mac_address, &ip);
+        let result = tokio::task::spawn_blocking(probe)
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e)));
+        match result {
+            Ok(true) => ClientEvent::AddressInUse,
+            Ok(false) => ClientEvent::AddressAvailable,
+            Err(e) => {
+                warn!("Unable to probe {}: {}", ip, e);
+                ClientEvent::AddressAvailable
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    async fn probe(&self, _ip: Ipv4Addr) -> ClientEvent {
+        ClientEvent::AddressAvailable
+    }
+}
+
+#[cfg(test)]
+mod async_dhcp_client {
+    use super::*;
+    use crate::dhcp::{
+        client::ClientState,
+        packet::DhcpMessageType,
+        testing::{Fault, MockServer},
+    };
+    use mac_address::MacAddress;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    fn test_mac() -> MacAddress {
+        MacAddress::new([0x10, 0x7b, 0x44, 0x93, 0xe6, 0xd0])
+    }
+
+    // Answers everything the client sends until it hangs up
+    fn spawn_server(mut server: MockServer) -> AsyncMemoryTransport {
+        let (transport, mut server_end) = AsyncMemoryTransport::pair();
+        tokio::spawn(async move {
+            while let Ok((_, packet)) = server_end.receive_addressed().await {
+                for (destination, reply) in server.respond(&packet) {
+                    if server_end.send(destination, &reply).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        transport
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_acquire_renew_release() {
+        let server = MockServer::new(Ipv4Addr::new(192, 168, 1, 1))
+            .with_lease_time(Duration::from_secs(600));
+        let mut client = AsyncDhcpClient::new(DhcpClient::new(test_mac()), spawn_server(server));
+
+        let lease = client.acquire().await.unwrap();
+        assert_eq!(lease.get_client_ip(), Ipv4Addr::new(192, 168, 1, 2));
+        assert_eq!(client.get_client().get_state(), ClientState::Bound);
+
+        let started = Instant::now();
+        let renewed = client.renew().await.unwrap();
+        // The paused clock jumps straight to T1
+        assert_eq!(started.elapsed(), Duration::from_secs(300));
+        assert_eq!(renewed.get_client_ip(), lease.get_client_ip());
+
+        let released = client.release().await.unwrap();
+        assert_eq!(
+            released.map(|x| x.get_client_ip()),
+            Some(lease.get_client_ip())
+        );
+        assert_eq!(client.get_client().get_state(), ClientState::Init);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_acquire_times_out() {
+        let server = MockServer::new(Ipv4Addr::new(192, 168, 1, 1))
+            .with_fault(DhcpMessageType::Discover, Fault::Silence);
+        let client = DhcpClient::new(test_mac()).with_retries(0, 0);
+        let mut client = AsyncDhcpClient::new(client, spawn_server(server));
+
+        let error = client.acquire().await.unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_renew_refused() {
+        // Rapid commit keeps the only DHCPREQUEST for the renewal
+        let server = MockServer::new(Ipv4Addr::new(192, 168, 1, 1))
+            .with_lease_time(Duration::from_secs(600))
+            .with_rapid_commit(true)
+            .with_fault(DhcpMessageType::Request, Fault::Nak);
+        let client = DhcpClient::new(test_mac()).with_rapid_commit(true);
+        let mut client = AsyncDhcpClient::new(client, spawn_server(server));
+        client.acquire().await.unwrap();
+
+        let error = client.renew().await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionRefused);
+        assert_eq!(client.get_client().get_state(), ClientState::Init);
+        assert_eq!(client.get_client().get_lease(), None);
+
+        let lease = client.acquire().await.unwrap();
+        assert_eq!(client.get_client().get_state(), ClientState::Bound);
+        assert_eq!(lease.get_client_ip(), Ipv4Addr::new(192, 168, 1, 2));
+    }
+
+    #[tokio::test]
+    async fn test_renew_without_lease() {
+        let (transport, _server_end) = AsyncMemoryTransport::pair();
+        let mut client = AsyncDhcpClient::new(DhcpClient::new(test_mac()), transport);
+
+        assert_eq!(
+            client.renew().await.unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert_eq!(client.release().await.unwrap(), None);
+    }
+}
@@ -0,0 +1,1732 @@
+use crate::dhcp::{
+    duid::ClientIdentifier,
+    error::DhcpNameError,
+    fqdn::ClientFqdn,
+    options::{check_domain_name, DhcpOptionValue},
+    packet::{DhcpMessageType, DhcpOptionType, DhcpPacket, TransactionToken},
+    requirements::ClientMessage,
+    selector::{FirstOffer, OfferSelector},
+};
+use log::{info, warn};
+use mac_address::MacAddress;
+use rand::{random, Rng};
+use std::{
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+// How long to wait for a server to answer a DHCPINFORM or the driver to finish a probe
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+// DHCPDISCOVER and DHCPREQUEST are retransmitted after 4s, doubling up to 64s, each
+// randomized by up to a second either way. See RFC 2131 section 4.1
+const FIRST_RETRANSMISSION: Duration = Duration::from_secs(4);
+const MAX_RETRANSMISSION: Duration = Duration::from_secs(64);
+const RETRANSMISSION_JITTER_MS: i64 = 1000;
+// Retransmissions before giving up, 4 + 8 + 16 + 32 + 64 seconds for discovery
+const DEFAULT_DISCOVER_RETRIES: u32 = 4;
+const DEFAULT_REQUEST_RETRIES: u32 = 3;
+// Shortest wait between renewal retransmissions, see RFC 2131 section 4.4.5
+const MIN_RENEWAL_RETRY: Duration = Duration::from_secs(60);
+// How long to hold off discovery after declining an address, see RFC 2131 section 3.1
+const DECLINE_BACKOFF: Duration = Duration::from_secs(10);
+// Settings a statically addressed host asks for in a DHCPINFORM
+const INFORM_PARAMETERS: [DhcpOptionType; 6] = [
+    DhcpOptionType::SubnetMask,
+    DhcpOptionType::RouterIp,
+    DhcpOptionType::DnsServerIp,
+    DhcpOptionType::DomainName,
+    DhcpOptionType::NtpServers,
+    DhcpOptionType::DomainSearch,
+];
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ClientState {
+    Init,
+    Selecting,
+    Requesting,
+    Bound,
+    Renewing,
+    Rebinding,
+    InitReboot,
+    Rebooting,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum ClientEvent {
+    Start,
+    PacketReceived(DhcpPacket),
+    TimerFired,
+    AddressAvailable,
+    AddressInUse,
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub enum ClientAction {
+    Broadcast(DhcpPacket),
+    Unicast(Ipv4Addr, DhcpPacket),
+    Probe(Ipv4Addr),
+    Bound(Lease),
+    Renewed(Lease),
+    Rebound(Lease),
+    Nak(Option<Ipv4Addr>),
+    Expired(Lease),
+    Released(Lease),
+    Declined(Lease),
+    Informed(DhcpPacket),
+    TimedOut(ClientState),
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Lease {
+    client_ip: Ipv4Addr,
+    server_ip: Ipv4Addr,
+    lease_time: Duration,
+    renewal_time: Duration,
+    rebinding_time: Duration,
+    acquired: Instant,
+    ack: DhcpPacket,
+}
+
+pub struct DhcpClient {
+    state: ClientState,
+    mac_address: MacAddress,
+    client_id: Option<ClientIdentifier>,
+    host_name: Option<String>,
+    fqdn: Option<ClientFqdn>,
+    vendor_class: Option<Vec<u8>>,
+    transaction: TransactionToken,
+    previous_ip: Option<Ipv4Addr>,
+    static_ip: Option<Ipv4Addr>,
+    requested_ip: Option<Ipv4Addr>,
+    lease_time: Option<Duration>,
+    // Offers collected while SELECTING and the one picked from them
+    offers: Vec<DhcpPacket>,
+    offer_window: Duration,
+    selector: Box<dyn OfferSelector + Send>,
+    offer: Option<DhcpPacket>,
+    lease: Option<Lease>,
+    conflict_detection: bool,
+    rapid_commit: bool,
+    probing: Option<Lease>,
+    request_sent: Option<Instant>,
+    timeout: Option<Instant>,
+    // When the current acquisition or renewal began, reported in secs
+    started: Option<Instant>,
+    retries: u32,
+    discover_retries: u32,
+    request_retries: u32,
+}
+
+// A DHCPRELEASE is unicast straight to the leasing server, see RFC 2131 section 4.4.6
+pub fn release_packet(
+    mac_address: &MacAddress,
+    client_ip: &Ipv4Addr,
+    server_ip: &Ipv4Addr,
+) -> DhcpPacket {
+    let release = DhcpPacket::new()
+        .with_transaction(&random())
+        .with_mac_address(mac_address)
+        .with_current_ip(client_ip)
+        .with_broadcast_flag(false)
+        .with_option(DhcpOptionValue::MessageType(DhcpMessageType::Release))
+        .with_option(DhcpOptionValue::DhcpServerIp(*server_ip));
+    checked(ClientMessage::Release, release)
+}
+
+// Every message is built to the option rules of RFC 2131 table 5, debug builds make sure of it
+fn checked(message: ClientMessage, packet: DhcpPacket) -> DhcpPacket {
+    debug_assert_eq!(message.check(&packet), Ok(()));
+    packet
+}
+
+impl Lease {
+    fn from_ack(ack: DhcpPacket, server_ip: Ipv4Addr, acquired: Instant) -> Option<Self> {
+        let lease_time = ack.get_lease_time()?;
+        // RFC 2131 section 4.4.5 defaults T1 to 0.5 and T2 to 0.875 of the lease
+        let renewal_time = ack.get_renewal_time().unwrap_or(lease_time / 2);
+        let rebinding_time = ack
+            .get_rebinding_time()
+            .unwrap_or_else(|| lease_time.mul_f64(0.875));
+        Some(Lease {
+            client_ip: ack.get_client_ip(),
+            server_ip: ack.get_server_ip().unwrap_or(server_ip),
+            lease_time,
+            renewal_time,
+            rebinding_time,
+            acquired,
+            ack,
+        })
+    }
+
+    // A lease carried over from an earlier run, see StoredLease::to_lease
+    pub(crate) fn from_parts(
+        ack: DhcpPacket,
+        client_ip: Ipv4Addr,
+        server_ip: Ipv4Addr,
+        lease_time: Duration,
+        renewal_time: Duration,
+        rebinding_time: Duration,
+        acquired: Instant,
+    ) -> Self {
+        Lease {
+            client_ip,
+            server_ip,
+            lease_time,
+            renewal_time,
+            rebinding_time,
+            acquired,
+            ack,
+        }
+    }
+
+    pub fn get_client_ip(&self) -> Ipv4Addr {
+        self.client_ip
+    }
+
+    pub fn get_server_ip(&self) -> Ipv4Addr {
+        self.server_ip
+    }
+
+    pub fn get_lease_time(&self) -> Duration {
+        self.lease_time
+    }
+
+    pub fn get_renewal_time(&self) -> Duration {
+        self.renewal_time
+    }
+
+    pub fn get_rebinding_time(&self) -> Duration {
+        self.rebinding_time
+    }
+
+    pub fn get_acquired(&self) -> Instant {
+        self.acquired
+    }
+
+    pub fn get_renewal_deadline(&self) -> Instant {
+        self.acquired + self.renewal_time
+    }
+
+    pub fn get_rebinding_deadline(&self) -> Instant {
+        self.acquired + self.rebinding_time
+    }
+
+    pub fn get_expiry(&self) -> Instant {
+        self.acquired + self.lease_time
+    }
+
+    pub fn get_packet(&self) -> &DhcpPacket {
+        &self.ack
+    }
+}
+
+impl DhcpClient {
+    pub fn new(mac_address: MacAddress) -> Self {
+        DhcpClient {
+            state: ClientState::Init,
+            mac_address,
+            client_id: None,
+            host_name: None,
+            fqdn: None,
+            vendor_class: None,
+            transaction: random(),
+            previous_ip: None,
+            static_ip: None,
+            requested_ip: None,
+            lease_time: None,
+            offers: vec![],
+            offer_window: Duration::ZERO,
+            selector: Box::new(FirstOffer),
+            offer: None,
+            lease: None,
+            conflict_detection: false,
+            rapid_commit: false,
+            probing: None,
+            request_sent: None,
+            timeout: None,
+            started: None,
+            retries: 0,
+            discover_retries: DEFAULT_DISCOVER_RETRIES,
+            request_retries: DEFAULT_REQUEST_RETRIES,
+        }
+    }
+
+    // Identify by IAID and DUID in every message, rather than by the mac address alone
+    pub fn with_client_id(mut self, client_id: &ClientIdentifier) -> Self {
+        self.client_id = Some(client_id.clone());
+        self
+    }
+
+    // Tell servers our name through option 12
+    pub fn with_host_name(mut self, host_name: &str) -> Result<Self, DhcpNameError> {
+        check_domain_name(host_name)?;
+        self.host_name = Some(host_name.to_string());
+        Ok(self)
+    }
+
+    // Ask servers to register the name in DNS through option 81. RFC 4702 section 3 rules
+    // out sending option 12 alongside it, so any host name is left out
+    pub fn with_fqdn(mut self, fqdn: &ClientFqdn) -> Self {
+        self.fqdn = Some(fqdn.clone());
+        self
+    }
+
+    // Sent as option 60, servers pick what to put in the Vendor Specific Information
+    // option by it
+    pub fn with_vendor_class(mut self, vendor_class: &[u8]) -> Self {
+        self.vendor_class = Some(vendor_class.to_vec());
+        self
+    }
+
+    // Resume a lease that is still held, e.g. one read back from the lease store. Starting
+    // renews it with its server right away, or rebinds with any server once past T2
+    pub fn with_held_lease(mut self, lease: &Lease) -> Self {
+        self.previous_ip = None;
+        self.state = ClientState::Bound;
+        self.timeout = Some(lease.get_renewal_deadline());
+        self.lease = Some(lease.clone());
+        self
+    }
+
+    // Start from INIT-REBOOT and try to reclaim an address held before
+    pub fn with_previous_ip(mut self, ip: &Ipv4Addr) -> Self {
+        self.previous_ip = Some(*ip);
+        self.state = ClientState::InitReboot;
+        self
+    }
+
+    // Only fetch configuration with DHCPINFORM for an address set up by other means.
+    // This happens outside the RFC 2131 state diagram, so the client stays in INIT
+    pub fn with_static_ip(mut self, ip: &Ipv4Addr) -> Self {
+        self.static_ip = Some(*ip);
+        self.state = ClientState::Init;
+        self
+    }
+
+    // Suggest an address in DHCPDISCOVER, the server may still offer another
+    pub fn with_requested_ip(mut self, ip: &Ipv4Addr) -> Self {
+        self.requested_ip = Some(*ip);
+        self
+    }
+
+    // Ask for a lease of this length in every DHCPDISCOVER and DHCPREQUEST
+    pub fn with_lease_time(mut self, lease_time: Duration) -> Self {
+        self.lease_time = Some(lease_time);
+        self
+    }
+
+    // Keep collecting offers this long after the first one arrives, then let the selector
+    // choose. Without a window the first offer is taken right away
+    pub fn with_offer_window(mut self, window: Duration) -> Self {
+        self.offer_window = window;
+        self
+    }
+
+    pub fn with_offer_selector<S: OfferSelector + Send + 'static>(mut self, selector: S) -> Self {
+        self.selector = Box::new(selector);
+        self
+    }
+
+    // Ask the driver to probe ACKed addresses before using them
+    pub fn with_conflict_detection(mut self, enabled: bool) -> Self {
+        self.conflict_detection = enabled;
+        self
+    }
+
+    // Offer the two message exchange of RFC 4039 in DHCPDISCOVER. Servers that do not take it
+    // up still answer with offers, which go through the usual REQUEST
+    pub fn with_rapid_commit(mut self, enabled: bool) -> Self {
+        self.rapid_commit = enabled;
+        self
+    }
+
+    // How often an unanswered DHCPDISCOVER and DHCPREQUEST are sent again before giving up
+    pub fn with_retries(mut self, discover_retries: u32, request_retries: u32) -> Self {
+        self.discover_retries = discover_retries;
+        self.request_retries = request_retries;
+        self
+    }
+
+    pub fn get_mac_address(&self) -> MacAddress {
+        self.mac_address
+    }
+
+    pub fn get_client_id(&self) -> Option<&ClientIdentifier> {
+        self.client_id.as_ref()
+    }
+
+    pub fn get_vendor_class(&self) -> Option<&[u8]> {
+        self.vendor_class.as_deref()
+    }
+
+    pub fn get_state(&self) -> ClientState {
+        self.state
+    }
+
+    pub fn get_transaction(&self) -> TransactionToken {
+        self.transaction
+    }
+
+    pub fn get_lease(&self) -> Option<&Lease> {
+        self.lease.as_ref()
+    }
+
+    pub fn get_timeout(&self) -> Option<Instant> {
+        self.timeout
+    }
+
+    // Give the held lease back to its server and return to INIT
+    pub fn release(&mut self) -> Vec<ClientAction> {
+        let lease = match self.lease.take() {
+            Some(lease) => lease,
+            None => return vec![],
+        };
+        let release = self.with_client_id_option(release_packet(
+            &self.mac_address,
+            &lease.client_ip,
+            &lease.server_ip,
+        ));
+
+        info!("Sending DHCPRELEASE packet for {}", lease.client_ip);
+        self.reset();
+        vec![
+            ClientAction::Unicast(lease.server_ip, release),
+            ClientAction::Released(lease),
+        ]
+    }
+
+    pub fn handle(&mut self, event: ClientEvent, now: Instant) -> Vec<ClientAction> {
+        match event {
+            ClientEvent::Start => self.handle_start(now),
+            ClientEvent::PacketReceived(packet) => {
+                if !packet.is_transaction(&self.transaction) {
+                    return vec![];
+                }
+                self.handle_packet(packet, now)
+            }
+            ClientEvent::TimerFired => match self.timeout {
+                Some(deadline) if deadline <= now => {
+                    self.timeout = None;
+                    self.handle_timer(now)
+                }
+                _ => vec![],
+            },
+            ClientEvent::AddressAvailable => match self.probing.take() {
+                Some(lease) => self.bind(lease),
+                None => vec![],
+            },
+            ClientEvent::AddressInUse => match self.probing.take() {
+                Some(lease) => self.send_decline(lease, now),
+                None => vec![],
+            },
+        }
+    }
+
+    fn handle_start(&mut self, now: Instant) -> Vec<ClientAction> {
+        match self.state {
+            ClientState::Init if self.static_ip.is_some() => self.send_inform(now),
+            ClientState::Init => self.send_discover(now),
+            ClientState::InitReboot => self.send_reboot_request(now),
+            ClientState::Bound => match &self.lease {
+                Some(lease) if now < lease.get_rebinding_deadline() => self.send_renew_request(now),
+                Some(_) => self.send_rebind_request(now),
+                None => vec![],
+            },
+            _ => vec![],
+        }
+    }
+
+    fn handle_packet(&mut self, packet: DhcpPacket, now: Instant) -> Vec<ClientAction> {
+        if self.probing.is_some() {
+            return vec![];
+        }
+        match (self.state, packet.get_type()) {
+            (ClientState::Init, Some(DhcpMessageType::Ack)) if self.static_ip.is_some() => {
+                self.handle_inform_ack(packet)
+            }
+            (ClientState::Selecting, Some(DhcpMessageType::Offer)) => {
+                self.handle_offer(packet, now)
+            }
+            // Without the option echoed back the ACK is not a rapid commit, see RFC 4039 section 4
+            (ClientState::Selecting, Some(DhcpMessageType::Ack))
+                if self.rapid_commit && packet.is_rapid_commit() =>
+            {
+                info!("DHCP Server committed the lease without a DHCPREQUEST");
+                self.offers.clear();
+                self.handle_ack(packet, now)
+            }
+            (ClientState::Requesting, Some(DhcpMessageType::Ack)) => {
+                // Only accept the answer from the server we chose
+                let chosen_server = self.offer.as_ref().and_then(|x| x.get_server_ip());
+                if packet.get_server_ip() != chosen_server {
+                    return vec![];
+                }
+                self.handle_ack(packet, now)
+            }
+            (ClientState::Rebooting, Some(DhcpMessageType::Ack))
+            | (ClientState::Renewing, Some(DhcpMessageType::Ack))
+            | (ClientState::Rebinding, Some(DhcpMessageType::Ack)) => self.handle_ack(packet, now),
+            (ClientState::Requesting, Some(DhcpMessageType::Nak))
+            | (ClientState::Rebooting, Some(DhcpMessageType::Nak))
+            | (ClientState::Renewing, Some(DhcpMessageType::Nak))
+            | (ClientState::Rebinding, Some(DhcpMessageType::Nak)) => self.handle_nak(packet, now),
+            _ => vec![],
+        }
+    }
+
+    fn handle_timer(&mut self, now: Instant) -> Vec<ClientAction> {
+        // A probe the driver never finished ends the exchange, whichever state it began in
+        if let Some(lease) = self.probing.take() {
+            warn!("Probing {} did not finish in time", lease.client_ip);
+            let state = self.state;
+            self.reset();
+            return vec![ClientAction::TimedOut(state)];
+        }
+        match self.state {
+            ClientState::Init if self.static_ip.is_some() => {
+                warn!("No response received to DHCPINFORM");
+                vec![ClientAction::TimedOut(ClientState::Init)]
+            }
+            ClientState::Init => self.send_discover(now),
+            ClientState::Selecting if !self.offers.is_empty() => self.choose_offer(now),
+            ClientState::Selecting if self.retries < self.discover_retries => {
+                self.retries += 1;
+                self.discover(now)
+            }
+            ClientState::Requesting if self.can_retry_request() => {
+                self.retries += 1;
+                self.selecting_request(now)
+            }
+            ClientState::Rebooting if self.can_retry_request() => {
+                self.retries += 1;
+                self.reboot_request(now)
+            }
+            ClientState::Rebooting => self.abandon_reboot(now),
+            ClientState::Selecting | ClientState::Requesting => {
+                warn!("No response received while {:?}", self.state);
+                let state = self.state;
+                self.reset();
+                vec![ClientAction::TimedOut(state)]
+            }
+            ClientState::Bound => self.send_renew_request(now),
+            ClientState::Renewing => {
+                let lease = self.lease.as_ref().expect("Renewing without a lease");
+                if now < lease.get_rebinding_deadline() {
+                    self.send_renew_request(now)
+                } else {
+                    self.send_rebind_request(now)
+                }
+            }
+            ClientState::Rebinding => {
+                let lease = self.lease.as_ref().expect("Rebinding without a lease");
+                if now < lease.get_expiry() {
+                    return self.send_rebind_request(now);
+                }
+                let lease = self.lease.take().unwrap();
+                warn!("Lease on {} expired", lease.client_ip);
+                self.reset();
+                // Expiry drops us back to INIT, which starts over right away
+                self.timeout = Some(now);
+                vec![ClientAction::Expired(lease)]
+            }
+            ClientState::InitReboot => vec![],
+        }
+    }
+
+    fn handle_offer(&mut self, offer: DhcpPacket, now: Instant) -> Vec<ClientAction> {
+        let server_ip = match offer.get_server_ip() {
+            Some(ip) => ip,
+            None => return vec![],
+        };
+        info!(
+            "DHCP Server {} offered ip {}",
+            server_ip,
+            offer.get_client_ip()
+        );
+
+        self.offers.push(offer);
+        if self.offer_window.is_zero() {
+            return self.choose_offer(now);
+        }
+        if self.offers.len() == 1 {
+            self.timeout = Some(now + self.offer_window);
+        }
+        vec![]
+    }
+
+    // The REQUEST names the chosen server, which tells the others to withdraw their offers
+    fn choose_offer(&mut self, now: Instant) -> Vec<ClientAction> {
+        let mut offers = std::mem::take(&mut self.offers);
+        let offer = match self.selector.select(&offers) {
+            Some(i) if i < offers.len() => offers.swap_remove(i),
+            _ => {
+                info!(
+                    "No acceptable offer among {}, discovering again",
+                    offers.len()
+                );
+                self.timeout = Some(now + self.retransmission_delay());
+                return vec![];
+            }
+        };
+        info!(
+            "Choosing ip {} offered by {}",
+            offer.get_client_ip(),
+            offer.get_server_ip().unwrap()
+        );
+
+        self.offer = Some(offer);
+        self.state = ClientState::Requesting;
+        self.retries = 0;
+        self.selecting_request(now)
+    }
+
+    // Sent for the chosen offer, again on each retransmission
+    fn selecting_request(&mut self, now: Instant) -> Vec<ClientAction> {
+        let offer = self.offer.as_ref().expect("Requesting without an offer");
+        let request = self
+            .base_packet(DhcpMessageType::Request)
+            .with_seconds(self.elapsed(now))
+            .with_option(DhcpOptionValue::RequestedIp(offer.get_client_ip()))
+            .with_option(DhcpOptionValue::DhcpServerIp(
+                offer.get_server_ip().unwrap(),
+            ));
+        let request = checked(
+            ClientMessage::SelectingRequest,
+            self.with_requested_lease_time(request),
+        );
+
+        info!("Sending DHCPREQUEST packet");
+        self.request_sent = Some(now);
+        self.timeout = Some(now + self.retransmission_delay());
+        vec![ClientAction::Broadcast(request)]
+    }
+
+    fn handle_ack(&mut self, ack: DhcpPacket, now: Instant) -> Vec<ClientAction> {
+        let fallback_server = match (&self.offer, &self.lease) {
+            (Some(offer), _) => offer.get_server_ip(),
+            (None, Some(lease)) => Some(lease.server_ip),
+            (None, None) => ack.get_server_ip(),
+        };
+        let acquired = self.request_sent.unwrap_or(now);
+        let lease = match fallback_server.and_then(|x| Lease::from_ack(ack, x, acquired)) {
+            Some(lease) => lease,
+            None => return vec![],
+        };
+        info!(
+            "DHCP Server {} accepted the assigned ip {} with a lease of {}s",
+            lease.server_ip,
+            lease.client_ip,
+            lease.lease_time.as_secs()
+        );
+        if let Some(fqdn) = lease.ack.get_client_fqdn() {
+            info!(
+                "DHCP Server will update A record for {}: {}, PTR record: {}",
+                fqdn.get_name(),
+                fqdn.is_server_updating_a(),
+                fqdn.is_server_updating_ptr()
+            );
+        }
+
+        // A freshly assigned address is checked before use, renewals keep the one in use
+        let is_new_address = matches!(
+            self.state,
+            ClientState::Selecting | ClientState::Requesting | ClientState::Rebooting
+        );
+        if self.conflict_detection && is_new_address {
+            info!("Probing {} for conflicts", lease.client_ip);
+            let ip = lease.client_ip;
+            self.probing = Some(lease);
+            self.timeout = Some(now + RESPONSE_TIMEOUT);
+            return vec![ClientAction::Probe(ip)];
+        }
+        self.bind(lease)
+    }
+
+    fn bind(&mut self, lease: Lease) -> Vec<ClientAction> {
+        let previous_state = self.state;
+        self.state = ClientState::Bound;
+        self.offer = None;
+        self.request_sent = None;
+        self.started = None;
+        self.timeout = Some(lease.get_renewal_deadline());
+        self.lease = Some(lease.clone());
+        match previous_state {
+            ClientState::Renewing => vec![ClientAction::Renewed(lease)],
+            ClientState::Rebinding => vec![ClientAction::Rebound(lease)],
+            _ => vec![ClientAction::Bound(lease)],
+        }
+    }
+
+    // The answer to an INFORM carries neither a yiaddr nor a lease time
+    fn handle_inform_ack(&mut self, ack: DhcpPacket) -> Vec<ClientAction> {
+        match ack.get_server_ip() {
+            Some(ip) => info!("DHCP Server {} answered the DHCPINFORM", ip),
+            None => info!("DHCP Server answered the DHCPINFORM"),
+        }
+        self.timeout = None;
+        vec![ClientAction::Informed(ack)]
+    }
+
+    fn handle_nak(&mut self, nak: DhcpPacket, now: Instant) -> Vec<ClientAction> {
+        let server_ip = nak.get_server_ip();
+        match server_ip {
+            Some(ip) => warn!("DHCP Server {} refused the request", ip),
+            None => warn!("DHCP Server refused the request"),
+        }
+        self.lease = None;
+        self.reset();
+        // A NAK sends us back to INIT, which starts over right away
+        self.timeout = Some(now);
+        vec![ClientAction::Nak(server_ip)]
+    }
+
+    fn send_discover(&mut self, now: Instant) -> Vec<ClientAction> {
+        self.transaction = random();
+        self.state = ClientState::Selecting;
+        self.started = Some(now);
+        self.retries = 0;
+        self.discover(now)
+    }
+
+    // Retransmissions keep the xid, so a late offer to an earlier one is still taken
+    fn discover(&mut self, now: Instant) -> Vec<ClientAction> {
+        let mut discover = self
+            .base_packet(DhcpMessageType::Discover)
+            .with_seconds(self.elapsed(now));
+        if let Some(ip) = self.requested_ip {
+            discover = discover.with_option(DhcpOptionValue::RequestedIp(ip));
+        }
+        discover = self.with_requested_lease_time(discover);
+        if self.rapid_commit {
+            discover = discover.with_option(DhcpOptionValue::RapidCommit);
+            // A committed lease runs from the DISCOVER that asked for it
+            self.request_sent = Some(now);
+        }
+
+        let discover = checked(ClientMessage::Discover, discover);
+
+        info!("Sending DHCPDISCOVERY packet");
+        self.timeout = Some(now + self.retransmission_delay());
+        vec![ClientAction::Broadcast(discover)]
+    }
+
+    // Another host already uses the address, tell the server and start over after a pause
+    fn send_decline(&mut self, lease: Lease, now: Instant) -> Vec<ClientAction> {
+        let decline = self
+            .base_packet(DhcpMessageType::Decline)
+            .with_option(DhcpOptionValue::RequestedIp(lease.client_ip))
+            .with_option(DhcpOptionValue::DhcpServerIp(lease.server_ip));
+        let decline = checked(ClientMessage::Decline, decline);
+
+        warn!("Address {} is already in use, declining", lease.client_ip);
+        self.reset();
+        self.timeout = Some(now + DECLINE_BACKOFF);
+        vec![
+            ClientAction::Broadcast(decline),
+            ClientAction::Declined(lease),
+        ]
+    }
+
+    fn send_inform(&mut self, now: Instant) -> Vec<ClientAction> {
+        let static_ip = self.static_ip.expect("Informing without a static ip");
+        self.transaction = random();
+        let inform = self
+            .base_packet(DhcpMessageType::Inform)
+            .with_current_ip(&static_ip)
+            .with_broadcast_flag(false)
+            .with_option(DhcpOptionValue::ParameterRequest(
+                INFORM_PARAMETERS.iter().map(|x| *x as u8).collect(),
+            ));
+        let inform = checked(ClientMessage::Inform, inform);
+
+        info!("Sending DHCPINFORM packet for {}", static_ip);
+        self.timeout = Some(now + RESPONSE_TIMEOUT);
+        vec![ClientAction::Broadcast(inform)]
+    }
+
+    fn send_reboot_request(&mut self, now: Instant) -> Vec<ClientAction> {
+        if self.previous_ip.is_none() {
+            return self.send_discover(now);
+        }
+        self.transaction = random();
+        self.state = ClientState::Rebooting;
+        self.started = Some(now);
+        self.retries = 0;
+        self.reboot_request(now)
+    }
+
+    // Nobody vouched for the previous address, so it is only suggested in a fresh DISCOVER.
+    // RFC 2131 section 3.2 lets the client keep using it instead, but nothing here knows
+    // whether the lease is still good
+    fn abandon_reboot(&mut self, now: Instant) -> Vec<ClientAction> {
+        let previous_ip = self
+            .previous_ip
+            .take()
+            .expect("Rebooting without a previous ip");
+        warn!(
+            "No response to DHCPREQUEST for {}, discovering instead",
+            previous_ip
+        );
+        self.requested_ip.get_or_insert(previous_ip);
+        self.send_discover(now)
+    }
+
+    fn reboot_request(&mut self, now: Instant) -> Vec<ClientAction> {
+        let previous_ip = self.previous_ip.expect("Rebooting without a previous ip");
+        let request = self
+            .base_packet(DhcpMessageType::Request)
+            .with_seconds(self.elapsed(now))
+            .with_option(DhcpOptionValue::RequestedIp(previous_ip));
+        let request = checked(
+            ClientMessage::RebootRequest,
+            self.with_requested_lease_time(request),
+        );
+
+        info!("Sending DHCPREQUEST packet for previous ip {}", previous_ip);
+        self.request_sent = Some(now);
+        self.timeout = Some(now + self.retransmission_delay());
+        vec![ClientAction::Broadcast(request)]
+    }
+
+    // Unicast to the leasing server between T1 and T2
+    fn send_renew_request(&mut self, now: Instant) -> Vec<ClientAction> {
+        let lease = self.lease.as_ref().expect("Renewing without a lease");
+        let server_ip = lease.server_ip;
+        let deadline = lease.get_rebinding_deadline();
+        let request = self.renewal_packet(now);
+
+        info!("Sending DHCPREQUEST packet to renew with {}", server_ip);
+        self.state = ClientState::Renewing;
+        self.timeout = Some(Self::retry_deadline(now, deadline));
+        vec![ClientAction::Unicast(server_ip, request)]
+    }
+
+    // Broadcast to any server between T2 and expiry
+    fn send_rebind_request(&mut self, now: Instant) -> Vec<ClientAction> {
+        let lease = self.lease.as_ref().expect("Rebinding without a lease");
+        let deadline = lease.get_expiry();
+        let request = self.renewal_packet(now);
+
+        info!("Sending DHCPREQUEST packet to rebind");
+        self.state = ClientState::Rebinding;
+        self.timeout = Some(Self::retry_deadline(now, deadline));
+        vec![ClientAction::Broadcast(request)]
+    }
+
+    fn renewal_packet(&mut self, now: Instant) -> DhcpPacket {
+        let client_ip = self.lease.as_ref().unwrap().client_ip;
+        // Each retransmission is a fresh transaction, secs counts from T1
+        self.transaction = random();
+        self.request_sent = Some(now);
+        let started = *self.started.get_or_insert(now);
+        let request = self
+            .base_packet(DhcpMessageType::Request)
+            .with_seconds(Self::seconds_between(started, now))
+            .with_current_ip(&client_ip)
+            .with_broadcast_flag(false);
+        checked(
+            ClientMessage::RenewalRequest,
+            self.with_requested_lease_time(request),
+        )
+    }
+
+    // Wait half the time left until the deadline, but no less than a minute
+    fn retry_deadline(now: Instant, deadline: Instant) -> Instant {
+        let wait = (deadline.saturating_duration_since(now) / 2).max(MIN_RENEWAL_RETRY);
+        (now + wait).min(deadline)
+    }
+
+    fn can_retry_request(&self) -> bool {
+        self.retries < self.request_retries
+    }
+
+    // The wait after the current attempt, randomized so clients that started together spread out
+    fn retransmission_delay(&self) -> Duration {
+        let doublings = self.retries.min(4);
+        let base = (FIRST_RETRANSMISSION * 2u32.pow(doublings)).min(MAX_RETRANSMISSION);
+        let jitter =
+            rand::thread_rng().gen_range(-RETRANSMISSION_JITTER_MS..=RETRANSMISSION_JITTER_MS);
+        Duration::from_millis((base.as_millis() as i64 + jitter) as u64)
+    }
+
+    fn elapsed(&self, now: Instant) -> u16 {
+        Self::seconds_between(self.started.unwrap_or(now), now)
+    }
+
+    // Saturates, an exchange that somehow lasts over 18 hours reports 65535
+    fn seconds_between(start: Instant, now: Instant) -> u16 {
+        now.saturating_duration_since(start)
+            .as_secs()
+            .min(u16::MAX as u64) as u16
+    }
+
+    fn base_packet(&self, mtype: DhcpMessageType) -> DhcpPacket {
+        let mut packet = DhcpPacket::new()
+            .with_transaction(&self.transaction)
+            .with_mac_address(&self.mac_address)
+            .with_option(DhcpOptionValue::MessageType(mtype));
+        // A DHCPDECLINE carries nothing beyond the address and server
+        if mtype != DhcpMessageType::Decline {
+            match (&self.fqdn, &self.host_name) {
+                (Some(fqdn), _) => {
+                    packet = packet.with_option(DhcpOptionValue::ClientFqdn(fqdn.clone()))
+                }
+                (None, Some(name)) => {
+                    packet = packet.with_option(DhcpOptionValue::HostName(name.clone()))
+                }
+                (None, None) => {}
+            }
+            if let Some(vendor_class) = &self.vendor_class {
+                packet = packet.with_option(DhcpOptionValue::VendorClass(vendor_class.clone()));
+            }
+        }
+        self.with_client_id_option(packet)
+    }
+
+    // RFC 4361 section 6.1 asks for the same identifier in all messages
+    fn with_client_id_option(&self, packet: DhcpPacket) -> DhcpPacket {
+        match &self.client_id {
+            Some(client_id) => packet.with_option(client_id.get_option()),
+            None => packet,
+        }
+    }
+
+    fn with_requested_lease_time(&self, packet: DhcpPacket) -> DhcpPacket {
+        match self.lease_time {
+            Some(lease_time) => packet.with_option(DhcpOptionValue::LeaseTime(lease_time)),
+            None => packet,
+        }
+    }
+
+    // Return to INIT, dropping anything tied to the abandoned exchange
+    fn reset(&mut self) {
+        self.state = ClientState::Init;
+        self.offers.clear();
+        self.offer = None;
+        self.probing = None;
+        self.request_sent = None;
+        self.timeout = None;
+        self.started = None;
+        self.retries = 0;
+    }
+}
+
+#[cfg(test)]
+mod dhcp_client {
+    use super::*;
+    use crate::dhcp::{
+        duid::{iaid_from_interface, Duid},
+        traits::Serialize,
+    };
+    use pretty_assertions::assert_eq;
+
+    fn test_mac() -> MacAddress {
+        MacAddress::new([0x10, 0x7b, 0x44, 0x93, 0xe6, 0xd0])
+    }
+
+    fn server_ip() -> Ipv4Addr {
+        Ipv4Addr::new(192, 168, 1, 1)
+    }
+
+    fn reply(client: &DhcpClient, mtype: DhcpMessageType, ip: Ipv4Addr) -> DhcpPacket {
+        DhcpPacket::new()
+            .with_transaction(&client.get_transaction())
+            .with_client_ip(&ip)
+            .with_option(DhcpOptionValue::MessageType(mtype))
+            .with_option(DhcpOptionValue::DhcpServerIp(server_ip()))
+            .with_option(DhcpOptionValue::LeaseTime(Duration::from_secs(3600)))
+    }
+
+    fn sent_packet(actions: &[ClientAction]) -> &DhcpPacket {
+        match actions.first() {
+            Some(ClientAction::Broadcast(packet)) => packet,
+            Some(ClientAction::Unicast(_, packet)) => packet,
+            other => panic!("Expected a packet to be sent, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_start_sends_discover() {
+        let now = Instant::now();
+        let mut client = DhcpClient::new(test_mac());
+
+        let actions = client.handle(ClientEvent::Start, now);
+
+        assert_eq!(client.get_state(), ClientState::Selecting);
+        assert!(sent_packet(&actions).is_type(DhcpMessageType::Discover));
+        assert!(sent_packet(&actions).is_transaction(&client.get_transaction()));
+        assert_eq!(sent_packet(&actions).get_seconds(), 0);
+        assert_retransmission_after(&client, now, 4);
+    }
+
+    // The timer is set `seconds` after `now`, give or take the jitter
+    fn assert_retransmission_after(client: &DhcpClient, now: Instant, seconds: u64) {
+        let wait = client.get_timeout().unwrap() - now;
+        assert!(
+            wait >= Duration::from_secs(seconds - 1) && wait <= Duration::from_secs(seconds + 1),
+            "Expected a retransmission after {}s, got {:?}",
+            seconds,
+            wait
+        );
+    }
+
+    #[test]
+    fn test_discover_backoff() {
+        let start = Instant::now();
+        let mut client = DhcpClient::new(test_mac());
+        client.handle(ClientEvent::Start, start);
+        let transaction = client.get_transaction();
+
+        for seconds in [8, 16, 32, 64].iter() {
+            let now = client.get_timeout().unwrap();
+            let actions = client.handle(ClientEvent::TimerFired, now);
+            let discover = sent_packet(&actions);
+            assert!(discover.is_type(DhcpMessageType::Discover));
+            assert!(discover.is_transaction(&transaction));
+            assert_eq!(discover.get_seconds() as u64, (now - start).as_secs());
+            assert_retransmission_after(&client, now, *seconds);
+        }
+
+        let now = client.get_timeout().unwrap();
+        let actions = client.handle(ClientEvent::TimerFired, now);
+        assert_eq!(
+            actions,
+            vec![ClientAction::TimedOut(ClientState::Selecting)]
+        );
+        assert_eq!(client.get_state(), ClientState::Init);
+    }
+
+    #[test]
+    fn test_request_retransmission() {
+        let start = Instant::now();
+        let offered_ip = Ipv4Addr::new(192, 168, 1, 99);
+        let mut client = DhcpClient::new(test_mac()).with_retries(0, 1);
+        client.handle(ClientEvent::Start, start);
+        let now = start + Duration::from_secs(2);
+        let offer = reply(&client, DhcpMessageType::Offer, offered_ip);
+        client.handle(ClientEvent::PacketReceived(offer), now);
+        assert_retransmission_after(&client, now, 4);
+
+        let now = client.get_timeout().unwrap();
+        let actions = client.handle(ClientEvent::TimerFired, now);
+        let request = sent_packet(&actions);
+        assert!(request.is_type(DhcpMessageType::Request));
+        assert_eq!(request.get_requested_ip(), Some(offered_ip));
+        assert_eq!(request.get_server_ip(), Some(server_ip()));
+        assert_eq!(request.get_seconds() as u64, (now - start).as_secs());
+        assert_retransmission_after(&client, now, 8);
+
+        let actions = client.handle(ClientEvent::TimerFired, client.get_timeout().unwrap());
+        assert_eq!(
+            actions,
+            vec![ClientAction::TimedOut(ClientState::Requesting)]
+        );
+    }
+
+    #[test]
+    fn test_full_exchange() {
+        let now = Instant::now();
+        let offered_ip = Ipv4Addr::new(192, 168, 1, 99);
+        let mut client = DhcpClient::new(test_mac());
+        client.handle(ClientEvent::Start, now);
+
+        let offer = reply(&client, DhcpMessageType::Offer, offered_ip);
+        let actions = client.handle(ClientEvent::PacketReceived(offer), now);
+        let request = sent_packet(&actions);
+        assert_eq!(client.get_state(), ClientState::Requesting);
+        assert!(request.is_type(DhcpMessageType::Request));
+        assert_eq!(request.get_server_ip(), Some(server_ip()));
+
+        let ack = reply(&client, DhcpMessageType::Ack, offered_ip);
+        let actions = client.handle(ClientEvent::PacketReceived(ack), now);
+        assert_eq!(client.get_state(), ClientState::Bound);
+        match &actions[..] {
+            [ClientAction::Bound(lease)] => {
+                assert_eq!(lease.get_client_ip(), offered_ip);
+                assert_eq!(lease.get_server_ip(), server_ip());
+                assert_eq!(lease.get_lease_time(), Duration::from_secs(3600));
+            }
+            other => panic!("Expected a bound lease, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_rapid_commit() {
+        let now = Instant::now();
+        let offered_ip = Ipv4Addr::new(192, 168, 1, 99);
+        let mut client = DhcpClient::new(test_mac()).with_rapid_commit(true);
+        let actions = client.handle(ClientEvent::Start, now);
+        assert!(sent_packet(&actions).is_rapid_commit());
+
+        // A plain ACK in SELECTING is not a commit
+        let ack = reply(&client, DhcpMessageType::Ack, offered_ip);
+        assert!(client
+            .handle(ClientEvent::PacketReceived(ack), now)
+            .is_empty());
+        assert_eq!(client.get_state(), ClientState::Selecting);
+
+        let later = now + Duration::from_secs(1);
+        let ack = reply(&client, DhcpMessageType::Ack, offered_ip)
+            .with_option(DhcpOptionValue::RapidCommit);
+        let actions = client.handle(ClientEvent::PacketReceived(ack), later);
+        assert_eq!(client.get_state(), ClientState::Bound);
+        match &actions[..] {
+            [ClientAction::Bound(lease)] => {
+                assert_eq!(lease.get_client_ip(), offered_ip);
+                assert_eq!(lease.get_acquired(), now);
+            }
+            other => panic!("Expected a bound lease, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_rapid_commit_falls_back_to_offer() {
+        let now = Instant::now();
+        let offered_ip = Ipv4Addr::new(192, 168, 1, 99);
+        let mut client = DhcpClient::new(test_mac()).with_rapid_commit(true);
+        client.handle(ClientEvent::Start, now);
+
+        let offer = reply(&client, DhcpMessageType::Offer, offered_ip);
+        let actions = client.handle(ClientEvent::PacketReceived(offer), now);
+        let request = sent_packet(&actions);
+        assert!(request.is_type(DhcpMessageType::Request));
+        assert!(!request.is_rapid_commit());
+        assert_eq!(client.get_state(), ClientState::Requesting);
+
+        let ack = reply(&client, DhcpMessageType::Ack, offered_ip);
+        let actions = client.handle(ClientEvent::PacketReceived(ack), now);
+        assert!(matches!(actions[..], [ClientAction::Bound(_)]));
+    }
+
+    #[test]
+    fn test_offer_window() {
+        let now = Instant::now();
+        let window = Duration::from_secs(2);
+        let other_server = Ipv4Addr::new(192, 168, 1, 2);
+        let mut client = DhcpClient::new(test_mac())
+            .with_offer_window(window)
+            .with_offer_selector(crate::dhcp::selector::LongestLease);
+        client.handle(ClientEvent::Start, now);
+
+        let short = reply(
+            &client,
+            DhcpMessageType::Offer,
+            Ipv4Addr::new(192, 168, 1, 10),
+        );
+        let long = DhcpPacket::new()
+            .with_transaction(&client.get_transaction())
+            .with_client_ip(&Ipv4Addr::new(192, 168, 1, 20))
+            .with_option(DhcpOptionValue::MessageType(DhcpMessageType::Offer))
+            .with_option(DhcpOptionValue::DhcpServerIp(other_server))
+            .with_option(DhcpOptionValue::LeaseTime(Duration::from_secs(7200)));
+        assert!(client
+            .handle(ClientEvent::PacketReceived(short), now)
+            .is_empty());
+        assert_eq!(client.get_timeout(), Some(now + window));
+        let later = now + Duration::from_secs(1);
+        assert!(client
+            .handle(ClientEvent::PacketReceived(long), later)
+            .is_empty());
+        assert_eq!(client.get_state(), ClientState::Selecting);
+        assert_eq!(client.get_timeout(), Some(now + window));
+
+        let actions = client.handle(ClientEvent::TimerFired, now + window);
+        let request = sent_packet(&actions);
+        assert_eq!(client.get_state(), ClientState::Requesting);
+        assert_eq!(
+            request.get_requested_ip(),
+            Some(Ipv4Addr::new(192, 168, 1, 20))
+        );
+        assert_eq!(request.get_server_ip(), Some(other_server));
+
+        // Only the chosen server's answer counts
+        let ack = reply(
+            &client,
+            DhcpMessageType::Ack,
+            Ipv4Addr::new(192, 168, 1, 10),
+        );
+        assert!(client
+            .handle(ClientEvent::PacketReceived(ack), now + window)
+            .is_empty());
+    }
+
+    struct RejectAll;
+
+    impl OfferSelector for RejectAll {
+        fn select(&self, _offers: &[DhcpPacket]) -> Option<usize> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_rejected_offers_keep_discovering() {
+        let now = Instant::now();
+        let mut client = DhcpClient::new(test_mac()).with_offer_selector(RejectAll);
+        client.handle(ClientEvent::Start, now);
+
+        let offer = reply(
+            &client,
+            DhcpMessageType::Offer,
+            Ipv4Addr::new(192, 168, 1, 10),
+        );
+        assert!(client
+            .handle(ClientEvent::PacketReceived(offer), now)
+            .is_empty());
+        assert_eq!(client.get_state(), ClientState::Selecting);
+
+        let actions = client.handle(ClientEvent::TimerFired, client.get_timeout().unwrap());
+        assert!(sent_packet(&actions).is_type(DhcpMessageType::Discover));
+    }
+
+    #[test]
+    fn test_ignores_foreign_transaction() {
+        let now = Instant::now();
+        let mut client = DhcpClient::new(test_mac());
+        client.handle(ClientEvent::Start, now);
+
+        let offer = reply(
+            &client,
+            DhcpMessageType::Offer,
+            Ipv4Addr::new(192, 168, 1, 99),
+        )
+        .with_transaction(&[0xde, 0xad, 0xbe, 0xef]);
+        let actions = client.handle(ClientEvent::PacketReceived(offer), now);
+
+        assert!(actions.is_empty());
+        assert_eq!(client.get_state(), ClientState::Selecting);
+    }
+
+    #[test]
+    fn test_nak_returns_to_init() {
+        let now = Instant::now();
+        let offered_ip = Ipv4Addr::new(192, 168, 1, 99);
+        let mut client = DhcpClient::new(test_mac());
+        client.handle(ClientEvent::Start, now);
+        let offer = reply(&client, DhcpMessageType::Offer, offered_ip);
+        client.handle(ClientEvent::PacketReceived(offer), now);
+
+        let nak = reply(&client, DhcpMessageType::Nak, offered_ip);
+        let actions = client.handle(ClientEvent::PacketReceived(nak), now);
+
+        assert_eq!(actions, vec![ClientAction::Nak(Some(server_ip()))]);
+        assert_eq!(client.get_state(), ClientState::Init);
+    }
+
+    #[test]
+    fn test_timer_fired_before_deadline_is_ignored() {
+        let now = Instant::now();
+        let mut client = DhcpClient::new(test_mac());
+        client.handle(ClientEvent::Start, now);
+
+        assert!(client.handle(ClientEvent::TimerFired, now).is_empty());
+        assert_eq!(client.get_state(), ClientState::Selecting);
+
+        let actions = client.handle(ClientEvent::TimerFired, client.get_timeout().unwrap());
+        assert!(sent_packet(&actions).is_type(DhcpMessageType::Discover));
+        assert_eq!(client.get_state(), ClientState::Selecting);
+    }
+
+    #[test]
+    fn test_init_reboot_requests_previous_ip() {
+        let now = Instant::now();
+        let previous_ip = Ipv4Addr::new(192, 168, 1, 42);
+        let mut client = DhcpClient::new(test_mac()).with_previous_ip(&previous_ip);
+        assert_eq!(client.get_state(), ClientState::InitReboot);
+
+        let actions = client.handle(ClientEvent::Start, now);
+        let request = sent_packet(&actions);
+        assert_eq!(client.get_state(), ClientState::Rebooting);
+        assert!(request.is_type(DhcpMessageType::Request));
+        assert_eq!(request.get_requested_ip(), Some(previous_ip));
+        assert_eq!(request.get_current_ip(), Ipv4Addr::UNSPECIFIED);
+        assert_eq!(request.get_server_ip(), None);
+
+        let ack = reply(&client, DhcpMessageType::Ack, previous_ip);
+        client.handle(ClientEvent::PacketReceived(ack), now);
+        assert_eq!(client.get_state(), ClientState::Bound);
+    }
+
+    #[test]
+    fn test_init_reboot_nak() {
+        let now = Instant::now();
+        let previous_ip = Ipv4Addr::new(192, 168, 1, 42);
+        let mut client = DhcpClient::new(test_mac()).with_previous_ip(&previous_ip);
+        client.handle(ClientEvent::Start, now);
+
+        let nak = reply(&client, DhcpMessageType::Nak, Ipv4Addr::UNSPECIFIED);
+        let actions = client.handle(ClientEvent::PacketReceived(nak), now);
+        assert_eq!(actions, vec![ClientAction::Nak(Some(server_ip()))]);
+        assert_eq!(client.get_state(), ClientState::Init);
+
+        let actions = client.handle(ClientEvent::TimerFired, now);
+        let discover = sent_packet(&actions);
+        assert!(discover.is_type(DhcpMessageType::Discover));
+        assert_eq!(discover.get_requested_ip(), None);
+    }
+
+    #[test]
+    fn test_init_reboot_silence_falls_back_to_discover() {
+        let now = Instant::now();
+        let previous_ip = Ipv4Addr::new(192, 168, 1, 42);
+        let mut client = DhcpClient::new(test_mac())
+            .with_previous_ip(&previous_ip)
+            .with_retries(4, 1);
+        client.handle(ClientEvent::Start, now);
+        let first_transaction = client.get_transaction();
+
+        let actions = client.handle(ClientEvent::TimerFired, client.get_timeout().unwrap());
+        assert!(sent_packet(&actions).is_type(DhcpMessageType::Request));
+        assert_eq!(client.get_state(), ClientState::Rebooting);
+
+        let actions = client.handle(ClientEvent::TimerFired, client.get_timeout().unwrap());
+        let discover = sent_packet(&actions);
+        assert!(discover.is_type(DhcpMessageType::Discover));
+        assert_eq!(discover.get_requested_ip(), Some(previous_ip));
+        assert_ne!(discover.get_transaction(), first_transaction);
+        assert_eq!(client.get_state(), ClientState::Selecting);
+    }
+
+    #[test]
+    fn test_requested_ip_and_lease_time() {
+        let now = Instant::now();
+        let wanted_ip = Ipv4Addr::new(192, 168, 1, 42);
+        let mut client = DhcpClient::new(test_mac())
+            .with_requested_ip(&wanted_ip)
+            .with_lease_time(Duration::from_secs(7200));
+
+        let actions = client.handle(ClientEvent::Start, now);
+        let discover = sent_packet(&actions);
+        assert_eq!(discover.get_requested_ip(), Some(wanted_ip));
+        assert_eq!(discover.get_lease_time(), Some(Duration::from_secs(7200)));
+
+        // The server's choice wins over the suggestion
+        let offered_ip = Ipv4Addr::new(192, 168, 1, 99);
+        let offer = reply(&client, DhcpMessageType::Offer, offered_ip);
+        let actions = client.handle(ClientEvent::PacketReceived(offer), now);
+        let request = sent_packet(&actions);
+        assert_eq!(request.get_requested_ip(), Some(offered_ip));
+        assert_eq!(request.get_lease_time(), Some(Duration::from_secs(7200)));
+    }
+
+    fn bind(client: &mut DhcpClient, ip: Ipv4Addr, now: Instant) -> Lease {
+        client.handle(ClientEvent::Start, now);
+        let offer = reply(client, DhcpMessageType::Offer, ip);
+        client.handle(ClientEvent::PacketReceived(offer), now);
+        let ack = reply(client, DhcpMessageType::Ack, ip);
+        client.handle(ClientEvent::PacketReceived(ack), now);
+        client.get_lease().unwrap().clone()
+    }
+
+    #[test]
+    fn test_lease_default_timers() {
+        let now = Instant::now();
+        let mut client = DhcpClient::new(test_mac());
+        let lease = bind(&mut client, Ipv4Addr::new(192, 168, 1, 99), now);
+
+        assert_eq!(lease.get_renewal_time(), Duration::from_secs(1800));
+        assert_eq!(lease.get_rebinding_time(), Duration::from_secs(3150));
+        assert_eq!(client.get_timeout(), Some(now + Duration::from_secs(1800)));
+    }
+
+    #[test]
+    fn test_lease_server_timers() {
+        let now = Instant::now();
+        let offered_ip = Ipv4Addr::new(192, 168, 1, 99);
+        let mut client = DhcpClient::new(test_mac());
+        client.handle(ClientEvent::Start, now);
+        let offer = reply(&client, DhcpMessageType::Offer, offered_ip);
+        client.handle(ClientEvent::PacketReceived(offer), now);
+        let ack = reply(&client, DhcpMessageType::Ack, offered_ip)
+            .with_option(DhcpOptionValue::RenewalTime(Duration::from_secs(900)))
+            .with_option(DhcpOptionValue::RebindingTime(Duration::from_secs(1800)));
+        client.handle(ClientEvent::PacketReceived(ack), now);
+
+        let lease = client.get_lease().unwrap();
+        assert_eq!(lease.get_renewal_time(), Duration::from_secs(900));
+        assert_eq!(lease.get_rebinding_time(), Duration::from_secs(1800));
+    }
+
+    #[test]
+    fn test_renew_at_t1() {
+        let now = Instant::now();
+        let offered_ip = Ipv4Addr::new(192, 168, 1, 99);
+        let mut client = DhcpClient::new(test_mac());
+        let lease = bind(&mut client, offered_ip, now);
+
+        let t1 = lease.get_renewal_deadline();
+        let actions = client.handle(ClientEvent::TimerFired, t1);
+        assert_eq!(client.get_state(), ClientState::Renewing);
+        match &actions[..] {
+            [ClientAction::Unicast(addr, request)] => {
+                assert_eq!(*addr, server_ip());
+                assert!(request.is_type(DhcpMessageType::Request));
+                assert_eq!(request.get_current_ip(), offered_ip);
+                assert_eq!(request.get_server_ip(), None);
+            }
+            other => panic!("Expected a unicast request, got {:?}", other),
+        }
+
+        let ack = reply(&client, DhcpMessageType::Ack, offered_ip);
+        let actions = client.handle(ClientEvent::PacketReceived(ack), t1);
+        assert!(matches!(actions[..], [ClientAction::Renewed(_)]));
+        assert_eq!(client.get_state(), ClientState::Bound);
+        assert_eq!(client.get_lease().unwrap().get_acquired(), t1);
+    }
+
+    #[test]
+    fn test_resume_held_lease() {
+        let now = Instant::now();
+        let held_ip = Ipv4Addr::new(192, 168, 1, 99);
+        let lease = bind(&mut DhcpClient::new(test_mac()), held_ip, now);
+        let mut client = DhcpClient::new(test_mac()).with_held_lease(&lease);
+        assert_eq!(client.get_state(), ClientState::Bound);
+        assert_eq!(client.get_timeout(), Some(lease.get_renewal_deadline()));
+
+        let actions = client.handle(ClientEvent::Start, now);
+        assert_eq!(client.get_state(), ClientState::Renewing);
+        match &actions[..] {
+            [ClientAction::Unicast(addr, request)] => {
+                assert_eq!(*addr, server_ip());
+                assert_eq!(request.get_current_ip(), held_ip);
+                assert_eq!(request.get_requested_ip(), None);
+                assert_eq!(request.get_server_ip(), None);
+            }
+            other => panic!("Expected a unicast request, got {:?}", other),
+        }
+        let ack = reply(&client, DhcpMessageType::Ack, held_ip);
+        let actions = client.handle(ClientEvent::PacketReceived(ack), now);
+        assert!(matches!(actions[..], [ClientAction::Renewed(_)]));
+
+        // Past T2 the original server is no longer waited for
+        let mut client = DhcpClient::new(test_mac()).with_held_lease(&lease);
+        let actions = client.handle(ClientEvent::Start, lease.get_rebinding_deadline());
+        assert_eq!(client.get_state(), ClientState::Rebinding);
+        assert!(matches!(actions[..], [ClientAction::Broadcast(_)]));
+    }
+
+    #[test]
+    fn test_rebind_and_expire() {
+        let now = Instant::now();
+        let mut client = DhcpClient::new(test_mac());
+        let lease = bind(&mut client, Ipv4Addr::new(192, 168, 1, 99), now);
+
+        client.handle(ClientEvent::TimerFired, lease.get_renewal_deadline());
+        let actions = client.handle(ClientEvent::TimerFired, lease.get_rebinding_deadline());
+        assert_eq!(client.get_state(), ClientState::Rebinding);
+        assert!(matches!(actions[..], [ClientAction::Broadcast(_)]));
+
+        let actions = client.handle(ClientEvent::TimerFired, lease.get_expiry());
+        assert!(matches!(actions[..], [ClientAction::Expired(_)]));
+        assert_eq!(client.get_state(), ClientState::Init);
+        assert!(client.get_lease().is_none());
+
+        // INIT moves straight on to discovery
+        let actions = client.handle(ClientEvent::TimerFired, lease.get_expiry());
+        assert_eq!(client.get_state(), ClientState::Selecting);
+        assert!(sent_packet(&actions).is_type(DhcpMessageType::Discover));
+    }
+
+    #[test]
+    fn test_renewal_retry_deadline() {
+        let now = Instant::now();
+
+        assert_eq!(
+            DhcpClient::retry_deadline(now, now + Duration::from_secs(1000)),
+            now + Duration::from_secs(500)
+        );
+        assert_eq!(
+            DhcpClient::retry_deadline(now, now + Duration::from_secs(100)),
+            now + MIN_RENEWAL_RETRY
+        );
+        assert_eq!(
+            DhcpClient::retry_deadline(now, now + Duration::from_secs(30)),
+            now + Duration::from_secs(30)
+        );
+    }
+
+    #[test]
+    fn test_release() {
+        let now = Instant::now();
+        let offered_ip = Ipv4Addr::new(192, 168, 1, 99);
+        let mut client = DhcpClient::new(test_mac());
+        bind(&mut client, offered_ip, now);
+
+        let actions = client.release();
+        match &actions[..] {
+            [ClientAction::Unicast(addr, release), ClientAction::Released(lease)] => {
+                assert_eq!(*addr, server_ip());
+                assert!(release.is_type(DhcpMessageType::Release));
+                assert_eq!(release.get_current_ip(), offered_ip);
+                assert_eq!(release.get_server_ip(), Some(server_ip()));
+                assert_eq!(lease.get_client_ip(), offered_ip);
+            }
+            other => panic!("Expected a unicast release, got {:?}", other),
+        }
+        assert_eq!(client.get_state(), ClientState::Init);
+        assert_eq!(client.get_timeout(), None);
+        assert!(client.get_lease().is_none());
+
+        // Nothing left to give back
+        assert!(client.release().is_empty());
+    }
+
+    #[test]
+    fn test_client_id_in_every_message() {
+        let now = Instant::now();
+        let offered_ip = Ipv4Addr::new(192, 168, 1, 99);
+        let client_id =
+            ClientIdentifier::new(iaid_from_interface("eth0"), Duid::from_mac(&test_mac()));
+        let expected = Some(client_id.serialize());
+        let mut client = DhcpClient::new(test_mac()).with_client_id(&client_id);
+
+        let actions = client.handle(ClientEvent::Start, now);
+        assert_eq!(sent_packet(&actions).get_client_id(), expected);
+        let offer = reply(&client, DhcpMessageType::Offer, offered_ip);
+        let actions = client.handle(ClientEvent::PacketReceived(offer), now);
+        assert_eq!(sent_packet(&actions).get_client_id(), expected);
+        let ack = reply(&client, DhcpMessageType::Ack, offered_ip);
+        client.handle(ClientEvent::PacketReceived(ack), now);
+
+        let actions = client.handle(ClientEvent::TimerFired, client.get_timeout().unwrap());
+        assert_eq!(sent_packet(&actions).get_client_id(), expected);
+        let actions = client.release();
+        assert_eq!(sent_packet(&actions).get_client_id(), expected);
+    }
+
+    #[test]
+    fn test_host_name_and_fqdn() {
+        let now = Instant::now();
+        let mut client = DhcpClient::new(test_mac())
+            .with_host_name("appliance")
+            .unwrap();
+        let actions = client.handle(ClientEvent::Start, now);
+        let discover = sent_packet(&actions);
+        assert_eq!(discover.get_host_name(), Some("appliance".to_string()));
+        assert_eq!(discover.get_client_fqdn(), None);
+        assert!(DhcpClient::new(test_mac())
+            .with_host_name(&"a".repeat(64))
+            .is_err());
+
+        let fqdn = ClientFqdn::new("appliance").unwrap();
+        let offered_ip = Ipv4Addr::new(192, 168, 1, 99);
+        let mut client = DhcpClient::new(test_mac())
+            .with_host_name("appliance")
+            .unwrap()
+            .with_fqdn(&fqdn)
+            .with_conflict_detection(true);
+        client.handle(ClientEvent::Start, now);
+        let offer = reply(&client, DhcpMessageType::Offer, offered_ip);
+        let actions = client.handle(ClientEvent::PacketReceived(offer), now);
+        let request = sent_packet(&actions);
+        assert_eq!(request.get_client_fqdn(), Some(fqdn));
+        assert_eq!(request.get_host_name(), None);
+
+        // A DHCPDECLINE after a conflict leaves the name out
+        let ack = reply(&client, DhcpMessageType::Ack, offered_ip);
+        client.handle(ClientEvent::PacketReceived(ack), now);
+        let actions = client.handle(ClientEvent::AddressInUse, now);
+        let decline = sent_packet(&actions);
+        assert!(decline.is_type(DhcpMessageType::Decline));
+        assert_eq!(decline.get_client_fqdn(), None);
+    }
+
+    #[test]
+    fn test_vendor_class() {
+        let now = Instant::now();
+        let offered_ip = Ipv4Addr::new(192, 168, 1, 99);
+        let mut client = DhcpClient::new(test_mac())
+            .with_vendor_class(b"ExamplePhone 1.0")
+            .with_conflict_detection(true);
+        assert_eq!(client.get_vendor_class(), Some(&b"ExamplePhone 1.0"[..]));
+        let actions = client.handle(ClientEvent::Start, now);
+        let discover = sent_packet(&actions);
+        assert_eq!(
+            discover.get_vendor_class(),
+            Some(b"ExamplePhone 1.0".to_vec())
+        );
+
+        let offer = reply(&client, DhcpMessageType::Offer, offered_ip);
+        let actions = client.handle(ClientEvent::PacketReceived(offer), now);
+        let request = sent_packet(&actions);
+        assert_eq!(
+            request.get_vendor_class(),
+            Some(b"ExamplePhone 1.0".to_vec())
+        );
+
+        let ack = reply(&client, DhcpMessageType::Ack, offered_ip);
+        client.handle(ClientEvent::PacketReceived(ack), now);
+        let actions = client.handle(ClientEvent::AddressInUse, now);
+        assert_eq!(sent_packet(&actions).get_vendor_class(), None);
+    }
+
+    #[test]
+    fn test_probe_before_bind() {
+        let now = Instant::now();
+        let offered_ip = Ipv4Addr::new(192, 168, 1, 99);
+        let mut client = DhcpClient::new(test_mac()).with_conflict_detection(true);
+        client.handle(ClientEvent::Start, now);
+        let offer = reply(&client, DhcpMessageType::Offer, offered_ip);
+        client.handle(ClientEvent::PacketReceived(offer), now);
+
+        let ack = reply(&client, DhcpMessageType::Ack, offered_ip);
+        let actions = client.handle(ClientEvent::PacketReceived(ack), now);
+        assert_eq!(actions, vec![ClientAction::Probe(offered_ip)]);
+        assert_eq!(client.get_state(), ClientState::Requesting);
+        assert!(client.get_lease().is_none());
+
+        let actions = client.handle(ClientEvent::AddressAvailable, now);
+        assert!(matches!(actions[..], [ClientAction::Bound(_)]));
+        assert_eq!(client.get_state(), ClientState::Bound);
+    }
+
+    #[test]
+    fn test_rapid_commit_probes_before_bind() {
+        let now = Instant::now();
+        let offered_ip = Ipv4Addr::new(192, 168, 1, 99);
+        let mut client = DhcpClient::new(test_mac())
+            .with_rapid_commit(true)
+            .with_conflict_detection(true);
+        client.handle(ClientEvent::Start, now);
+
+        let ack = reply(&client, DhcpMessageType::Ack, offered_ip)
+            .with_option(DhcpOptionValue::RapidCommit);
+        let actions = client.handle(ClientEvent::PacketReceived(ack), now);
+        assert_eq!(actions, vec![ClientAction::Probe(offered_ip)]);
+
+        let actions = client.handle(ClientEvent::AddressInUse, now);
+        assert!(matches!(
+            actions[..],
+            [ClientAction::Broadcast(_), ClientAction::Declined(_)]
+        ));
+        assert_eq!(client.get_state(), ClientState::Init);
+    }
+
+    #[test]
+    fn test_probe_timeout() {
+        let now = Instant::now();
+        let previous_ip = Ipv4Addr::new(192, 168, 1, 42);
+        let mut client = DhcpClient::new(test_mac())
+            .with_previous_ip(&previous_ip)
+            .with_conflict_detection(true);
+        client.handle(ClientEvent::Start, now);
+        let ack = reply(&client, DhcpMessageType::Ack, previous_ip);
+        let actions = client.handle(ClientEvent::PacketReceived(ack), now);
+        assert_eq!(actions, vec![ClientAction::Probe(previous_ip)]);
+
+        // No REBOOT REQUEST goes out again while the probe is pending
+        let actions = client.handle(ClientEvent::TimerFired, now + RESPONSE_TIMEOUT);
+        assert_eq!(
+            actions,
+            vec![ClientAction::TimedOut(ClientState::Rebooting)]
+        );
+        assert_eq!(client.get_state(), ClientState::Init);
+        assert_eq!(client.handle(ClientEvent::AddressAvailable, now), vec![]);
+
+        let offered_ip = Ipv4Addr::new(192, 168, 1, 99);
+        let mut client = DhcpClient::new(test_mac()).with_conflict_detection(true);
+        client.handle(ClientEvent::Start, now);
+        let offer = reply(&client, DhcpMessageType::Offer, offered_ip);
+        client.handle(ClientEvent::PacketReceived(offer), now);
+        let ack = reply(&client, DhcpMessageType::Ack, offered_ip);
+        client.handle(ClientEvent::PacketReceived(ack), now);
+        let actions = client.handle(ClientEvent::TimerFired, now + RESPONSE_TIMEOUT);
+        assert_eq!(
+            actions,
+            vec![ClientAction::TimedOut(ClientState::Requesting)]
+        );
+        assert_eq!(client.get_timeout(), None);
+    }
+
+    #[test]
+    fn test_decline_on_conflict() {
+        let now = Instant::now();
+        let offered_ip = Ipv4Addr::new(192, 168, 1, 99);
+        let mut client = DhcpClient::new(test_mac()).with_conflict_detection(true);
+        client.handle(ClientEvent::Start, now);
+        let offer = reply(&client, DhcpMessageType::Offer, offered_ip);
+        client.handle(ClientEvent::PacketReceived(offer), now);
+        let ack = reply(&client, DhcpMessageType::Ack, offered_ip);
+        client.handle(ClientEvent::PacketReceived(ack), now);
+
+        let actions = client.handle(ClientEvent::AddressInUse, now);
+        match &actions[..] {
+            [ClientAction::Broadcast(decline), ClientAction::Declined(lease)] => {
+                assert!(decline.is_type(DhcpMessageType::Decline));
+                assert_eq!(lease.get_client_ip(), offered_ip);
+                assert_eq!(decline.get_server_ip(), Some(server_ip()));
+                assert_eq!(decline.get_current_ip(), Ipv4Addr::UNSPECIFIED);
+            }
+            other => panic!("Expected a broadcast decline, got {:?}", other),
+        }
+        assert_eq!(client.get_state(), ClientState::Init);
+        assert_eq!(client.get_timeout(), Some(now + DECLINE_BACKOFF));
+
+        // Discovery restarts only once the back-off is over
+        assert!(client.handle(ClientEvent::TimerFired, now).is_empty());
+        let actions = client.handle(ClientEvent::TimerFired, now + DECLINE_BACKOFF);
+        assert!(sent_packet(&actions).is_type(DhcpMessageType::Discover));
+    }
+
+    #[test]
+    fn test_renewal_skips_probe() {
+        let now = Instant::now();
+        let offered_ip = Ipv4Addr::new(192, 168, 1, 99);
+        let mut client = DhcpClient::new(test_mac()).with_conflict_detection(true);
+        client.handle(ClientEvent::Start, now);
+        let offer = reply(&client, DhcpMessageType::Offer, offered_ip);
+        client.handle(ClientEvent::PacketReceived(offer), now);
+        let ack = reply(&client, DhcpMessageType::Ack, offered_ip);
+        client.handle(ClientEvent::PacketReceived(ack), now);
+        client.handle(ClientEvent::AddressAvailable, now);
+
+        let t1 = client.get_lease().unwrap().get_renewal_deadline();
+        client.handle(ClientEvent::TimerFired, t1);
+        let ack = reply(&client, DhcpMessageType::Ack, offered_ip);
+        let actions = client.handle(ClientEvent::PacketReceived(ack), t1);
+        assert!(matches!(actions[..], [ClientAction::Renewed(_)]));
+    }
+
+    #[test]
+    fn test_inform() {
+        let now = Instant::now();
+        let static_ip = Ipv4Addr::new(192, 168, 1, 10);
+        let mut client = DhcpClient::new(test_mac()).with_static_ip(&static_ip);
+
+        let actions = client.handle(ClientEvent::Start, now);
+        let inform = sent_packet(&actions);
+        assert!(inform.is_type(DhcpMessageType::Inform));
+        assert_eq!(inform.get_current_ip(), static_ip);
+        assert_eq!(inform.get_option(DhcpOptionType::RequestedIp), None);
+        assert_eq!(
+            inform.get_option(DhcpOptionType::ParameterRequest),
+            Some(DhcpOptionValue::ParameterRequest(vec![
+                1, 3, 6, 15, 42, 119
+            ]))
+        );
+
+        // No yiaddr and no lease time in the answer
+        let ack = DhcpPacket::new()
+            .with_transaction(&client.get_transaction())
+            .with_option(DhcpOptionValue::MessageType(DhcpMessageType::Ack))
+            .with_option(DhcpOptionValue::DnsServerIp(vec![server_ip()]));
+        let actions = client.handle(ClientEvent::PacketReceived(ack.clone()), now);
+
+        assert_eq!(actions, vec![ClientAction::Informed(ack)]);
+        assert_eq!(client.get_state(), ClientState::Init);
+        assert_eq!(client.get_timeout(), None);
+        assert!(client.get_lease().is_none());
+    }
+
+    #[test]
+    fn test_inform_timeout() {
+        let now = Instant::now();
+        let mut client =
+            DhcpClient::new(test_mac()).with_static_ip(&Ipv4Addr::new(192, 168, 1, 10));
+        client.handle(ClientEvent::Start, now);
+
+        let actions = client.handle(ClientEvent::TimerFired, now + RESPONSE_TIMEOUT);
+        assert_eq!(actions, vec![ClientAction::TimedOut(ClientState::Init)]);
+        assert_eq!(client.get_timeout(), None);
+    }
+}
@@ -0,0 +1,426 @@
+use crate::dhcp::{
+    error::DhcpParseError,
+    options::DhcpOptionValue,
+    store::write_atomically,
+    traits::{Deserialize, Serialize},
+};
+use hmac::{Hmac, Mac};
+use mac_address::MacAddress;
+use sha2::Sha256;
+use std::{
+    convert::TryInto,
+    fmt, fs, io,
+    path::Path,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// DUID types from RFC 8415 section 11 and RFC 6355
+const DUID_LLT: u16 = 1;
+const DUID_EN: u16 = 2;
+const DUID_LL: u16 = 3;
+const DUID_UUID: u16 = 4;
+// Hardware type of Ethernet from the ARP parameters registry
+const HARDWARE_ETHERNET: u16 = 1;
+// DUID-LLT counts seconds from midnight UTC on January 1st 2000
+const DUID_EPOCH: u64 = 946_684_800;
+// RFC 8415 section 11.1 allows at most 128 bytes after the type
+const MAX_DUID_LENGTH: usize = 128;
+// Keys the hash of the machine id, so our DUID-UUID is not shared with other programs and
+// does not give the machine id away, see machine-id(5)
+const MACHINE_ID_APP_KEY: [u8; 16] = [
+    0xde, 0xe4, 0xaf, 0x63, 0x70, 0xb7, 0x8d, 0xca, 0xc2, 0x9f, 0x34, 0x2c, 0x4b, 0xc2, 0xb9, 0xa9,
+];
+// Marks an option 61 that holds an IAID and DUID, see RFC 4361 section 6.1
+const CLIENT_ID_DUID: u8 = 255;
+
+// A DHCP Unique Identifier, the same one a DHCPv6 client on this host would use
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Duid {
+    LinkLayerTime {
+        hardware_type: u16,
+        time: u32,
+        address: Vec<u8>,
+    },
+    Enterprise {
+        enterprise_number: u32,
+        identifier: Vec<u8>,
+    },
+    LinkLayer {
+        hardware_type: u16,
+        address: Vec<u8>,
+    },
+    Uuid([u8; 16]),
+    Unknown(u16, Vec<u8>),
+}
+
+impl Duid {
+    // DUID-LLT of an Ethernet address, `created` is when the DUID is first made
+    pub fn from_mac_time(mac_address: &MacAddress, created: SystemTime) -> Self {
+        let seconds = created
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            .saturating_sub(DUID_EPOCH);
+        Duid::LinkLayerTime {
+            hardware_type: HARDWARE_ETHERNET,
+            // Wraps around like the RFC asks for
+            time: seconds as u32,
+            address: mac_address.bytes().to_vec(),
+        }
+    }
+
+    // DUID-LL, only stable for as long as the NIC stays in place
+    pub fn from_mac(mac_address: &MacAddress) -> Self {
+        Duid::LinkLayer {
+            hardware_type: HARDWARE_ETHERNET,
+            address: mac_address.bytes().to_vec(),
+        }
+    }
+
+    // DUID-EN, an identifier assigned by the vendor holding the IANA enterprise number
+    pub fn from_enterprise(enterprise_number: u32, identifier: &[u8]) -> Self {
+        Duid::Enterprise {
+            enterprise_number,
+            identifier: identifier.to_vec(),
+        }
+    }
+
+    // DUID-UUID derived from systemd's machine id the way sd_id128_get_machine_app_specific
+    // does it: an HMAC-SHA256 keyed by the machine id, cut down to a version 4 UUID
+    pub fn from_machine_id(machine_id: &[u8; 16]) -> Self {
+        let mut hmac = Hmac::<Sha256>::new_from_slice(machine_id).unwrap();
+        hmac.update(&MACHINE_ID_APP_KEY);
+        let mut uuid: [u8; 16] = hmac.finalize().into_bytes()[..16].try_into().unwrap();
+        uuid[6] = (uuid[6] & 0x0f) | 0x40;
+        uuid[8] = (uuid[8] & 0x3f) | 0x80;
+        Duid::Uuid(uuid)
+    }
+
+    pub fn get_type(&self) -> u16 {
+        match self {
+            Duid::LinkLayerTime { .. } => DUID_LLT,
+            Duid::Enterprise { .. } => DUID_EN,
+            Duid::LinkLayer { .. } => DUID_LL,
+            Duid::Uuid(_) => DUID_UUID,
+            Duid::Unknown(duid_type, _) => *duid_type,
+        }
+    }
+
+    // Reads the DUID saved at `path`, saving a newly created one when there is none yet
+    pub fn load_or_create<P: AsRef<Path>, F: FnOnce() -> io::Result<Duid>>(
+        path: P,
+        create: F,
+    ) -> io::Result<Self> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(contents) => contents.trim().parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} does not hold a DUID", path.display()),
+                )
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let duid = create()?;
+                if duid.serialize().len() > 2 + MAX_DUID_LENGTH {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("DUID {} is longer than {} bytes", duid, MAX_DUID_LENGTH),
+                    ));
+                }
+                write_atomically(path, format!("{}\n", duid).as_bytes())?;
+                Ok(duid)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Serialize for Duid {
+    type Out = Vec<u8>;
+    fn serialize(&self) -> Self::Out {
+        let mut buffer = self.get_type().to_be_bytes().to_vec();
+        match self {
+            Duid::LinkLayerTime {
+                hardware_type,
+                time,
+                address,
+            } => {
+                buffer.extend_from_slice(&hardware_type.to_be_bytes());
+                buffer.extend_from_slice(&time.to_be_bytes());
+                buffer.extend_from_slice(address);
+            }
+            Duid::Enterprise {
+                enterprise_number,
+                identifier,
+            } => {
+                buffer.extend_from_slice(&enterprise_number.to_be_bytes());
+                buffer.extend_from_slice(identifier);
+            }
+            Duid::LinkLayer {
+                hardware_type,
+                address,
+            } => {
+                buffer.extend_from_slice(&hardware_type.to_be_bytes());
+                buffer.extend_from_slice(address);
+            }
+            Duid::Uuid(uuid) => buffer.extend_from_slice(uuid),
+            Duid::Unknown(_, body) => buffer.extend_from_slice(body),
+        }
+        buffer
+    }
+}
+
+impl Deserialize for Duid {
+    type Out = Self;
+    type Error = DhcpParseError;
+    // DUIDs are opaque to servers, so a body that does not fit its type is kept as is
+    fn deserialize(data: &[u8]) -> Result<Self::Out, Self::Error> {
+        if data.len() < 2 {
+            return Err(DhcpParseError::TruncatedHeader {
+                offset: data.len(),
+                expected: 2,
+            });
+        }
+        if data.len() > 2 + MAX_DUID_LENGTH {
+            return Err(DhcpParseError::OversizedDuid {
+                offset: 2 + MAX_DUID_LENGTH,
+                length: data.len() - 2,
+            });
+        }
+        let duid_type = u16::from_be_bytes([data[0], data[1]]);
+        let body = &data[2..];
+        let u16_at = |x: usize| u16::from_be_bytes([body[x], body[x + 1]]);
+        let u32_at = |x: usize| u32::from_be_bytes(body[x..x + 4].try_into().unwrap());
+        let duid = match duid_type {
+            DUID_LLT if body.len() >= 6 => Duid::LinkLayerTime {
+                hardware_type: u16_at(0),
+                time: u32_at(2),
+                address: body[6..].to_vec(),
+            },
+            DUID_EN if body.len() >= 4 => Duid::Enterprise {
+                enterprise_number: u32_at(0),
+                identifier: body[4..].to_vec(),
+            },
+            DUID_LL if body.len() >= 2 => Duid::LinkLayer {
+                hardware_type: u16_at(0),
+                address: body[2..].to_vec(),
+            },
+            DUID_UUID if body.len() == 16 => Duid::Uuid(body.try_into().unwrap()),
+            _ => Duid::Unknown(duid_type, body.to_vec()),
+        };
+        Ok(duid)
+    }
+}
+
+// Colon separated hex, the way dhcpcd and systemd-networkd write DUIDs
+impl fmt::Display for Duid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<String> = self
+            .serialize()
+            .iter()
+            .map(|x| format!("{:02x}", x))
+            .collect();
+        write!(f, "{}", bytes.join(":"))
+    }
+}
+
+impl FromStr for Duid {
+    type Err = DhcpParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = s
+            .split(':')
+            .map(|x| match x.len() {
+                2 => u8::from_str_radix(x, 16).ok(),
+                _ => None,
+            })
+            .collect::<Option<Vec<u8>>>()
+            .unwrap_or_default();
+        Duid::deserialize(&bytes)
+    }
+}
+
+// Identity per interface, derived from its name so it survives a NIC swap.
+// FNV-1a keeps the value the same across builds, unlike std's hasher
+pub fn iaid_from_interface(interface: &str) -> u32 {
+    interface.bytes().fold(0x811c_9dc5, |hash, x| {
+        (hash ^ x as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+// An option 61 made of an IAID and a DUID, see RFC 4361 section 6.1
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ClientIdentifier {
+    iaid: u32,
+    duid: Duid,
+}
+
+impl ClientIdentifier {
+    pub fn new(iaid: u32, duid: Duid) -> Self {
+        ClientIdentifier { iaid, duid }
+    }
+
+    pub fn get_iaid(&self) -> u32 {
+        self.iaid
+    }
+
+    pub fn get_duid(&self) -> &Duid {
+        &self.duid
+    }
+
+    pub fn get_option(&self) -> DhcpOptionValue {
+        DhcpOptionValue::ClientId(self.serialize())
+    }
+}
+
+impl Serialize for ClientIdentifier {
+    type Out = Vec<u8>;
+    fn serialize(&self) -> Self::Out {
+        let mut buffer = vec![CLIENT_ID_DUID];
+        buffer.extend_from_slice(&self.iaid.to_be_bytes());
+        buffer.extend(self.duid.serialize());
+        buffer
+    }
+}
+
+impl Deserialize for ClientIdentifier {
+    type Out = Option<Self>;
+    type Error = DhcpParseError;
+    // Identifiers of any other type, such as a plain hardware address, are None
+    fn deserialize(data: &[u8]) -> Result<Self::Out, Self::Error> {
+        match data {
+            // The type, four bytes of IAID and at least the DUID type
+            [CLIENT_ID_DUID, ..] if data.len() >= 7 => {
+                let iaid = u32::from_be_bytes(data[1..5].try_into().unwrap());
+                Ok(Some(ClientIdentifier::new(
+                    iaid,
+                    Duid::deserialize(&data[5..])?,
+                )))
+            }
+            [CLIENT_ID_DUID, ..] => Err(DhcpParseError::TruncatedHeader {
+                offset: data.len(),
+                expected: 7,
+            }),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod client_identifier {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    fn test_mac() -> MacAddress {
+        MacAddress::new([0x10, 0x7b, 0x44, 0x93, 0xe6, 0xd0])
+    }
+
+    #[test]
+    fn test_duid_encoding() {
+        let created = UNIX_EPOCH + Duration::from_secs(DUID_EPOCH + 0x1234_5678);
+        assert_eq!(
+            Duid::from_mac_time(&test_mac(), created).serialize(),
+            vec![0, 1, 0, 1, 0x12, 0x34, 0x56, 0x78, 0x10, 0x7b, 0x44, 0x93, 0xe6, 0xd0]
+        );
+        assert_eq!(
+            Duid::from_mac(&test_mac()).serialize(),
+            vec![0, 3, 0, 1, 0x10, 0x7b, 0x44, 0x93, 0xe6, 0xd0]
+        );
+        let enterprise = Duid::from_enterprise(43793, &[0xaa, 0xbb]);
+        assert_eq!(
+            enterprise.serialize(),
+            vec![0, 2, 0, 0, 0xab, 0x11, 0xaa, 0xbb]
+        );
+        let uuid = Duid::Uuid([7; 16]);
+        assert_eq!(uuid.serialize().len(), 18);
+
+        for duid in [
+            Duid::from_mac_time(&test_mac(), created),
+            Duid::from_mac(&test_mac()),
+            enterprise,
+            uuid,
+            Duid::Unknown(9, vec![1, 2, 3]),
+        ] {
+            assert_eq!(Duid::deserialize(&duid.serialize()), Ok(duid.clone()));
+            assert_eq!(duid.to_string().parse(), Ok(duid));
+        }
+        assert_eq!(
+            Duid::deserialize(&[0, 4, 1, 2]),
+            Ok(Duid::Unknown(4, vec![1, 2]))
+        );
+        assert!("00:03:0".parse::<Duid>().is_err());
+
+        let longest = Duid::from_enterprise(43793, &[0; MAX_DUID_LENGTH - 4]).serialize();
+        assert!(Duid::deserialize(&longest).is_ok());
+        assert_eq!(
+            Duid::deserialize(&[&longest[..], &[0]].concat()),
+            Err(DhcpParseError::OversizedDuid {
+                offset: 130,
+                length: 129
+            })
+        );
+    }
+
+    #[test]
+    fn test_machine_id_uuid() {
+        let machine_id = [0x5a; 16];
+        let uuid = match Duid::from_machine_id(&machine_id) {
+            Duid::Uuid(uuid) => uuid,
+            duid => panic!("Expected a DUID-UUID, got {:?}", duid),
+        };
+        assert_ne!(uuid, machine_id);
+        assert_eq!(uuid[6] >> 4, 4);
+        assert_eq!(uuid[8] >> 6, 2);
+        assert_eq!(
+            Duid::from_machine_id(&machine_id),
+            Duid::from_machine_id(&machine_id)
+        );
+        assert_ne!(
+            Duid::from_machine_id(&machine_id),
+            Duid::from_machine_id(&[0xa5; 16])
+        );
+    }
+
+    #[test]
+    fn test_client_identifier() {
+        let id = ClientIdentifier::new(0x0102_0304, Duid::from_mac(&test_mac()));
+
+        assert_eq!(
+            id.get_option(),
+            DhcpOptionValue::ClientId(vec![
+                0xff, 1, 2, 3, 4, 0, 3, 0, 1, 0x10, 0x7b, 0x44, 0x93, 0xe6, 0xd0
+            ])
+        );
+        assert_eq!(ClientIdentifier::deserialize(&id.serialize()), Ok(Some(id)));
+        assert_eq!(ClientIdentifier::deserialize(&[1, 0x10, 0x7b]), Ok(None));
+        assert!(ClientIdentifier::deserialize(&[0xff, 1, 2]).is_err());
+        assert_eq!(iaid_from_interface("eth0"), iaid_from_interface("eth0"));
+        assert_ne!(iaid_from_interface("eth0"), iaid_from_interface("eth1"));
+    }
+
+    #[test]
+    fn test_persisted_duid() {
+        let dir = std::env::temp_dir().join(format!("dchp_client-{}-duid", std::process::id()));
+        let path = dir.join("duid");
+        let _ = fs::remove_dir_all(&dir);
+
+        let created = Duid::load_or_create(&path, || Ok(Duid::from_mac(&test_mac()))).unwrap();
+        let loaded = Duid::load_or_create(&path, || Ok(Duid::Uuid([0; 16]))).unwrap();
+
+        assert_eq!(created, loaded);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "00:03:00:01:10:7b:44:93:e6:d0\n"
+        );
+
+        // An oversized DUID is neither saved nor loaded
+        let oversized = Duid::from_enterprise(43793, &[0; MAX_DUID_LENGTH]);
+        let other = dir.join("oversized");
+        assert!(Duid::load_or_create(&other, || Ok(oversized.clone())).is_err());
+        assert!(!other.exists());
+        fs::write(&other, format!("{}\n", oversized)).unwrap();
+        assert!(Duid::load_or_create(&other, || Ok(Duid::from_mac(&test_mac()))).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,157 @@
+use crate::dhcp::{packet::DhcpOptionType, requirements::ClientMessage};
+use num_traits::FromPrimitive;
+use std::{error::Error, fmt};
+
+// Offsets are counted in bytes from the start of the datagram
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum DhcpParseError {
+    TruncatedHeader {
+        offset: usize,
+        expected: usize,
+    },
+    BadMagicCookie {
+        offset: usize,
+        cookie: [u8; 4],
+    },
+    MissingOptionLength {
+        offset: usize,
+        id: u8,
+    },
+    OptionOverrun {
+        offset: usize,
+        id: u8,
+        length: usize,
+    },
+    MissingEnd {
+        offset: usize,
+    },
+    UnsupportedAddressLength {
+        offset: usize,
+        length: u8,
+    },
+    OversizedDuid {
+        offset: usize,
+        length: usize,
+    },
+}
+
+impl DhcpParseError {
+    pub fn get_offset(&self) -> usize {
+        match self {
+            DhcpParseError::TruncatedHeader { offset, .. }
+            | DhcpParseError::BadMagicCookie { offset, .. }
+            | DhcpParseError::MissingOptionLength { offset, .. }
+            | DhcpParseError::OptionOverrun { offset, .. }
+            | DhcpParseError::MissingEnd { offset }
+            | DhcpParseError::UnsupportedAddressLength { offset, .. }
+            | DhcpParseError::OversizedDuid { offset, .. } => *offset,
+        }
+    }
+}
+
+impl fmt::Display for DhcpParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DhcpParseError::TruncatedHeader { offset, expected } => write!(
+                f,
+                "header truncated at byte {}, expected at least {} bytes",
+                offset, expected
+            ),
+            DhcpParseError::BadMagicCookie { offset, cookie } => {
+                write!(f, "bad magic cookie {:02x?} at byte {}", cookie, offset)
+            }
+            DhcpParseError::MissingOptionLength { offset, id } => {
+                write!(f, "option {} at byte {} has no length byte", id, offset)
+            }
+            DhcpParseError::OptionOverrun { offset, id, length } => write!(
+                f,
+                "option {} at byte {} claims {} bytes, overrunning the packet",
+                id, offset, length
+            ),
+            DhcpParseError::MissingEnd { offset } => {
+                write!(f, "options end at byte {} without an End option", offset)
+            }
+            DhcpParseError::UnsupportedAddressLength { offset, length } => write!(
+                f,
+                "unsupported address length {} at byte {}",
+                length, offset
+            ),
+            DhcpParseError::OversizedDuid { offset, length } => write!(
+                f,
+                "DUID of {} bytes runs past the 128 byte limit at byte {}",
+                length, offset
+            ),
+        }
+    }
+}
+
+impl Error for DhcpParseError {}
+
+// A client message that breaks the option rules of RFC 2131 table 5
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum DhcpMessageError {
+    WrongType { message: ClientMessage },
+    MissingOption { message: ClientMessage, id: u8 },
+    UnexpectedOption { message: ClientMessage, id: u8 },
+}
+
+impl DhcpMessageError {
+    pub fn get_message(&self) -> ClientMessage {
+        match self {
+            DhcpMessageError::WrongType { message }
+            | DhcpMessageError::MissingOption { message, .. }
+            | DhcpMessageError::UnexpectedOption { message, .. } => *message,
+        }
+    }
+}
+
+impl fmt::Display for DhcpMessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let option_name = |id: &u8| match <DhcpOptionType as FromPrimitive>::from_u8(*id) {
+            Some(option) => option.to_string(),
+            None => format!("option {}", id),
+        };
+        match self {
+            DhcpMessageError::WrongType { message } => {
+                write!(f, "packet is not a {}", message)
+            }
+            DhcpMessageError::MissingOption { message, id } => {
+                write!(f, "{} is missing {}", message, option_name(id))
+            }
+            DhcpMessageError::UnexpectedOption { message, id } => {
+                write!(f, "{} must not carry {}", message, option_name(id))
+            }
+        }
+    }
+}
+
+impl Error for DhcpMessageError {}
+
+// A host or domain name outside the limits of RFC 1035 section 2.3.4
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum DhcpNameError {
+    Empty,
+    EmptyLabel { name: String },
+    LabelTooLong { label: String },
+    NameTooLong { length: usize },
+}
+
+impl fmt::Display for DhcpNameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DhcpNameError::Empty => write!(f, "name is empty"),
+            DhcpNameError::EmptyLabel { name } => write!(f, "{} has an empty label", name),
+            DhcpNameError::LabelTooLong { label } => write!(
+                f,
+                "label {} is {} bytes long, at most 63 are allowed",
+                label,
+                label.len()
+            ),
+            DhcpNameError::NameTooLong { length } => {
+                write!(f, "name is {} bytes long, at most 253 are allowed", length)
+            }
+        }
+    }
+}
+
+impl Error for DhcpNameError {}
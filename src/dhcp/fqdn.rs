@@ -0,0 +1,238 @@
+use crate::dhcp::{
+    error::DhcpNameError,
+    options::{check_domain_name, decode_domain_name, encode_domain_name},
+};
+
+// Flag bits of the Client FQDN option, see RFC 4702 section 2.1
+const FLAG_S: u8 = 0x01;
+const FLAG_O: u8 = 0x02;
+const FLAG_E: u8 = 0x04;
+const FLAG_N: u8 = 0x08;
+// Clients send 0 in both RCODE fields, servers answer with 255
+const CLIENT_RCODE: u8 = 0;
+
+// The name a client wants in DNS and who should update the records for it.
+// Names ending in a dot are fully qualified, anything else is completed by the server
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ClientFqdn {
+    flags: u8,
+    rcode1: u8,
+    rcode2: u8,
+    name: String,
+    fully_qualified: bool,
+}
+
+impl ClientFqdn {
+    // Asks the server to update both the A and PTR records, in the canonical wire format
+    pub fn new(name: &str) -> Result<Self, DhcpNameError> {
+        check_domain_name(name)?;
+        Ok(ClientFqdn {
+            flags: FLAG_S | FLAG_E,
+            rcode1: CLIENT_RCODE,
+            rcode2: CLIENT_RCODE,
+            name: name.trim_end_matches('.').to_string(),
+            fully_qualified: name.ends_with('.'),
+        })
+    }
+
+    // Off leaves the A record to the client, the server still updates the PTR record
+    pub fn with_server_updates(mut self, enabled: bool) -> Self {
+        self.set_flag(FLAG_S, enabled);
+        if enabled {
+            self.set_flag(FLAG_N, false);
+        }
+        self
+    }
+
+    // Asks the server to leave DNS alone altogether
+    pub fn with_no_updates(mut self, enabled: bool) -> Self {
+        self.set_flag(FLAG_N, enabled);
+        if enabled {
+            self.set_flag(FLAG_S, false);
+        }
+        self
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_flags(&self) -> u8 {
+        self.flags
+    }
+
+    pub fn get_rcodes(&self) -> (u8, u8) {
+        (self.rcode1, self.rcode2)
+    }
+
+    pub fn is_fully_qualified(&self) -> bool {
+        self.fully_qualified
+    }
+
+    pub fn is_server_updates(&self) -> bool {
+        self.flags & FLAG_S != 0
+    }
+
+    // Set by a server that went against what the client asked for
+    pub fn is_override(&self) -> bool {
+        self.flags & FLAG_O != 0
+    }
+
+    pub fn is_encoded(&self) -> bool {
+        self.flags & FLAG_E != 0
+    }
+
+    pub fn is_no_updates(&self) -> bool {
+        self.flags & FLAG_N != 0
+    }
+
+    // In a server's reply, whether it took care of the A record, see RFC 4702 section 3.1
+    pub fn is_server_updating_a(&self) -> bool {
+        self.is_server_updates() && !self.is_no_updates()
+    }
+
+    // The PTR record is the server's unless it does no updates at all
+    pub fn is_server_updating_ptr(&self) -> bool {
+        !self.is_no_updates()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer = vec![self.flags, self.rcode1, self.rcode2];
+        if self.is_encoded() {
+            let mut name = encode_domain_name(&self.name);
+            // A partial name leaves out the root label
+            if !self.fully_qualified {
+                name.pop();
+            }
+            buffer.extend(name);
+        } else {
+            buffer.extend_from_slice(self.name.as_bytes());
+            if self.fully_qualified {
+                buffer.push(b'.');
+            }
+        }
+        buffer
+    }
+
+    pub fn decode(body: &[u8]) -> Option<Self> {
+        let (flags, rcode1, rcode2, name) = match body {
+            [flags, rcode1, rcode2, name @ ..] => (*flags, *rcode1, *rcode2, name),
+            _ => return None,
+        };
+        let (name, fully_qualified) = if name.is_empty() {
+            (String::new(), false)
+        } else if flags & FLAG_E != 0 {
+            match decode_domain_name(name, 0) {
+                Some((decoded, next)) if next == name.len() => (decoded, true),
+                _ => {
+                    let mut terminated = name.to_vec();
+                    terminated.push(0);
+                    match decode_domain_name(&terminated, 0) {
+                        Some((decoded, next)) if next == terminated.len() => (decoded, false),
+                        _ => return None,
+                    }
+                }
+            }
+        } else {
+            // The deprecated ASCII form
+            let text = String::from_utf8_lossy(name);
+            (text.trim_end_matches('.').to_string(), text.ends_with('.'))
+        };
+        Some(ClientFqdn {
+            flags,
+            rcode1,
+            rcode2,
+            name,
+            fully_qualified,
+        })
+    }
+
+    fn set_flag(&mut self, flag: u8, enabled: bool) {
+        if enabled {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+    }
+}
+
+#[cfg(test)]
+mod client_fqdn {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(
+            ClientFqdn::new("host.example.org.").unwrap().encode(),
+            b"\x05\x00\x00\x04host\x07example\x03org\x00".to_vec()
+        );
+        assert_eq!(
+            ClientFqdn::new("host")
+                .unwrap()
+                .with_no_updates(true)
+                .encode(),
+            b"\x0c\x00\x00\x04host".to_vec()
+        );
+        assert_eq!(
+            ClientFqdn::new("host")
+                .unwrap()
+                .with_no_updates(true)
+                .with_server_updates(true)
+                .get_flags(),
+            FLAG_S | FLAG_E
+        );
+    }
+
+    #[test]
+    fn test_decode_reply() {
+        let reply = ClientFqdn::decode(b"\x07\xff\xff\x04host\x07example\x03org\x00").unwrap();
+        assert_eq!(reply.get_name(), "host.example.org");
+        assert!(reply.is_fully_qualified());
+        assert!(reply.is_override());
+        assert_eq!(reply.get_rcodes(), (255, 255));
+        assert!(reply.is_server_updating_a());
+        assert!(reply.is_server_updating_ptr());
+
+        let reply = ClientFqdn::decode(b"\x04\xff\xff\x04host").unwrap();
+        assert_eq!(reply.get_name(), "host");
+        assert!(!reply.is_fully_qualified());
+        assert!(!reply.is_server_updating_a());
+        assert!(reply.is_server_updating_ptr());
+
+        let reply = ClientFqdn::decode(b"\x08\xff\xffhost.example.org.").unwrap();
+        assert!(!reply.is_encoded());
+        assert_eq!(reply.get_name(), "host.example.org");
+        assert!(reply.is_fully_qualified());
+        assert!(!reply.is_server_updating_ptr());
+
+        assert_eq!(ClientFqdn::decode(b"\x05\x00"), None);
+        assert_eq!(ClientFqdn::decode(b"\x05\x00\x00\x09host"), None);
+    }
+
+    #[test]
+    fn test_name_limits() {
+        let label = "a".repeat(63);
+        assert!(ClientFqdn::new(&format!("{}.example.org.", label)).is_ok());
+        assert_eq!(
+            ClientFqdn::new(&format!("{}a.example.org", label)),
+            Err(DhcpNameError::LabelTooLong {
+                label: format!("{}a", label)
+            })
+        );
+        // Four labels of 63 bytes and their length bytes leave no room for the root label
+        let name = [label.as_str(); 4].join(".");
+        assert_eq!(
+            ClientFqdn::new(&name),
+            Err(DhcpNameError::NameTooLong { length: 255 })
+        );
+        assert!(ClientFqdn::new(&name[2..]).is_ok());
+        assert_eq!(ClientFqdn::new("."), Err(DhcpNameError::Empty));
+        assert_eq!(
+            ClientFqdn::new("host..example.org"),
+            Err(DhcpNameError::EmptyLabel {
+                name: "host..example.org".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,342 @@
+use crate::dhcp::{
+    options::DhcpOptionValue,
+    packet::{DhcpOptionType, DhcpPacket},
+};
+use std::{
+    env, fmt, io,
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+};
+
+// The transitions dhclient-script is told about through $reason
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum HookReason {
+    Bound,
+    Renew,
+    Rebind,
+    Reboot,
+    Expire,
+    Fail,
+    Release,
+}
+
+impl HookReason {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            HookReason::Bound => "BOUND",
+            HookReason::Renew => "RENEW",
+            HookReason::Rebind => "REBIND",
+            HookReason::Reboot => "REBOOT",
+            HookReason::Expire => "EXPIRE",
+            HookReason::Fail => "FAIL",
+            HookReason::Release => "RELEASE",
+        }
+    }
+}
+
+impl fmt::Display for HookReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.get_name())
+    }
+}
+
+// One transition and the environment the script sees for it
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct HookEvent {
+    reason: HookReason,
+    environment: Vec<(String, String)>,
+}
+
+impl HookEvent {
+    pub fn new(reason: HookReason, interface: &str) -> Self {
+        HookEvent {
+            reason,
+            environment: vec![
+                ("reason".to_string(), reason.to_string()),
+                ("interface".to_string(), interface.to_string()),
+            ],
+        }
+    }
+
+    // The lease being moved to, as the new_* variables
+    pub fn with_new_lease(mut self, address: &Ipv4Addr, packet: &DhcpPacket) -> Self {
+        self.environment
+            .extend(lease_variables("new_", address, packet));
+        self
+    }
+
+    // The lease being left behind, as the old_* variables
+    pub fn with_old_lease(mut self, address: &Ipv4Addr, packet: &DhcpPacket) -> Self {
+        self.environment
+            .extend(lease_variables("old_", address, packet));
+        self
+    }
+
+    pub fn get_reason(&self) -> HookReason {
+        self.reason
+    }
+
+    pub fn get_environment(&self) -> &[(String, String)] {
+        &self.environment
+    }
+
+    pub fn get_variable(&self, name: &str) -> Option<&str> {
+        self.environment
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// Runs a dhclient-script compatible script for each HookEvent
+pub struct HookRunner {
+    script: PathBuf,
+}
+
+impl HookRunner {
+    pub fn new<P: AsRef<Path>>(script: P) -> Self {
+        HookRunner {
+            script: script.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn get_script(&self) -> &Path {
+        &self.script
+    }
+
+    // Waits for the script to finish. Only PATH is passed through from our own environment
+    pub fn run(&self, event: &HookEvent) -> io::Result<ExitStatus> {
+        let mut command = Command::new(&self.script);
+        command.env_clear();
+        if let Some(path) = env::var_os("PATH") {
+            command.env("PATH", path);
+        }
+        command
+            .envs(event.environment.iter().map(|(key, value)| (key, value)))
+            .status()
+    }
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Variable names follow dhcp-options(5) with dashes turned into underscores
+fn lease_variables(prefix: &str, address: &Ipv4Addr, packet: &DhcpPacket) -> Vec<(String, String)> {
+    let mut variables = vec![("ip_address", address.to_string())];
+
+    if let Some(mask) = packet.get_subnet() {
+        let network = u32::from(*address) & u32::from(mask);
+        variables.push(("subnet_mask", mask.to_string()));
+        variables.push(("network_number", Ipv4Addr::from(network).to_string()));
+        // Derived like dhclient does when the server leaves it out
+        let broadcast = packet
+            .get_broadcast_address()
+            .unwrap_or_else(|| Ipv4Addr::from(network | !u32::from(mask)));
+        variables.push(("broadcast_address", broadcast.to_string()));
+    } else if let Some(broadcast) = packet.get_broadcast_address() {
+        variables.push(("broadcast_address", broadcast.to_string()));
+    }
+
+    if let Some(DhcpOptionValue::RouterIp(routers)) = packet.get_option(DhcpOptionType::RouterIp) {
+        variables.push(("routers", join(&routers)));
+    }
+    let static_routes: Vec<String> = packet
+        .get_static_routes()
+        .iter()
+        .map(|(destination, router)| format!("{} {}", destination, router))
+        .collect();
+    let lists = [
+        ("static_routes", static_routes),
+        ("domain_name_servers", to_strings(packet.get_dns_servers())),
+        ("ntp_servers", to_strings(packet.get_ntp_servers())),
+        ("time_servers", to_strings(packet.get_time_servers())),
+        ("log_servers", to_strings(packet.get_log_servers())),
+        ("nis_servers", to_strings(packet.get_nis_servers())),
+        (
+            "netbios_name_servers",
+            to_strings(packet.get_netbios_name_servers()),
+        ),
+        ("smtp_server", to_strings(packet.get_smtp_servers())),
+        ("domain_search", packet.get_domain_search()),
+    ];
+    for (name, values) in lists.iter() {
+        if !values.is_empty() {
+            variables.push((*name, join(values)));
+        }
+    }
+
+    let values = [
+        ("domain_name", packet.get_domain_name()),
+        ("host_name", packet.get_host_name()),
+        ("root_path", packet.get_root_path()),
+        ("nis_domain", packet.get_nis_domain()),
+        ("netbios_scope", packet.get_netbios_scope()),
+        ("tftp_server_name", packet.get_tftp_server_name()),
+        ("bootfile_name", packet.get_boot_file_name()),
+        (
+            "time_offset",
+            packet.get_time_offset().map(|x| x.to_string()),
+        ),
+        (
+            "default_ip_ttl",
+            packet.get_default_ip_ttl().map(|x| x.to_string()),
+        ),
+        (
+            "interface_mtu",
+            packet.get_interface_mtu().map(|x| x.to_string()),
+        ),
+        (
+            "netbios_node_type",
+            packet.get_netbios_node_type().map(|x| x.to_string()),
+        ),
+        (
+            "dhcp_lease_time",
+            packet.get_lease_time().map(|x| x.as_secs().to_string()),
+        ),
+        (
+            "dhcp_renewal_time",
+            packet.get_renewal_time().map(|x| x.as_secs().to_string()),
+        ),
+        (
+            "dhcp_rebinding_time",
+            packet.get_rebinding_time().map(|x| x.as_secs().to_string()),
+        ),
+        (
+            "dhcp_server_identifier",
+            packet.get_server_ip().map(|x| x.to_string()),
+        ),
+        (
+            "dhcp_message_type",
+            packet.get_type().map(|x| (x as u8).to_string()),
+        ),
+    ];
+    for (name, value) in values.iter() {
+        if let Some(value) = value {
+            variables.push((*name, value.clone()));
+        }
+    }
+
+    variables
+        .into_iter()
+        .map(|(name, value)| (format!("{}{}", prefix, name), value))
+        .collect()
+}
+
+fn to_strings(ips: Vec<Ipv4Addr>) -> Vec<String> {
+    ips.iter().map(|x| x.to_string()).collect()
+}
+
+#[cfg(test)]
+mod hook_runner {
+    use super::*;
+    use crate::dhcp::packet::DhcpMessageType;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    fn test_ack() -> DhcpPacket {
+        DhcpPacket::new()
+            .with_option(DhcpOptionValue::MessageType(DhcpMessageType::Ack))
+            .with_option(DhcpOptionValue::SubnetMask(Ipv4Addr::new(255, 255, 255, 0)))
+            .with_option(DhcpOptionValue::RouterIp(vec![
+                Ipv4Addr::new(192, 168, 1, 1),
+                Ipv4Addr::new(192, 168, 1, 2),
+            ]))
+            .with_option(DhcpOptionValue::DnsServerIp(vec![
+                Ipv4Addr::new(1, 1, 1, 1),
+                Ipv4Addr::new(8, 8, 8, 8),
+            ]))
+            .with_option(DhcpOptionValue::DomainName("example.org".to_string()))
+            .with_option(DhcpOptionValue::LeaseTime(Duration::from_secs(3600)))
+            .with_option(DhcpOptionValue::DhcpServerIp(Ipv4Addr::new(192, 168, 1, 1)))
+    }
+
+    #[test]
+    fn test_environment() {
+        let event = HookEvent::new(HookReason::Bound, "eth0")
+            .with_new_lease(&Ipv4Addr::new(192, 168, 1, 99), &test_ack());
+
+        assert_eq!(event.get_variable("reason"), Some("BOUND"));
+        assert_eq!(event.get_variable("interface"), Some("eth0"));
+        assert_eq!(event.get_variable("new_ip_address"), Some("192.168.1.99"));
+        assert_eq!(event.get_variable("new_subnet_mask"), Some("255.255.255.0"));
+        assert_eq!(
+            event.get_variable("new_network_number"),
+            Some("192.168.1.0")
+        );
+        assert_eq!(
+            event.get_variable("new_broadcast_address"),
+            Some("192.168.1.255")
+        );
+        assert_eq!(
+            event.get_variable("new_routers"),
+            Some("192.168.1.1 192.168.1.2")
+        );
+        assert_eq!(
+            event.get_variable("new_domain_name_servers"),
+            Some("1.1.1.1 8.8.8.8")
+        );
+        assert_eq!(event.get_variable("new_domain_name"), Some("example.org"));
+        assert_eq!(event.get_variable("new_dhcp_lease_time"), Some("3600"));
+        assert_eq!(
+            event.get_variable("new_dhcp_server_identifier"),
+            Some("192.168.1.1")
+        );
+        assert_eq!(event.get_variable("new_dhcp_message_type"), Some("5"));
+        assert_eq!(event.get_variable("new_host_name"), None);
+        assert_eq!(event.get_variable("old_ip_address"), None);
+    }
+
+    #[test]
+    fn test_old_lease() {
+        let event = HookEvent::new(HookReason::Expire, "eth0")
+            .with_old_lease(&Ipv4Addr::new(192, 168, 1, 99), &DhcpPacket::new());
+
+        assert_eq!(event.get_reason(), HookReason::Expire);
+        assert_eq!(
+            event.get_environment(),
+            &[
+                ("reason".to_string(), "EXPIRE".to_string()),
+                ("interface".to_string(), "eth0".to_string()),
+                ("old_ip_address".to_string(), "192.168.1.99".to_string()),
+            ][..]
+        );
+    }
+
+    // Needs a shell and the execute bit
+    #[cfg(unix)]
+    #[test]
+    fn test_run_script() {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let dir = env::temp_dir().join(format!("dchp_client-{}-hooks", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("hook");
+        let output = dir.join("output");
+        fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\necho \"$reason $interface $new_ip_address $new_routers\" > {}\n",
+                output.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let event = HookEvent::new(HookReason::Renew, "eth0")
+            .with_new_lease(&Ipv4Addr::new(192, 168, 1, 99), &test_ack());
+
+        let status = HookRunner::new(&script).run(&event).unwrap();
+
+        assert!(status.success());
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "RENEW eth0 192.168.1.99 192.168.1.1 192.168.1.2\n"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,5 +1,63 @@
+pub mod arp;
+pub use arp::{ArpOperation, ArpPacket};
+
+#[cfg(feature = "tokio")]
+pub mod async_client;
+#[cfg(feature = "tokio")]
+pub use async_client::{
+    AsyncDhcpClient, AsyncDhcpTransport, AsyncMemoryTransport, AsyncUdpTransport,
+};
+
+pub mod client;
+pub use client::{release_packet, ClientAction, ClientEvent, ClientState, DhcpClient, Lease};
+
+pub mod duid;
+pub use duid::{iaid_from_interface, ClientIdentifier, Duid};
+
+pub mod error;
+pub use error::{DhcpMessageError, DhcpNameError, DhcpParseError};
+
+pub mod fqdn;
+pub use fqdn::ClientFqdn;
+
+pub mod hooks;
+pub use hooks::{HookEvent, HookReason, HookRunner};
+
+pub mod netconf;
+pub use netconf::InterfaceConfig;
+#[cfg(all(target_os = "linux", feature = "netlink"))]
+pub use netconf::Netlink;
+
+pub mod options;
+pub use options::DhcpOptionValue;
+
 pub mod packet;
 pub use packet::{DhcpMessageType, DhcpOption, DhcpOptionType, DhcpPacket, TransactionToken};
 
+pub mod raw;
+#[cfg(target_os = "linux")]
+pub use raw::RawTransport;
+
+pub mod requirements;
+pub use requirements::{ClientMessage, OptionRequirement};
+
+pub mod resolv;
+pub use resolv::{ResolvConf, ResolvConfWriter};
+
+pub mod selector;
+pub use selector::{FirstOffer, LongestLease, OfferSelector, PreferAddress, PreferServer};
+
+pub mod store;
+pub use store::{LeaseStore, StoredLease};
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+pub mod transport;
+pub use transport::{DhcpTransport, MemoryTransport, UdpTransport};
+
 pub mod traits;
 pub use traits::{Deserialize, Serialize};
+
+pub mod vendor;
+pub use vendor::{VendorDecoder, VendorFormat, VendorRegistry, VendorSchema, VendorValue};
@@ -0,0 +1,315 @@
+use crate::dhcp::{client::Lease, packet::DhcpPacket};
+use std::net::Ipv4Addr;
+
+#[cfg(all(target_os = "linux", feature = "netlink"))]
+pub use linux::Netlink;
+
+// The address, default route and MTU a lease asks for on one interface
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct InterfaceConfig {
+    interface: String,
+    address: Ipv4Addr,
+    prefix_len: u8,
+    router: Option<Ipv4Addr>,
+    mtu: Option<u16>,
+    // What the link had before the lease's MTU was set, so it can be put back
+    original_mtu: Option<u16>,
+}
+
+impl InterfaceConfig {
+    // `ack` supplies the options, `address` is the one that was assigned
+    pub fn new(interface: &str, address: &Ipv4Addr, ack: &DhcpPacket) -> Self {
+        let prefix_len = match ack.get_subnet() {
+            Some(mask) => prefix_len(&mask),
+            None => classful_prefix_len(address),
+        };
+        InterfaceConfig {
+            interface: interface.to_string(),
+            address: *address,
+            prefix_len,
+            router: ack.get_router_ip(),
+            mtu: ack.get_interface_mtu(),
+            original_mtu: None,
+        }
+    }
+
+    // Carried over from an earlier apply, as found in the lease store
+    pub fn with_original_mtu(mut self, mtu: Option<u16>) -> Self {
+        self.original_mtu = mtu;
+        self
+    }
+
+    pub fn from_lease(interface: &str, lease: &Lease) -> Self {
+        Self::new(interface, &lease.get_client_ip(), lease.get_packet())
+    }
+
+    pub fn get_interface(&self) -> &str {
+        &self.interface
+    }
+
+    pub fn get_address(&self) -> Ipv4Addr {
+        self.address
+    }
+
+    pub fn get_prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    pub fn get_router(&self) -> Option<Ipv4Addr> {
+        self.router
+    }
+
+    pub fn get_mtu(&self) -> Option<u16> {
+        self.mtu
+    }
+
+    pub fn get_original_mtu(&self) -> Option<u16> {
+        self.original_mtu
+    }
+}
+
+// Counts the leading ones, a mask with holes in it has no meaningful prefix anyway
+fn prefix_len(mask: &Ipv4Addr) -> u8 {
+    u32::from(*mask).leading_ones() as u8
+}
+
+// Servers that leave out the subnet mask get the address class default
+fn classful_prefix_len(address: &Ipv4Addr) -> u8 {
+    match address.octets()[0] {
+        0..=127 => 8,
+        128..=191 => 16,
+        _ => 24,
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "netlink"))]
+mod linux {
+    use super::*;
+    use futures::TryStreamExt;
+    use netlink_packet_route::{link::nlas::Nla, LinkMessage};
+    use rtnetlink::{Handle, IpVersion};
+    use std::{convert::TryInto, io, net::IpAddr};
+    use tokio::runtime::{Builder, Runtime};
+
+    // Configures interfaces over rtnetlink, blocking on a runtime of its own
+    pub struct Netlink {
+        runtime: Runtime,
+        handle: Handle,
+    }
+
+    impl Netlink {
+        pub fn open() -> io::Result<Self> {
+            let runtime = Builder::new_current_thread().enable_io().build()?;
+            let handle = runtime.block_on(async {
+                let (connection, handle, _) = rtnetlink::new_connection()?;
+                tokio::spawn(connection);
+                Ok::<_, io::Error>(handle)
+            })?;
+            Ok(Netlink { runtime, handle })
+        }
+
+        // Safe to repeat, anything already in place is replaced. Returns the MTU the link had
+        // before the lease's was set, to be handed back through the config on removal
+        pub fn apply(&self, config: &InterfaceConfig) -> io::Result<Option<u16>> {
+            self.runtime.block_on(async {
+                let link = self.link(&config.interface).await?;
+                let index = link.header.index;
+                let (mtu, original_mtu) = match (config.mtu, config.original_mtu) {
+                    (Some(mtu), Some(original)) => (Some(mtu), Some(original)),
+                    (Some(mtu), None) => (Some(mtu), link_mtu(&link)),
+                    // The lease no longer sets one, so the link goes back to its own
+                    (None, original) => (original, None),
+                };
+                let mut request = self.handle.link().set(index).up();
+                if let Some(mtu) = mtu {
+                    request = request.mtu(mtu as u32);
+                }
+                request.execute().await.map_err(netlink_error)?;
+
+                self.handle
+                    .address()
+                    .add(index, IpAddr::V4(config.address), config.prefix_len)
+                    .replace()
+                    .execute()
+                    .await
+                    .map_err(netlink_error)?;
+
+                if let Some(router) = config.router {
+                    self.handle
+                        .route()
+                        .add()
+                        .v4()
+                        .output_interface(index)
+                        .gateway(router)
+                        .replace()
+                        .execute()
+                        .await
+                        .map_err(netlink_error)?;
+                }
+                Ok(original_mtu)
+            })
+        }
+
+        // Undoes apply, leaving the link up. Parts that are already gone are skipped
+        pub fn remove(&self, config: &InterfaceConfig) -> io::Result<()> {
+            self.runtime.block_on(async {
+                let index = self.link(&config.interface).await?.header.index;
+
+                if let Some(router) = config.router {
+                    let mut routes = self.handle.route().get(IpVersion::V4).execute();
+                    while let Some(route) = routes.try_next().await.map_err(netlink_error)? {
+                        let is_default = route.header.destination_prefix_length == 0;
+                        if is_default
+                            && route.output_interface() == Some(index)
+                            && route.gateway() == Some(IpAddr::V4(router))
+                        {
+                            self.handle
+                                .route()
+                                .del(route)
+                                .execute()
+                                .await
+                                .map_err(netlink_error)?;
+                        }
+                    }
+                }
+
+                let mut addresses = self
+                    .handle
+                    .address()
+                    .get()
+                    .set_link_index_filter(index)
+                    .set_address_filter(IpAddr::V4(config.address))
+                    .set_prefix_length_filter(config.prefix_len)
+                    .execute();
+                while let Some(address) = addresses.try_next().await.map_err(netlink_error)? {
+                    self.handle
+                        .address()
+                        .del(address)
+                        .execute()
+                        .await
+                        .map_err(netlink_error)?;
+                }
+
+                // Leases without an MTU never changed it
+                if let Some(original) = config.original_mtu {
+                    self.handle
+                        .link()
+                        .set(index)
+                        .mtu(original as u32)
+                        .execute()
+                        .await
+                        .map_err(netlink_error)?;
+                }
+                Ok(())
+            })
+        }
+
+        async fn link(&self, interface: &str) -> io::Result<LinkMessage> {
+            let mut links = self
+                .handle
+                .link()
+                .get()
+                .match_name(interface.to_string())
+                .execute();
+            match links.try_next().await {
+                Ok(Some(link)) => Ok(link),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no interface named {}", interface),
+                )),
+            }
+        }
+    }
+
+    fn link_mtu(link: &LinkMessage) -> Option<u16> {
+        link.nlas.iter().find_map(|x| match x {
+            Nla::Mtu(mtu) => (*mtu).try_into().ok(),
+            _ => None,
+        })
+    }
+
+    fn netlink_error(error: rtnetlink::Error) -> io::Error {
+        io::Error::other(error)
+    }
+}
+
+#[cfg(test)]
+mod interface_config {
+    use super::*;
+    use crate::dhcp::options::DhcpOptionValue;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_from_ack() {
+        let ack = DhcpPacket::new()
+            .with_option(DhcpOptionValue::SubnetMask(Ipv4Addr::new(255, 255, 252, 0)))
+            .with_option(DhcpOptionValue::RouterIp(vec![
+                Ipv4Addr::new(10, 0, 0, 1),
+                Ipv4Addr::new(10, 0, 0, 2),
+            ]))
+            .with_option(DhcpOptionValue::InterfaceMtu(9000));
+
+        let config = InterfaceConfig::new("eth0", &Ipv4Addr::new(10, 0, 1, 20), &ack);
+
+        assert_eq!(config.get_interface(), "eth0");
+        assert_eq!(config.get_address(), Ipv4Addr::new(10, 0, 1, 20));
+        assert_eq!(config.get_prefix_len(), 22);
+        assert_eq!(config.get_router(), Some(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(config.get_mtu(), Some(9000));
+    }
+
+    #[test]
+    fn test_classful_fallback() {
+        let ack = DhcpPacket::new();
+        let prefix = |a, b, c, d| {
+            InterfaceConfig::new("eth0", &Ipv4Addr::new(a, b, c, d), &ack).get_prefix_len()
+        };
+
+        assert_eq!(prefix(10, 1, 2, 3), 8);
+        assert_eq!(prefix(172, 16, 0, 5), 16);
+        assert_eq!(prefix(192, 168, 1, 5), 24);
+    }
+
+    #[cfg(all(target_os = "linux", feature = "netlink"))]
+    #[test]
+    #[ignore]
+    fn test_apply_in_namespace() {
+        use std::process::Command;
+
+        // Netns membership is per thread, so unsharing here keeps the host untouched
+        std::thread::spawn(|| {
+            assert_eq!(unsafe { libc::unshare(libc::CLONE_NEWNET) }, 0);
+            let ip = |args: &[&str]| {
+                let output = Command::new("ip").args(args).output().unwrap();
+                assert!(output.status.success(), "ip {:?} failed", args);
+                String::from_utf8(output.stdout).unwrap()
+            };
+            ip(&[
+                "link", "add", "dhcp0", "type", "veth", "peer", "name", "dhcp1",
+            ]);
+
+            let ack = DhcpPacket::new()
+                .with_option(DhcpOptionValue::SubnetMask(Ipv4Addr::new(255, 255, 255, 0)))
+                .with_option(DhcpOptionValue::RouterIp(vec![Ipv4Addr::new(192, 0, 2, 1)]))
+                .with_option(DhcpOptionValue::InterfaceMtu(1400));
+            let config = InterfaceConfig::new("dhcp0", &Ipv4Addr::new(192, 0, 2, 50), &ack);
+            let netlink = Netlink::open().unwrap();
+
+            ip(&["link", "set", "dhcp0", "mtu", "9000"]);
+            let original_mtu = netlink.apply(&config).unwrap();
+            assert_eq!(original_mtu, Some(9000));
+            let config = config.with_original_mtu(original_mtu);
+            assert_eq!(netlink.apply(&config).unwrap(), Some(9000));
+            assert!(ip(&["addr", "show", "dhcp0"]).contains("inet 192.0.2.50/24"));
+            assert!(ip(&["link", "show", "dhcp0"]).contains("mtu 1400"));
+            assert!(ip(&["route", "show", "default"]).contains("via 192.0.2.1 dev dhcp0"));
+
+            netlink.remove(&config).unwrap();
+            assert!(!ip(&["addr", "show", "dhcp0"]).contains("192.0.2.50"));
+            assert!(ip(&["link", "show", "dhcp0"]).contains("mtu 9000"));
+            assert_eq!(ip(&["route", "show", "default"]), "");
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -0,0 +1,778 @@
+use crate::dhcp::{
+    error::DhcpNameError,
+    fqdn::ClientFqdn,
+    packet::{DhcpMessageType, DhcpOption, DhcpOptionType},
+};
+use num_traits::FromPrimitive;
+use std::{convert::TryInto, net::Ipv4Addr, time::Duration};
+
+// Domain names are limited to this many bytes of labels, see RFC 1035 section 2.3.4
+const MAX_NAME_LENGTH: usize = 255;
+const MAX_LABEL_LENGTH: usize = 63;
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum DhcpOptionValue {
+    Pad,
+    SubnetMask(Ipv4Addr),
+    TimeOffset(i32),
+    RouterIp(Vec<Ipv4Addr>),
+    TimeServers(Vec<Ipv4Addr>),
+    NameServers(Vec<Ipv4Addr>),
+    DnsServerIp(Vec<Ipv4Addr>),
+    LogServers(Vec<Ipv4Addr>),
+    CookieServers(Vec<Ipv4Addr>),
+    LprServers(Vec<Ipv4Addr>),
+    ImpressServers(Vec<Ipv4Addr>),
+    ResourceLocationServers(Vec<Ipv4Addr>),
+    HostName(String),
+    BootFileSize(u16),
+    MeritDumpFile(String),
+    DomainName(String),
+    SwapServer(Ipv4Addr),
+    RootPath(String),
+    ExtensionsPath(String),
+    IpForwarding(bool),
+    NonLocalSourceRouting(bool),
+    PolicyFilter(Vec<(Ipv4Addr, Ipv4Addr)>),
+    MaxDatagramReassemblySize(u16),
+    DefaultIpTtl(u8),
+    PathMtuAgingTimeout(Duration),
+    PathMtuPlateauTable(Vec<u16>),
+    InterfaceMtu(u16),
+    AllSubnetsLocal(bool),
+    BroadcastAddress(Ipv4Addr),
+    PerformMaskDiscovery(bool),
+    MaskSupplier(bool),
+    PerformRouterDiscovery(bool),
+    RouterSolicitationAddress(Ipv4Addr),
+    StaticRoutes(Vec<(Ipv4Addr, Ipv4Addr)>),
+    TrailerEncapsulation(bool),
+    ArpCacheTimeout(Duration),
+    EthernetEncapsulation(bool),
+    TcpDefaultTtl(u8),
+    TcpKeepaliveInterval(Duration),
+    TcpKeepaliveGarbage(bool),
+    NisDomain(String),
+    NisServers(Vec<Ipv4Addr>),
+    NtpServers(Vec<Ipv4Addr>),
+    VendorSpecific(Vec<u8>),
+    NetbiosNameServers(Vec<Ipv4Addr>),
+    NetbiosDatagramServers(Vec<Ipv4Addr>),
+    NetbiosNodeType(u8),
+    NetbiosScope(String),
+    XFontServers(Vec<Ipv4Addr>),
+    XDisplayManagers(Vec<Ipv4Addr>),
+    RequestedIp(Ipv4Addr),
+    LeaseTime(Duration),
+    OptionOverload(u8),
+    MessageType(DhcpMessageType),
+    DhcpServerIp(Ipv4Addr),
+    ParameterRequest(Vec<u8>),
+    Message(String),
+    MaxMessageSize(u16),
+    RenewalTime(Duration),
+    RebindingTime(Duration),
+    VendorClass(Vec<u8>),
+    ClientId(Vec<u8>),
+    NisPlusDomain(String),
+    NisPlusServers(Vec<Ipv4Addr>),
+    TftpServerName(String),
+    BootFileName(String),
+    MobileIpHomeAgents(Vec<Ipv4Addr>),
+    SmtpServers(Vec<Ipv4Addr>),
+    Pop3Servers(Vec<Ipv4Addr>),
+    NntpServers(Vec<Ipv4Addr>),
+    WwwServers(Vec<Ipv4Addr>),
+    FingerServers(Vec<Ipv4Addr>),
+    IrcServers(Vec<Ipv4Addr>),
+    StreetTalkServers(Vec<Ipv4Addr>),
+    StdaServers(Vec<Ipv4Addr>),
+    RapidCommit,
+    ClientFqdn(ClientFqdn),
+    DomainSearch(Vec<String>),
+    End,
+    Unknown(u8, Vec<u8>),
+}
+
+impl DhcpOptionValue {
+    pub fn get_id(&self) -> u8 {
+        match self {
+            DhcpOptionValue::Pad => DhcpOptionType::Pad as u8,
+            DhcpOptionValue::SubnetMask(_) => DhcpOptionType::SubnetMask as u8,
+            DhcpOptionValue::TimeOffset(_) => DhcpOptionType::TimeOffset as u8,
+            DhcpOptionValue::RouterIp(_) => DhcpOptionType::RouterIp as u8,
+            DhcpOptionValue::TimeServers(_) => DhcpOptionType::TimeServers as u8,
+            DhcpOptionValue::NameServers(_) => DhcpOptionType::NameServers as u8,
+            DhcpOptionValue::DnsServerIp(_) => DhcpOptionType::DnsServerIp as u8,
+            DhcpOptionValue::LogServers(_) => DhcpOptionType::LogServers as u8,
+            DhcpOptionValue::CookieServers(_) => DhcpOptionType::CookieServers as u8,
+            DhcpOptionValue::LprServers(_) => DhcpOptionType::LprServers as u8,
+            DhcpOptionValue::ImpressServers(_) => DhcpOptionType::ImpressServers as u8,
+            DhcpOptionValue::ResourceLocationServers(_) => {
+                DhcpOptionType::ResourceLocationServers as u8
+            }
+            DhcpOptionValue::HostName(_) => DhcpOptionType::HostName as u8,
+            DhcpOptionValue::BootFileSize(_) => DhcpOptionType::BootFileSize as u8,
+            DhcpOptionValue::MeritDumpFile(_) => DhcpOptionType::MeritDumpFile as u8,
+            DhcpOptionValue::DomainName(_) => DhcpOptionType::DomainName as u8,
+            DhcpOptionValue::SwapServer(_) => DhcpOptionType::SwapServer as u8,
+            DhcpOptionValue::RootPath(_) => DhcpOptionType::RootPath as u8,
+            DhcpOptionValue::ExtensionsPath(_) => DhcpOptionType::ExtensionsPath as u8,
+            DhcpOptionValue::IpForwarding(_) => DhcpOptionType::IpForwarding as u8,
+            DhcpOptionValue::NonLocalSourceRouting(_) => {
+                DhcpOptionType::NonLocalSourceRouting as u8
+            }
+            DhcpOptionValue::PolicyFilter(_) => DhcpOptionType::PolicyFilter as u8,
+            DhcpOptionValue::MaxDatagramReassemblySize(_) => {
+                DhcpOptionType::MaxDatagramReassemblySize as u8
+            }
+            DhcpOptionValue::DefaultIpTtl(_) => DhcpOptionType::DefaultIpTtl as u8,
+            DhcpOptionValue::PathMtuAgingTimeout(_) => DhcpOptionType::PathMtuAgingTimeout as u8,
+            DhcpOptionValue::PathMtuPlateauTable(_) => DhcpOptionType::PathMtuPlateauTable as u8,
+            DhcpOptionValue::InterfaceMtu(_) => DhcpOptionType::InterfaceMtu as u8,
+            DhcpOptionValue::AllSubnetsLocal(_) => DhcpOptionType::AllSubnetsLocal as u8,
+            DhcpOptionValue::BroadcastAddress(_) => DhcpOptionType::BroadcastAddress as u8,
+            DhcpOptionValue::PerformMaskDiscovery(_) => DhcpOptionType::PerformMaskDiscovery as u8,
+            DhcpOptionValue::MaskSupplier(_) => DhcpOptionType::MaskSupplier as u8,
+            DhcpOptionValue::PerformRouterDiscovery(_) => {
+                DhcpOptionType::PerformRouterDiscovery as u8
+            }
+            DhcpOptionValue::RouterSolicitationAddress(_) => {
+                DhcpOptionType::RouterSolicitationAddress as u8
+            }
+            DhcpOptionValue::StaticRoutes(_) => DhcpOptionType::StaticRoutes as u8,
+            DhcpOptionValue::TrailerEncapsulation(_) => DhcpOptionType::TrailerEncapsulation as u8,
+            DhcpOptionValue::ArpCacheTimeout(_) => DhcpOptionType::ArpCacheTimeout as u8,
+            DhcpOptionValue::EthernetEncapsulation(_) => {
+                DhcpOptionType::EthernetEncapsulation as u8
+            }
+            DhcpOptionValue::TcpDefaultTtl(_) => DhcpOptionType::TcpDefaultTtl as u8,
+            DhcpOptionValue::TcpKeepaliveInterval(_) => DhcpOptionType::TcpKeepaliveInterval as u8,
+            DhcpOptionValue::TcpKeepaliveGarbage(_) => DhcpOptionType::TcpKeepaliveGarbage as u8,
+            DhcpOptionValue::NisDomain(_) => DhcpOptionType::NisDomain as u8,
+            DhcpOptionValue::NisServers(_) => DhcpOptionType::NisServers as u8,
+            DhcpOptionValue::NtpServers(_) => DhcpOptionType::NtpServers as u8,
+            DhcpOptionValue::VendorSpecific(_) => DhcpOptionType::VendorSpecific as u8,
+            DhcpOptionValue::NetbiosNameServers(_) => DhcpOptionType::NetbiosNameServers as u8,
+            DhcpOptionValue::NetbiosDatagramServers(_) => {
+                DhcpOptionType::NetbiosDatagramServers as u8
+            }
+            DhcpOptionValue::NetbiosNodeType(_) => DhcpOptionType::NetbiosNodeType as u8,
+            DhcpOptionValue::NetbiosScope(_) => DhcpOptionType::NetbiosScope as u8,
+            DhcpOptionValue::XFontServers(_) => DhcpOptionType::XFontServers as u8,
+            DhcpOptionValue::XDisplayManagers(_) => DhcpOptionType::XDisplayManagers as u8,
+            DhcpOptionValue::RequestedIp(_) => DhcpOptionType::RequestedIp as u8,
+            DhcpOptionValue::LeaseTime(_) => DhcpOptionType::LeaseTime as u8,
+            DhcpOptionValue::OptionOverload(_) => DhcpOptionType::OptionOverload as u8,
+            DhcpOptionValue::MessageType(_) => DhcpOptionType::MessageType as u8,
+            DhcpOptionValue::DhcpServerIp(_) => DhcpOptionType::DhcpServerIp as u8,
+            DhcpOptionValue::ParameterRequest(_) => DhcpOptionType::ParameterRequest as u8,
+            DhcpOptionValue::Message(_) => DhcpOptionType::Message as u8,
+            DhcpOptionValue::MaxMessageSize(_) => DhcpOptionType::MaxMessageSize as u8,
+            DhcpOptionValue::RenewalTime(_) => DhcpOptionType::RenewalTime as u8,
+            DhcpOptionValue::RebindingTime(_) => DhcpOptionType::RebindingTime as u8,
+            DhcpOptionValue::VendorClass(_) => DhcpOptionType::VendorClass as u8,
+            DhcpOptionValue::ClientId(_) => DhcpOptionType::ClientId as u8,
+            DhcpOptionValue::NisPlusDomain(_) => DhcpOptionType::NisPlusDomain as u8,
+            DhcpOptionValue::NisPlusServers(_) => DhcpOptionType::NisPlusServers as u8,
+            DhcpOptionValue::TftpServerName(_) => DhcpOptionType::TftpServerName as u8,
+            DhcpOptionValue::BootFileName(_) => DhcpOptionType::BootFileName as u8,
+            DhcpOptionValue::MobileIpHomeAgents(_) => DhcpOptionType::MobileIpHomeAgents as u8,
+            DhcpOptionValue::SmtpServers(_) => DhcpOptionType::SmtpServers as u8,
+            DhcpOptionValue::Pop3Servers(_) => DhcpOptionType::Pop3Servers as u8,
+            DhcpOptionValue::NntpServers(_) => DhcpOptionType::NntpServers as u8,
+            DhcpOptionValue::WwwServers(_) => DhcpOptionType::WwwServers as u8,
+            DhcpOptionValue::FingerServers(_) => DhcpOptionType::FingerServers as u8,
+            DhcpOptionValue::IrcServers(_) => DhcpOptionType::IrcServers as u8,
+            DhcpOptionValue::StreetTalkServers(_) => DhcpOptionType::StreetTalkServers as u8,
+            DhcpOptionValue::StdaServers(_) => DhcpOptionType::StdaServers as u8,
+            DhcpOptionValue::RapidCommit => DhcpOptionType::RapidCommit as u8,
+            DhcpOptionValue::ClientFqdn(_) => DhcpOptionType::ClientFqdn as u8,
+            DhcpOptionValue::DomainSearch(_) => DhcpOptionType::DomainSearch as u8,
+            DhcpOptionValue::End => DhcpOptionType::End as u8,
+            DhcpOptionValue::Unknown(id, _) => *id,
+        }
+    }
+
+    pub fn encode(&self) -> DhcpOption {
+        let body = match self {
+            DhcpOptionValue::SubnetMask(ip)
+            | DhcpOptionValue::SwapServer(ip)
+            | DhcpOptionValue::BroadcastAddress(ip)
+            | DhcpOptionValue::RouterSolicitationAddress(ip)
+            | DhcpOptionValue::RequestedIp(ip)
+            | DhcpOptionValue::DhcpServerIp(ip) => ip.octets().to_vec(),
+            DhcpOptionValue::TimeOffset(value) => value.to_be_bytes().to_vec(),
+            DhcpOptionValue::RouterIp(ips)
+            | DhcpOptionValue::TimeServers(ips)
+            | DhcpOptionValue::NameServers(ips)
+            | DhcpOptionValue::DnsServerIp(ips)
+            | DhcpOptionValue::LogServers(ips)
+            | DhcpOptionValue::CookieServers(ips)
+            | DhcpOptionValue::LprServers(ips)
+            | DhcpOptionValue::ImpressServers(ips)
+            | DhcpOptionValue::ResourceLocationServers(ips)
+            | DhcpOptionValue::NisServers(ips)
+            | DhcpOptionValue::NtpServers(ips)
+            | DhcpOptionValue::NetbiosNameServers(ips)
+            | DhcpOptionValue::NetbiosDatagramServers(ips)
+            | DhcpOptionValue::XFontServers(ips)
+            | DhcpOptionValue::XDisplayManagers(ips)
+            | DhcpOptionValue::NisPlusServers(ips)
+            | DhcpOptionValue::MobileIpHomeAgents(ips)
+            | DhcpOptionValue::SmtpServers(ips)
+            | DhcpOptionValue::Pop3Servers(ips)
+            | DhcpOptionValue::NntpServers(ips)
+            | DhcpOptionValue::WwwServers(ips)
+            | DhcpOptionValue::FingerServers(ips)
+            | DhcpOptionValue::IrcServers(ips)
+            | DhcpOptionValue::StreetTalkServers(ips)
+            | DhcpOptionValue::StdaServers(ips) => encode_ip_list(ips),
+            DhcpOptionValue::HostName(text)
+            | DhcpOptionValue::MeritDumpFile(text)
+            | DhcpOptionValue::DomainName(text)
+            | DhcpOptionValue::RootPath(text)
+            | DhcpOptionValue::ExtensionsPath(text)
+            | DhcpOptionValue::NisDomain(text)
+            | DhcpOptionValue::NetbiosScope(text)
+            | DhcpOptionValue::Message(text)
+            | DhcpOptionValue::NisPlusDomain(text)
+            | DhcpOptionValue::TftpServerName(text)
+            | DhcpOptionValue::BootFileName(text) => text.as_bytes().to_vec(),
+            DhcpOptionValue::BootFileSize(value)
+            | DhcpOptionValue::MaxDatagramReassemblySize(value)
+            | DhcpOptionValue::InterfaceMtu(value)
+            | DhcpOptionValue::MaxMessageSize(value) => value.to_be_bytes().to_vec(),
+            DhcpOptionValue::IpForwarding(flag)
+            | DhcpOptionValue::NonLocalSourceRouting(flag)
+            | DhcpOptionValue::AllSubnetsLocal(flag)
+            | DhcpOptionValue::PerformMaskDiscovery(flag)
+            | DhcpOptionValue::MaskSupplier(flag)
+            | DhcpOptionValue::PerformRouterDiscovery(flag)
+            | DhcpOptionValue::TrailerEncapsulation(flag)
+            | DhcpOptionValue::EthernetEncapsulation(flag)
+            | DhcpOptionValue::TcpKeepaliveGarbage(flag) => vec![*flag as u8],
+            DhcpOptionValue::PolicyFilter(pairs) | DhcpOptionValue::StaticRoutes(pairs) => pairs
+                .iter()
+                .flat_map(|(a, b)| [a.octets(), b.octets()].concat())
+                .collect(),
+            DhcpOptionValue::DefaultIpTtl(value)
+            | DhcpOptionValue::TcpDefaultTtl(value)
+            | DhcpOptionValue::NetbiosNodeType(value) => vec![*value],
+            DhcpOptionValue::PathMtuAgingTimeout(time)
+            | DhcpOptionValue::ArpCacheTimeout(time)
+            | DhcpOptionValue::TcpKeepaliveInterval(time)
+            | DhcpOptionValue::LeaseTime(time)
+            | DhcpOptionValue::RenewalTime(time)
+            | DhcpOptionValue::RebindingTime(time) => encode_duration(time),
+            DhcpOptionValue::PathMtuPlateauTable(values) => {
+                values.iter().flat_map(|x| x.to_be_bytes()).collect()
+            }
+            DhcpOptionValue::VendorSpecific(bytes)
+            | DhcpOptionValue::ParameterRequest(bytes)
+            | DhcpOptionValue::VendorClass(bytes)
+            | DhcpOptionValue::ClientId(bytes) => bytes.clone(),
+            DhcpOptionValue::OptionOverload(overload) => vec![*overload],
+            DhcpOptionValue::MessageType(mtype) => vec![*mtype as u8],
+            DhcpOptionValue::DomainSearch(names) => encode_domain_list(names),
+            DhcpOptionValue::ClientFqdn(fqdn) => fqdn.encode(),
+            DhcpOptionValue::Pad | DhcpOptionValue::RapidCommit | DhcpOptionValue::End => vec![],
+            DhcpOptionValue::Unknown(_, body) => body.clone(),
+        };
+        DhcpOption::new(self.get_id(), body)
+    }
+
+    // Malformed bodies of known options fall back to Unknown rather than failing
+    pub fn decode(option: &DhcpOption) -> Self {
+        let (id, body) = (option.get_id(), option.get_body());
+        Self::decode_known(id, body).unwrap_or_else(|| DhcpOptionValue::Unknown(id, body.to_vec()))
+    }
+
+    fn decode_known(id: u8, body: &[u8]) -> Option<Self> {
+        let otype: DhcpOptionType = FromPrimitive::from_u8(id)?;
+        let value = match otype {
+            DhcpOptionType::Pad => DhcpOptionValue::Pad,
+            DhcpOptionType::SubnetMask => DhcpOptionValue::SubnetMask(decode_ip(body)?),
+            DhcpOptionType::TimeOffset => DhcpOptionValue::TimeOffset(decode_i32(body)?),
+            DhcpOptionType::RouterIp => DhcpOptionValue::RouterIp(decode_ip_list(body)?),
+            DhcpOptionType::TimeServers => DhcpOptionValue::TimeServers(decode_ip_list(body)?),
+            DhcpOptionType::NameServers => DhcpOptionValue::NameServers(decode_ip_list(body)?),
+            DhcpOptionType::DnsServerIp => DhcpOptionValue::DnsServerIp(decode_ip_list(body)?),
+            DhcpOptionType::LogServers => DhcpOptionValue::LogServers(decode_ip_list(body)?),
+            DhcpOptionType::CookieServers => DhcpOptionValue::CookieServers(decode_ip_list(body)?),
+            DhcpOptionType::LprServers => DhcpOptionValue::LprServers(decode_ip_list(body)?),
+            DhcpOptionType::ImpressServers => {
+                DhcpOptionValue::ImpressServers(decode_ip_list(body)?)
+            }
+            DhcpOptionType::ResourceLocationServers => {
+                DhcpOptionValue::ResourceLocationServers(decode_ip_list(body)?)
+            }
+            DhcpOptionType::HostName => DhcpOptionValue::HostName(decode_string(body)?),
+            DhcpOptionType::BootFileSize => DhcpOptionValue::BootFileSize(decode_u16(body)?),
+            DhcpOptionType::MeritDumpFile => DhcpOptionValue::MeritDumpFile(decode_string(body)?),
+            DhcpOptionType::DomainName => DhcpOptionValue::DomainName(decode_string(body)?),
+            DhcpOptionType::SwapServer => DhcpOptionValue::SwapServer(decode_ip(body)?),
+            DhcpOptionType::RootPath => DhcpOptionValue::RootPath(decode_string(body)?),
+            DhcpOptionType::ExtensionsPath => DhcpOptionValue::ExtensionsPath(decode_string(body)?),
+            DhcpOptionType::IpForwarding => DhcpOptionValue::IpForwarding(decode_bool(body)?),
+            DhcpOptionType::NonLocalSourceRouting => {
+                DhcpOptionValue::NonLocalSourceRouting(decode_bool(body)?)
+            }
+            DhcpOptionType::PolicyFilter => DhcpOptionValue::PolicyFilter(decode_ip_pairs(body)?),
+            DhcpOptionType::MaxDatagramReassemblySize => {
+                DhcpOptionValue::MaxDatagramReassemblySize(decode_u16(body)?)
+            }
+            DhcpOptionType::DefaultIpTtl => DhcpOptionValue::DefaultIpTtl(decode_u8(body)?),
+            DhcpOptionType::PathMtuAgingTimeout => {
+                DhcpOptionValue::PathMtuAgingTimeout(decode_duration(body)?)
+            }
+            DhcpOptionType::PathMtuPlateauTable => {
+                DhcpOptionValue::PathMtuPlateauTable(decode_u16_list(body)?)
+            }
+            DhcpOptionType::InterfaceMtu => DhcpOptionValue::InterfaceMtu(decode_u16(body)?),
+            DhcpOptionType::AllSubnetsLocal => DhcpOptionValue::AllSubnetsLocal(decode_bool(body)?),
+            DhcpOptionType::BroadcastAddress => DhcpOptionValue::BroadcastAddress(decode_ip(body)?),
+            DhcpOptionType::PerformMaskDiscovery => {
+                DhcpOptionValue::PerformMaskDiscovery(decode_bool(body)?)
+            }
+            DhcpOptionType::MaskSupplier => DhcpOptionValue::MaskSupplier(decode_bool(body)?),
+            DhcpOptionType::PerformRouterDiscovery => {
+                DhcpOptionValue::PerformRouterDiscovery(decode_bool(body)?)
+            }
+            DhcpOptionType::RouterSolicitationAddress => {
+                DhcpOptionValue::RouterSolicitationAddress(decode_ip(body)?)
+            }
+            DhcpOptionType::StaticRoutes => DhcpOptionValue::StaticRoutes(decode_ip_pairs(body)?),
+            DhcpOptionType::TrailerEncapsulation => {
+                DhcpOptionValue::TrailerEncapsulation(decode_bool(body)?)
+            }
+            DhcpOptionType::ArpCacheTimeout => {
+                DhcpOptionValue::ArpCacheTimeout(decode_duration(body)?)
+            }
+            DhcpOptionType::EthernetEncapsulation => {
+                DhcpOptionValue::EthernetEncapsulation(decode_bool(body)?)
+            }
+            DhcpOptionType::TcpDefaultTtl => DhcpOptionValue::TcpDefaultTtl(decode_u8(body)?),
+            DhcpOptionType::TcpKeepaliveInterval => {
+                DhcpOptionValue::TcpKeepaliveInterval(decode_duration(body)?)
+            }
+            DhcpOptionType::TcpKeepaliveGarbage => {
+                DhcpOptionValue::TcpKeepaliveGarbage(decode_bool(body)?)
+            }
+            DhcpOptionType::NisDomain => DhcpOptionValue::NisDomain(decode_string(body)?),
+            DhcpOptionType::NisServers => DhcpOptionValue::NisServers(decode_ip_list(body)?),
+            DhcpOptionType::NtpServers => DhcpOptionValue::NtpServers(decode_ip_list(body)?),
+            DhcpOptionType::VendorSpecific => DhcpOptionValue::VendorSpecific(body.to_vec()),
+            DhcpOptionType::NetbiosNameServers => {
+                DhcpOptionValue::NetbiosNameServers(decode_ip_list(body)?)
+            }
+            DhcpOptionType::NetbiosDatagramServers => {
+                DhcpOptionValue::NetbiosDatagramServers(decode_ip_list(body)?)
+            }
+            DhcpOptionType::NetbiosNodeType => DhcpOptionValue::NetbiosNodeType(decode_u8(body)?),
+            DhcpOptionType::NetbiosScope => DhcpOptionValue::NetbiosScope(decode_string(body)?),
+            DhcpOptionType::XFontServers => DhcpOptionValue::XFontServers(decode_ip_list(body)?),
+            DhcpOptionType::XDisplayManagers => {
+                DhcpOptionValue::XDisplayManagers(decode_ip_list(body)?)
+            }
+            DhcpOptionType::RequestedIp => DhcpOptionValue::RequestedIp(decode_ip(body)?),
+            DhcpOptionType::LeaseTime => DhcpOptionValue::LeaseTime(decode_duration(body)?),
+            DhcpOptionType::OptionOverload => match body {
+                [overload @ 1..=3] => DhcpOptionValue::OptionOverload(*overload),
+                _ => return None,
+            },
+            DhcpOptionType::MessageType => match body {
+                [mtype] => DhcpOptionValue::MessageType(FromPrimitive::from_u8(*mtype)?),
+                _ => return None,
+            },
+            DhcpOptionType::DhcpServerIp => DhcpOptionValue::DhcpServerIp(decode_ip(body)?),
+            DhcpOptionType::ParameterRequest => DhcpOptionValue::ParameterRequest(body.to_vec()),
+            DhcpOptionType::Message => DhcpOptionValue::Message(decode_string(body)?),
+            DhcpOptionType::MaxMessageSize => DhcpOptionValue::MaxMessageSize(decode_u16(body)?),
+            DhcpOptionType::RenewalTime => DhcpOptionValue::RenewalTime(decode_duration(body)?),
+            DhcpOptionType::RebindingTime => DhcpOptionValue::RebindingTime(decode_duration(body)?),
+            DhcpOptionType::VendorClass => DhcpOptionValue::VendorClass(body.to_vec()),
+            DhcpOptionType::ClientId if body.len() >= 2 => DhcpOptionValue::ClientId(body.to_vec()),
+            DhcpOptionType::ClientId => return None,
+            DhcpOptionType::NisPlusDomain => DhcpOptionValue::NisPlusDomain(decode_string(body)?),
+            DhcpOptionType::NisPlusServers => {
+                DhcpOptionValue::NisPlusServers(decode_ip_list(body)?)
+            }
+            DhcpOptionType::TftpServerName => DhcpOptionValue::TftpServerName(decode_string(body)?),
+            DhcpOptionType::BootFileName => DhcpOptionValue::BootFileName(decode_string(body)?),
+            // An empty list is valid here, meaning no home agents are available
+            DhcpOptionType::MobileIpHomeAgents if body.is_empty() => {
+                DhcpOptionValue::MobileIpHomeAgents(vec![])
+            }
+            DhcpOptionType::MobileIpHomeAgents => {
+                DhcpOptionValue::MobileIpHomeAgents(decode_ip_list(body)?)
+            }
+            DhcpOptionType::SmtpServers => DhcpOptionValue::SmtpServers(decode_ip_list(body)?),
+            DhcpOptionType::Pop3Servers => DhcpOptionValue::Pop3Servers(decode_ip_list(body)?),
+            DhcpOptionType::NntpServers => DhcpOptionValue::NntpServers(decode_ip_list(body)?),
+            DhcpOptionType::WwwServers => DhcpOptionValue::WwwServers(decode_ip_list(body)?),
+            DhcpOptionType::FingerServers => DhcpOptionValue::FingerServers(decode_ip_list(body)?),
+            DhcpOptionType::IrcServers => DhcpOptionValue::IrcServers(decode_ip_list(body)?),
+            DhcpOptionType::StreetTalkServers => {
+                DhcpOptionValue::StreetTalkServers(decode_ip_list(body)?)
+            }
+            DhcpOptionType::StdaServers => DhcpOptionValue::StdaServers(decode_ip_list(body)?),
+            DhcpOptionType::RapidCommit if body.is_empty() => DhcpOptionValue::RapidCommit,
+            DhcpOptionType::RapidCommit => return None,
+            DhcpOptionType::ClientFqdn => DhcpOptionValue::ClientFqdn(ClientFqdn::decode(body)?),
+            DhcpOptionType::DomainSearch => {
+                DhcpOptionValue::DomainSearch(decode_domain_list(body)?)
+            }
+            DhcpOptionType::End => DhcpOptionValue::End,
+        };
+        Some(value)
+    }
+}
+
+impl From<DhcpOptionValue> for DhcpOption {
+    fn from(value: DhcpOptionValue) -> Self {
+        value.encode()
+    }
+}
+
+impl From<&DhcpOption> for DhcpOptionValue {
+    fn from(option: &DhcpOption) -> Self {
+        DhcpOptionValue::decode(option)
+    }
+}
+
+pub(crate) fn decode_ip(body: &[u8]) -> Option<Ipv4Addr> {
+    let octets: [u8; 4] = body.try_into().ok()?;
+    Some(Ipv4Addr::from(octets))
+}
+
+fn encode_ip_list(ips: &[Ipv4Addr]) -> Vec<u8> {
+    ips.iter().flat_map(|x| x.octets()).collect()
+}
+
+pub(crate) fn decode_ip_list(body: &[u8]) -> Option<Vec<Ipv4Addr>> {
+    if body.is_empty() || body.len() % 4 != 0 {
+        return None;
+    }
+    Some(body.chunks_exact(4).filter_map(decode_ip).collect())
+}
+
+// Address pairs, used by Policy Filter (address, mask) and Static Route (destination, router)
+fn decode_ip_pairs(body: &[u8]) -> Option<Vec<(Ipv4Addr, Ipv4Addr)>> {
+    if body.is_empty() || body.len() % 8 != 0 {
+        return None;
+    }
+    let pairs = body
+        .chunks_exact(8)
+        .filter_map(|x| Some((decode_ip(&x[..4])?, decode_ip(&x[4..])?)))
+        .collect();
+    Some(pairs)
+}
+
+pub(crate) fn decode_u8(body: &[u8]) -> Option<u8> {
+    match body {
+        [value] => Some(*value),
+        _ => None,
+    }
+}
+
+pub(crate) fn decode_bool(body: &[u8]) -> Option<bool> {
+    match body {
+        [0] => Some(false),
+        [1] => Some(true),
+        _ => None,
+    }
+}
+
+pub(crate) fn decode_u16(body: &[u8]) -> Option<u16> {
+    Some(u16::from_be_bytes(body.try_into().ok()?))
+}
+
+fn decode_u16_list(body: &[u8]) -> Option<Vec<u16>> {
+    if body.is_empty() || body.len() % 2 != 0 {
+        return None;
+    }
+    Some(body.chunks_exact(2).filter_map(decode_u16).collect())
+}
+
+fn decode_i32(body: &[u8]) -> Option<i32> {
+    Some(i32::from_be_bytes(body.try_into().ok()?))
+}
+
+pub(crate) fn decode_string(body: &[u8]) -> Option<String> {
+    if body.is_empty() {
+        return None;
+    }
+    String::from_utf8(body.to_vec()).ok()
+}
+
+// Times are 32 bit seconds, network byte order
+fn decode_duration(body: &[u8]) -> Option<Duration> {
+    let secs: [u8; 4] = body.try_into().ok()?;
+    Some(Duration::from_secs(u32::from_be_bytes(secs) as u64))
+}
+
+fn encode_duration(time: &Duration) -> Vec<u8> {
+    let secs = time.as_secs().min(u32::MAX as u64) as u32;
+    secs.to_be_bytes().to_vec()
+}
+
+// RFC 1035 wire format names, one after the other as RFC 3397 lays out option 119
+pub(crate) fn encode_domain_list(names: &[String]) -> Vec<u8> {
+    let mut buffer = vec![];
+    for name in names {
+        buffer.extend(encode_domain_name(name));
+    }
+    buffer
+}
+
+pub(crate) fn encode_domain_name(name: &str) -> Vec<u8> {
+    let mut buffer = vec![];
+    for label in name
+        .trim_end_matches('.')
+        .split('.')
+        .filter(|x| !x.is_empty())
+    {
+        let label = &label.as_bytes()[..label.len().min(MAX_LABEL_LENGTH)];
+        buffer.push(label.len() as u8);
+        buffer.extend_from_slice(label);
+    }
+    buffer.push(0);
+    buffer
+}
+
+// Names we send have to fit the same limits as names in DNS, a single trailing dot is allowed
+pub(crate) fn check_domain_name(name: &str) -> Result<(), DhcpNameError> {
+    let trimmed = name.strip_suffix('.').unwrap_or(name);
+    if trimmed.is_empty() {
+        return Err(DhcpNameError::Empty);
+    }
+    for label in trimmed.split('.') {
+        if label.is_empty() {
+            return Err(DhcpNameError::EmptyLabel {
+                name: name.to_string(),
+            });
+        }
+        if label.len() > MAX_LABEL_LENGTH {
+            return Err(DhcpNameError::LabelTooLong {
+                label: label.to_string(),
+            });
+        }
+    }
+    // The wire form adds a length byte before the first label and the root label at the end
+    if trimmed.len() + 2 > MAX_NAME_LENGTH {
+        return Err(DhcpNameError::NameTooLong {
+            length: trimmed.len(),
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn decode_domain_list(body: &[u8]) -> Option<Vec<String>> {
+    let mut names = vec![];
+    let mut cursor = 0;
+    while cursor < body.len() {
+        let (name, next) = decode_domain_name(body, cursor)?;
+        names.push(name);
+        cursor = next;
+    }
+    Some(names)
+}
+
+// Reads one name starting at `start`, following compression pointers into earlier names.
+// Returns the name and where the next one begins
+pub(crate) fn decode_domain_name(body: &[u8], start: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = vec![];
+    let mut length = 0;
+    let mut cursor = start;
+    let mut next = None;
+    // Pointers may only go backwards, so this bounds the number of jumps
+    let mut jumps = 0;
+    loop {
+        let len = *body.get(cursor)? as usize;
+        if len == 0 {
+            let next = next.unwrap_or(cursor + 1);
+            return Some((labels.join("."), next));
+        }
+        if len & 0xc0 == 0xc0 {
+            let pointer = ((len & 0x3f) << 8) | *body.get(cursor + 1)? as usize;
+            if pointer >= cursor || jumps > body.len() {
+                return None;
+            }
+            next.get_or_insert(cursor + 2);
+            cursor = pointer;
+            jumps += 1;
+            continue;
+        }
+        let label = body.get((cursor + 1)..(cursor + 1 + len))?;
+        length += len + 1;
+        if length > MAX_NAME_LENGTH {
+            return None;
+        }
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        cursor += len + 1;
+    }
+}
+
+#[cfg(test)]
+mod dhcp_options {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn round_trip(value: DhcpOptionValue) {
+        assert_eq!(DhcpOptionValue::decode(&value.encode()), value);
+    }
+
+    #[test]
+    fn test_encode_option() {
+        assert_eq!(
+            DhcpOptionValue::MessageType(DhcpMessageType::Discover).encode(),
+            DhcpOption::new(53, vec![0x01])
+        );
+        assert_eq!(
+            DhcpOptionValue::RequestedIp(Ipv4Addr::new(192, 168, 1, 99)).encode(),
+            DhcpOption::new(50, vec![192, 168, 1, 99])
+        );
+        assert_eq!(
+            DhcpOptionValue::LeaseTime(Duration::from_secs(3600)).encode(),
+            DhcpOption::new(51, vec![0, 0, 0x0e, 0x10])
+        );
+    }
+
+    #[test]
+    fn test_round_trip_options() {
+        let ip = Ipv4Addr::new(192, 168, 1, 1);
+        round_trip(DhcpOptionValue::SubnetMask(Ipv4Addr::new(255, 255, 255, 0)));
+        round_trip(DhcpOptionValue::RouterIp(vec![ip]));
+        round_trip(DhcpOptionValue::DnsServerIp(vec![
+            ip,
+            Ipv4Addr::new(8, 8, 8, 8),
+        ]));
+        round_trip(DhcpOptionValue::HostName("host".to_string()));
+        round_trip(DhcpOptionValue::DomainName("example.com".to_string()));
+        round_trip(DhcpOptionValue::NtpServers(vec![ip]));
+        round_trip(DhcpOptionValue::RequestedIp(ip));
+        round_trip(DhcpOptionValue::LeaseTime(Duration::from_secs(3600)));
+        round_trip(DhcpOptionValue::OptionOverload(3));
+        round_trip(DhcpOptionValue::MessageType(DhcpMessageType::Ack));
+        round_trip(DhcpOptionValue::DhcpServerIp(ip));
+        round_trip(DhcpOptionValue::ParameterRequest(vec![1, 3, 6]));
+        round_trip(DhcpOptionValue::RenewalTime(Duration::from_secs(1800)));
+        round_trip(DhcpOptionValue::RebindingTime(Duration::from_secs(3150)));
+        round_trip(DhcpOptionValue::ClientId(vec![
+            1, 0x10, 0x7b, 0x44, 0x93, 0xe6, 0xd0,
+        ]));
+        round_trip(DhcpOptionValue::DomainSearch(vec![
+            "eng.example.com".to_string(),
+            "example.com".to_string(),
+        ]));
+        round_trip(DhcpOptionValue::RapidCommit);
+        round_trip(DhcpOptionValue::ClientFqdn(
+            ClientFqdn::new("host").unwrap(),
+        ));
+        round_trip(DhcpOptionValue::End);
+        round_trip(DhcpOptionValue::Unknown(224, vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn test_round_trip_rfc2132_options() {
+        let ip = Ipv4Addr::new(10, 0, 0, 1);
+        let mask = Ipv4Addr::new(255, 255, 255, 0);
+        round_trip(DhcpOptionValue::TimeOffset(-18000));
+        round_trip(DhcpOptionValue::TimeServers(vec![ip]));
+        round_trip(DhcpOptionValue::BootFileSize(512));
+        round_trip(DhcpOptionValue::RootPath("/srv/root".to_string()));
+        round_trip(DhcpOptionValue::IpForwarding(false));
+        round_trip(DhcpOptionValue::PolicyFilter(vec![(ip, mask)]));
+        round_trip(DhcpOptionValue::DefaultIpTtl(64));
+        round_trip(DhcpOptionValue::PathMtuAgingTimeout(Duration::from_secs(
+            600,
+        )));
+        round_trip(DhcpOptionValue::PathMtuPlateauTable(vec![68, 1500]));
+        round_trip(DhcpOptionValue::InterfaceMtu(1500));
+        round_trip(DhcpOptionValue::AllSubnetsLocal(true));
+        round_trip(DhcpOptionValue::StaticRoutes(vec![(ip, mask), (mask, ip)]));
+        round_trip(DhcpOptionValue::NisDomain("nis".to_string()));
+        round_trip(DhcpOptionValue::VendorSpecific(vec![1, 2, 3]));
+        round_trip(DhcpOptionValue::NetbiosNodeType(8));
+        round_trip(DhcpOptionValue::Message("no address".to_string()));
+        round_trip(DhcpOptionValue::MaxMessageSize(1500));
+        round_trip(DhcpOptionValue::VendorClass(b"MSFT 5.0".to_vec()));
+        round_trip(DhcpOptionValue::TftpServerName("tftp".to_string()));
+        round_trip(DhcpOptionValue::BootFileName("pxelinux.0".to_string()));
+        round_trip(DhcpOptionValue::MobileIpHomeAgents(vec![]));
+        round_trip(DhcpOptionValue::StdaServers(vec![ip]));
+    }
+
+    #[test]
+    fn test_encode_rfc2132_options() {
+        assert_eq!(
+            DhcpOptionValue::TimeOffset(-1).encode(),
+            DhcpOption::new(2, vec![0xff, 0xff, 0xff, 0xff])
+        );
+        assert_eq!(
+            DhcpOptionValue::StaticRoutes(vec![(
+                Ipv4Addr::new(10, 0, 0, 0),
+                Ipv4Addr::new(192, 168, 1, 1)
+            )])
+            .encode(),
+            DhcpOption::new(33, vec![10, 0, 0, 0, 192, 168, 1, 1])
+        );
+        assert_eq!(
+            DhcpOptionValue::InterfaceMtu(1500).encode(),
+            DhcpOption::new(26, vec![0x05, 0xdc])
+        );
+        assert_eq!(
+            DhcpOptionValue::decode(&DhcpOption::new(19, vec![2])),
+            DhcpOptionValue::Unknown(19, vec![2])
+        );
+        assert_eq!(
+            DhcpOptionValue::decode(&DhcpOption::new(33, vec![10, 0, 0, 0])),
+            DhcpOptionValue::Unknown(33, vec![10, 0, 0, 0])
+        );
+    }
+
+    #[test]
+    fn test_decode_malformed_as_unknown() {
+        assert_eq!(
+            DhcpOptionValue::decode(&DhcpOption::new(1, vec![255, 255])),
+            DhcpOptionValue::Unknown(1, vec![255, 255])
+        );
+        assert_eq!(
+            DhcpOptionValue::decode(&DhcpOption::new(53, vec![42])),
+            DhcpOptionValue::Unknown(53, vec![42])
+        );
+        assert_eq!(
+            DhcpOptionValue::decode(&DhcpOption::new(6, vec![1, 1, 1])),
+            DhcpOptionValue::Unknown(6, vec![1, 1, 1])
+        );
+        assert_eq!(
+            DhcpOptionValue::decode(&DhcpOption::new(68, vec![10, 0, 0])),
+            DhcpOptionValue::Unknown(68, vec![10, 0, 0])
+        );
+    }
+
+    #[test]
+    fn test_decode_compressed_domain_search() {
+        // Example from RFC 3397 section 2
+        let body = vec![
+            0x03, b'e', b'n', b'g', 0x05, b'a', b'p', b'p', b'l', b'e', 0x03, b'c', b'o', b'm',
+            0x00, 0xc0, 0x04, 0x03, b'f', b'o', b'o', 0xc0, 0x04,
+        ];
+
+        assert_eq!(
+            DhcpOptionValue::decode(&DhcpOption::new(119, body)),
+            DhcpOptionValue::DomainSearch(vec![
+                "eng.apple.com".to_string(),
+                "apple.com".to_string(),
+                "foo.apple.com".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_decode_domain_pointer_loop() {
+        assert_eq!(decode_domain_list(&[0xc0, 0x00]), None);
+        assert_eq!(decode_domain_list(&[0x03, b'f', b'o']), None);
+    }
+}
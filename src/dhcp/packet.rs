@@ -1,36 +1,220 @@
-use crate::dhcp::traits::{Deserialize, Serialize};
+use crate::dhcp::{
+    error::DhcpParseError,
+    fqdn::ClientFqdn,
+    options::DhcpOptionValue,
+    traits::{Deserialize, Serialize},
+};
 use mac_address::MacAddress;
 use num_derive::FromPrimitive;
-use num_traits::FromPrimitive;
-use std::{convert::TryInto, net::Ipv4Addr, time::Duration};
+use std::{convert::TryInto, fmt, net::Ipv4Addr, time::Duration};
 
 pub type TransactionToken = [u8; 4];
 
-#[derive(Copy, Clone, Eq, PartialEq, FromPrimitive)]
+// Fixed BOOTP header length, options start right after the magic cookie
+const HEADER_SIZE: usize = 240;
+const MAGIC_COOKIE: [u8; 4] = [0x63, 0x82, 0x53, 0x63];
+const CHADDR_SIZE: usize = 16;
+const SNAME_OFFSET: usize = 44;
+const SNAME_SIZE: usize = 64;
+const FILE_OFFSET: usize = 108;
+const FILE_SIZE: usize = 128;
+// Room left for options in the 576 byte message every host must accept, after the
+// IP and UDP headers, the BOOTP header and the magic cookie
+const OPTIONS_SIZE: usize = 308;
+// Option Overload values, see RFC 2132 section 9.3
+const OVERLOAD_FILE: u8 = 1;
+const OVERLOAD_SNAME: u8 = 2;
+// Longer option bodies are sent as several instances of the option, see RFC 3396
+const MAX_OPTION_SIZE: usize = 255;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, FromPrimitive)]
 pub enum DhcpMessageType {
     Discover = 1,
     Offer = 2,
     Request = 3,
+    Decline = 4,
     Ack = 5,
     Nak = 6,
+    Release = 7,
+    Inform = 8,
 }
 
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, FromPrimitive)]
 pub enum DhcpOptionType {
+    Pad = 0,
     SubnetMask = 1,
+    TimeOffset = 2,
     RouterIp = 3,
+    TimeServers = 4,
+    NameServers = 5,
     DnsServerIp = 6,
+    LogServers = 7,
+    CookieServers = 8,
+    LprServers = 9,
+    ImpressServers = 10,
+    ResourceLocationServers = 11,
     HostName = 12,
+    BootFileSize = 13,
+    MeritDumpFile = 14,
+    DomainName = 15,
+    SwapServer = 16,
+    RootPath = 17,
+    ExtensionsPath = 18,
+    IpForwarding = 19,
+    NonLocalSourceRouting = 20,
+    PolicyFilter = 21,
+    MaxDatagramReassemblySize = 22,
+    DefaultIpTtl = 23,
+    PathMtuAgingTimeout = 24,
+    PathMtuPlateauTable = 25,
+    InterfaceMtu = 26,
+    AllSubnetsLocal = 27,
+    BroadcastAddress = 28,
+    PerformMaskDiscovery = 29,
+    MaskSupplier = 30,
+    PerformRouterDiscovery = 31,
+    RouterSolicitationAddress = 32,
+    StaticRoutes = 33,
+    TrailerEncapsulation = 34,
+    ArpCacheTimeout = 35,
+    EthernetEncapsulation = 36,
+    TcpDefaultTtl = 37,
+    TcpKeepaliveInterval = 38,
+    TcpKeepaliveGarbage = 39,
+    NisDomain = 40,
+    NisServers = 41,
+    NtpServers = 42,
+    VendorSpecific = 43,
+    NetbiosNameServers = 44,
+    NetbiosDatagramServers = 45,
+    NetbiosNodeType = 46,
+    NetbiosScope = 47,
+    XFontServers = 48,
+    XDisplayManagers = 49,
     RequestedIp = 50,
     LeaseTime = 51,
+    OptionOverload = 52,
     MessageType = 53,
     DhcpServerIp = 54,
     ParameterRequest = 55,
+    Message = 56,
+    MaxMessageSize = 57,
+    RenewalTime = 58,
+    RebindingTime = 59,
+    VendorClass = 60,
     ClientId = 61,
+    NisPlusDomain = 64,
+    NisPlusServers = 65,
+    TftpServerName = 66,
+    BootFileName = 67,
+    MobileIpHomeAgents = 68,
+    SmtpServers = 69,
+    Pop3Servers = 70,
+    NntpServers = 71,
+    WwwServers = 72,
+    FingerServers = 73,
+    IrcServers = 74,
+    StreetTalkServers = 75,
+    StdaServers = 76,
+    RapidCommit = 80,
+    ClientFqdn = 81,
+    DomainSearch = 119,
     End = 255,
 }
 
-#[derive(Eq, PartialEq, Debug)]
+impl DhcpOptionType {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            DhcpOptionType::Pad => "Pad",
+            DhcpOptionType::SubnetMask => "Subnet Mask",
+            DhcpOptionType::TimeOffset => "Time Offset",
+            DhcpOptionType::RouterIp => "Router",
+            DhcpOptionType::TimeServers => "Time Server",
+            DhcpOptionType::NameServers => "Name Server",
+            DhcpOptionType::DnsServerIp => "Domain Name Server",
+            DhcpOptionType::LogServers => "Log Server",
+            DhcpOptionType::CookieServers => "Cookie Server",
+            DhcpOptionType::LprServers => "LPR Server",
+            DhcpOptionType::ImpressServers => "Impress Server",
+            DhcpOptionType::ResourceLocationServers => "Resource Location Server",
+            DhcpOptionType::HostName => "Host Name",
+            DhcpOptionType::BootFileSize => "Boot File Size",
+            DhcpOptionType::MeritDumpFile => "Merit Dump File",
+            DhcpOptionType::DomainName => "Domain Name",
+            DhcpOptionType::SwapServer => "Swap Server",
+            DhcpOptionType::RootPath => "Root Path",
+            DhcpOptionType::ExtensionsPath => "Extensions Path",
+            DhcpOptionType::IpForwarding => "IP Forwarding",
+            DhcpOptionType::NonLocalSourceRouting => "Non-Local Source Routing",
+            DhcpOptionType::PolicyFilter => "Policy Filter",
+            DhcpOptionType::MaxDatagramReassemblySize => "Maximum Datagram Reassembly Size",
+            DhcpOptionType::DefaultIpTtl => "Default IP Time-to-live",
+            DhcpOptionType::PathMtuAgingTimeout => "Path MTU Aging Timeout",
+            DhcpOptionType::PathMtuPlateauTable => "Path MTU Plateau Table",
+            DhcpOptionType::InterfaceMtu => "Interface MTU",
+            DhcpOptionType::AllSubnetsLocal => "All Subnets are Local",
+            DhcpOptionType::BroadcastAddress => "Broadcast Address",
+            DhcpOptionType::PerformMaskDiscovery => "Perform Mask Discovery",
+            DhcpOptionType::MaskSupplier => "Mask Supplier",
+            DhcpOptionType::PerformRouterDiscovery => "Perform Router Discovery",
+            DhcpOptionType::RouterSolicitationAddress => "Router Solicitation Address",
+            DhcpOptionType::StaticRoutes => "Static Route",
+            DhcpOptionType::TrailerEncapsulation => "Trailer Encapsulation",
+            DhcpOptionType::ArpCacheTimeout => "ARP Cache Timeout",
+            DhcpOptionType::EthernetEncapsulation => "Ethernet Encapsulation",
+            DhcpOptionType::TcpDefaultTtl => "TCP Default TTL",
+            DhcpOptionType::TcpKeepaliveInterval => "TCP Keepalive Interval",
+            DhcpOptionType::TcpKeepaliveGarbage => "TCP Keepalive Garbage",
+            DhcpOptionType::NisDomain => "NIS Domain",
+            DhcpOptionType::NisServers => "NIS Servers",
+            DhcpOptionType::NtpServers => "NTP Servers",
+            DhcpOptionType::VendorSpecific => "Vendor Specific Information",
+            DhcpOptionType::NetbiosNameServers => "NetBIOS Name Server",
+            DhcpOptionType::NetbiosDatagramServers => "NetBIOS Datagram Distribution Server",
+            DhcpOptionType::NetbiosNodeType => "NetBIOS Node Type",
+            DhcpOptionType::NetbiosScope => "NetBIOS Scope",
+            DhcpOptionType::XFontServers => "X Window Font Server",
+            DhcpOptionType::XDisplayManagers => "X Window Display Manager",
+            DhcpOptionType::RequestedIp => "Requested IP Address",
+            DhcpOptionType::LeaseTime => "IP Address Lease Time",
+            DhcpOptionType::OptionOverload => "Option Overload",
+            DhcpOptionType::MessageType => "DHCP Message Type",
+            DhcpOptionType::DhcpServerIp => "Server Identifier",
+            DhcpOptionType::ParameterRequest => "Parameter Request List",
+            DhcpOptionType::Message => "Message",
+            DhcpOptionType::MaxMessageSize => "Maximum DHCP Message Size",
+            DhcpOptionType::RenewalTime => "Renewal (T1) Time Value",
+            DhcpOptionType::RebindingTime => "Rebinding (T2) Time Value",
+            DhcpOptionType::VendorClass => "Vendor Class Identifier",
+            DhcpOptionType::ClientId => "Client Identifier",
+            DhcpOptionType::NisPlusDomain => "NIS+ Domain",
+            DhcpOptionType::NisPlusServers => "NIS+ Servers",
+            DhcpOptionType::TftpServerName => "TFTP Server Name",
+            DhcpOptionType::BootFileName => "Bootfile Name",
+            DhcpOptionType::MobileIpHomeAgents => "Mobile IP Home Agent",
+            DhcpOptionType::SmtpServers => "SMTP Server",
+            DhcpOptionType::Pop3Servers => "POP3 Server",
+            DhcpOptionType::NntpServers => "NNTP Server",
+            DhcpOptionType::WwwServers => "Default WWW Server",
+            DhcpOptionType::FingerServers => "Default Finger Server",
+            DhcpOptionType::IrcServers => "Default IRC Server",
+            DhcpOptionType::StreetTalkServers => "StreetTalk Server",
+            DhcpOptionType::StdaServers => "StreetTalk Directory Assistance Server",
+            DhcpOptionType::RapidCommit => "Rapid Commit",
+            DhcpOptionType::ClientFqdn => "Client FQDN",
+            DhcpOptionType::DomainSearch => "Domain Search",
+            DhcpOptionType::End => "End",
+        }
+    }
+}
+
+impl fmt::Display for DhcpOptionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.get_name(), *self as u8)
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
 pub struct DhcpPacket {
     op: u8,
     htype: u8,
@@ -43,12 +227,14 @@ pub struct DhcpPacket {
     yiaddr: [u8; 4],
     siaddr: [u8; 4],
     giaddr: [u8; 4],
-    chaddr: [u8; 208],
+    chaddr: [u8; CHADDR_SIZE],
+    sname: [u8; SNAME_SIZE],
+    file: [u8; FILE_SIZE],
     cookie: [u8; 4],
     options: Vec<DhcpOption>,
 }
 
-#[derive(Eq, PartialEq, Debug)]
+#[derive(Clone, Eq, PartialEq, Debug)]
 pub struct DhcpOption {
     id: u8,
     body: Vec<u8>,
@@ -68,8 +254,10 @@ impl DhcpPacket {
             yiaddr: [0x00; 4],
             siaddr: [0x00; 4],
             giaddr: [0x00; 4],
-            chaddr: [0x00; 208],
-            cookie: [0x63, 0x82, 0x53, 0x63],
+            chaddr: [0x00; CHADDR_SIZE],
+            sname: [0x00; SNAME_SIZE],
+            file: [0x00; FILE_SIZE],
+            cookie: MAGIC_COOKIE,
             options: vec![],
         }
     }
@@ -79,8 +267,49 @@ impl DhcpPacket {
         self
     }
 
-    pub fn with_option(mut self, option: DhcpOption) -> Self {
-        self.options.push(option);
+    // Seconds since the client began acquiring or renewing its address
+    pub fn with_seconds(mut self, secs: u16) -> Self {
+        self.secs = secs.to_be_bytes();
+        self
+    }
+
+    pub fn with_client_ip(mut self, ip: &Ipv4Addr) -> Self {
+        self.yiaddr = ip.octets();
+        self
+    }
+
+    pub fn with_current_ip(mut self, ip: &Ipv4Addr) -> Self {
+        self.ciaddr = ip.octets();
+        self
+    }
+
+    pub fn with_broadcast_flag(mut self, broadcast: bool) -> Self {
+        self.flags = if broadcast { [0x80, 0x00] } else { [0x00; 2] };
+        self
+    }
+
+    pub fn with_next_server_ip(mut self, ip: &Ipv4Addr) -> Self {
+        self.siaddr = ip.octets();
+        self
+    }
+
+    // Names longer than the field are cut short, leaving room for the terminating null
+    pub fn with_server_name(mut self, name: &str) -> Self {
+        self.sname = [0x00; SNAME_SIZE];
+        let name = &name.as_bytes()[..name.len().min(SNAME_SIZE - 1)];
+        self.sname[..name.len()].copy_from_slice(name);
+        self
+    }
+
+    pub fn with_boot_file(mut self, file: &str) -> Self {
+        self.file = [0x00; FILE_SIZE];
+        let file = &file.as_bytes()[..file.len().min(FILE_SIZE - 1)];
+        self.file[..file.len()].copy_from_slice(file);
+        self
+    }
+
+    pub fn with_option<T: Into<DhcpOption>>(mut self, option: T) -> Self {
+        self.options.push(option.into());
         self
     }
 
@@ -88,7 +317,7 @@ impl DhcpPacket {
         self.chaddr = maddr
             .bytes()
             .iter()
-            .chain([0; 202].iter())
+            .chain([0; 10].iter())
             .cloned()
             .collect::<Vec<_>>()
             .try_into()
@@ -96,6 +325,15 @@ impl DhcpPacket {
         self
     }
 
+    // Addresses longer than chaddr are cut short
+    pub fn with_hardware_address(mut self, address: &[u8]) -> Self {
+        let length = address.len().min(CHADDR_SIZE);
+        self.chaddr = [0x00; CHADDR_SIZE];
+        self.chaddr[..length].copy_from_slice(&address[..length]);
+        self.hlen = length as u8;
+        self
+    }
+
     pub fn is_type(&self, mtype: DhcpMessageType) -> bool {
         match self.get_type() {
             Some(t) => mtype == t,
@@ -104,10 +342,9 @@ impl DhcpPacket {
     }
 
     pub fn get_type(&self) -> Option<DhcpMessageType> {
-        let body = self.get_option_body(DhcpOptionType::MessageType);
-        match body {
-            Some(b) => FromPrimitive::from_u8(b[0]),
-            None => None,
+        match self.get_option(DhcpOptionType::MessageType) {
+            Some(DhcpOptionValue::MessageType(t)) => Some(t),
+            _ => None,
         }
     }
 
@@ -115,49 +352,379 @@ impl DhcpPacket {
         *token == self.xid
     }
 
+    pub fn get_transaction(&self) -> TransactionToken {
+        self.xid
+    }
+
+    pub fn get_seconds(&self) -> u16 {
+        u16::from_be_bytes(self.secs)
+    }
+
     pub fn get_client_ip(&self) -> Ipv4Addr {
         Ipv4Addr::from(self.yiaddr)
     }
 
+    pub fn get_current_ip(&self) -> Ipv4Addr {
+        Ipv4Addr::from(self.ciaddr)
+    }
+
+    pub fn get_next_server_ip(&self) -> Ipv4Addr {
+        Ipv4Addr::from(self.siaddr)
+    }
+
+    pub fn get_hardware_address(&self) -> &[u8] {
+        &self.chaddr[..(self.hlen as usize).min(CHADDR_SIZE)]
+    }
+
+    pub fn get_mac_address(&self) -> Option<MacAddress> {
+        let bytes: [u8; 6] = self.get_hardware_address().try_into().ok()?;
+        Some(MacAddress::new(bytes))
+    }
+
+    pub fn get_server_name(&self) -> Option<String> {
+        Self::get_field_string(&self.sname)
+    }
+
+    pub fn get_boot_file(&self) -> Option<String> {
+        Self::get_field_string(&self.file)
+    }
+
     pub fn get_lease_time(&self) -> Option<Duration> {
-        let body = self.get_option_body(DhcpOptionType::LeaseTime);
-        match body {
-            Some(b) => Some(Duration::from_secs(
-                u32::from_be_bytes(b.try_into().unwrap()) as u64, // assume BigEndian, should be safe on the net
-            )),
-            None => None,
+        match self.get_option(DhcpOptionType::LeaseTime) {
+            Some(DhcpOptionValue::LeaseTime(t)) => Some(t),
+            _ => None,
+        }
+    }
+
+    pub fn get_renewal_time(&self) -> Option<Duration> {
+        match self.get_option(DhcpOptionType::RenewalTime) {
+            Some(DhcpOptionValue::RenewalTime(t)) => Some(t),
+            _ => None,
+        }
+    }
+
+    pub fn get_rebinding_time(&self) -> Option<Duration> {
+        match self.get_option(DhcpOptionType::RebindingTime) {
+            Some(DhcpOptionValue::RebindingTime(t)) => Some(t),
+            _ => None,
         }
     }
 
     pub fn get_server_ip(&self) -> Option<Ipv4Addr> {
-        let body = self.get_option_body(DhcpOptionType::DhcpServerIp);
-        Self::get_option_ip_like(body)
+        match self.get_option(DhcpOptionType::DhcpServerIp) {
+            Some(DhcpOptionValue::DhcpServerIp(ip)) => Some(ip),
+            _ => None,
+        }
     }
 
     pub fn get_subnet(&self) -> Option<Ipv4Addr> {
-        let body = self.get_option_body(DhcpOptionType::SubnetMask);
-        Self::get_option_ip_like(body)
+        match self.get_option(DhcpOptionType::SubnetMask) {
+            Some(DhcpOptionValue::SubnetMask(ip)) => Some(ip),
+            _ => None,
+        }
     }
 
     pub fn get_router_ip(&self) -> Option<Ipv4Addr> {
-        let body = self.get_option_body(DhcpOptionType::RouterIp);
-        Self::get_option_ip_like(body)
+        match self.get_option(DhcpOptionType::RouterIp) {
+            Some(DhcpOptionValue::RouterIp(ips)) => ips.first().cloned(),
+            _ => None,
+        }
+    }
+
+    pub fn get_dns_servers(&self) -> Vec<Ipv4Addr> {
+        match self.get_option(DhcpOptionType::DnsServerIp) {
+            Some(DhcpOptionValue::DnsServerIp(ips)) => ips,
+            _ => vec![],
+        }
     }
 
-    fn get_option_body(&self, otype: DhcpOptionType) -> Option<&[u8]> {
-        let body = self.options.iter().find(|x| x.id == otype as u8);
-        match body {
-            Some(b) => Some(&b.body),
-            None => None,
+    pub fn get_ntp_servers(&self) -> Vec<Ipv4Addr> {
+        match self.get_option(DhcpOptionType::NtpServers) {
+            Some(DhcpOptionValue::NtpServers(ips)) => ips,
+            _ => vec![],
         }
     }
 
-    fn get_option_ip_like(body: Option<&[u8]>) -> Option<Ipv4Addr> {
-        match body {
-            Some(b) => Some(Ipv4Addr::from(u32::from_be_bytes(b.try_into().unwrap()))), // assume BigEndian, should be safe on the net
-            None => None,
+    pub fn get_domain_name(&self) -> Option<String> {
+        match self.get_option(DhcpOptionType::DomainName) {
+            Some(DhcpOptionValue::DomainName(name)) => Some(name),
+            _ => None,
         }
     }
+
+    pub fn get_time_offset(&self) -> Option<i32> {
+        match self.get_option(DhcpOptionType::TimeOffset) {
+            Some(DhcpOptionValue::TimeOffset(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_time_servers(&self) -> Vec<Ipv4Addr> {
+        match self.get_option(DhcpOptionType::TimeServers) {
+            Some(DhcpOptionValue::TimeServers(ips)) => ips,
+            _ => vec![],
+        }
+    }
+
+    pub fn get_log_servers(&self) -> Vec<Ipv4Addr> {
+        match self.get_option(DhcpOptionType::LogServers) {
+            Some(DhcpOptionValue::LogServers(ips)) => ips,
+            _ => vec![],
+        }
+    }
+
+    pub fn get_host_name(&self) -> Option<String> {
+        match self.get_option(DhcpOptionType::HostName) {
+            Some(DhcpOptionValue::HostName(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn get_root_path(&self) -> Option<String> {
+        match self.get_option(DhcpOptionType::RootPath) {
+            Some(DhcpOptionValue::RootPath(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn get_default_ip_ttl(&self) -> Option<u8> {
+        match self.get_option(DhcpOptionType::DefaultIpTtl) {
+            Some(DhcpOptionValue::DefaultIpTtl(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_interface_mtu(&self) -> Option<u16> {
+        match self.get_option(DhcpOptionType::InterfaceMtu) {
+            Some(DhcpOptionValue::InterfaceMtu(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_broadcast_address(&self) -> Option<Ipv4Addr> {
+        match self.get_option(DhcpOptionType::BroadcastAddress) {
+            Some(DhcpOptionValue::BroadcastAddress(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_static_routes(&self) -> Vec<(Ipv4Addr, Ipv4Addr)> {
+        match self.get_option(DhcpOptionType::StaticRoutes) {
+            Some(DhcpOptionValue::StaticRoutes(routes)) => routes,
+            _ => vec![],
+        }
+    }
+
+    pub fn get_nis_domain(&self) -> Option<String> {
+        match self.get_option(DhcpOptionType::NisDomain) {
+            Some(DhcpOptionValue::NisDomain(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn get_nis_servers(&self) -> Vec<Ipv4Addr> {
+        match self.get_option(DhcpOptionType::NisServers) {
+            Some(DhcpOptionValue::NisServers(ips)) => ips,
+            _ => vec![],
+        }
+    }
+
+    pub fn get_vendor_specific(&self) -> Option<Vec<u8>> {
+        match self.get_option(DhcpOptionType::VendorSpecific) {
+            Some(DhcpOptionValue::VendorSpecific(bytes)) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn get_netbios_name_servers(&self) -> Vec<Ipv4Addr> {
+        match self.get_option(DhcpOptionType::NetbiosNameServers) {
+            Some(DhcpOptionValue::NetbiosNameServers(ips)) => ips,
+            _ => vec![],
+        }
+    }
+
+    pub fn get_netbios_node_type(&self) -> Option<u8> {
+        match self.get_option(DhcpOptionType::NetbiosNodeType) {
+            Some(DhcpOptionValue::NetbiosNodeType(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_netbios_scope(&self) -> Option<String> {
+        match self.get_option(DhcpOptionType::NetbiosScope) {
+            Some(DhcpOptionValue::NetbiosScope(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn get_requested_ip(&self) -> Option<Ipv4Addr> {
+        match self.get_option(DhcpOptionType::RequestedIp) {
+            Some(DhcpOptionValue::RequestedIp(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_parameter_request(&self) -> Vec<u8> {
+        match self.get_option(DhcpOptionType::ParameterRequest) {
+            Some(DhcpOptionValue::ParameterRequest(bytes)) => bytes,
+            _ => vec![],
+        }
+    }
+
+    pub fn get_message(&self) -> Option<String> {
+        match self.get_option(DhcpOptionType::Message) {
+            Some(DhcpOptionValue::Message(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn get_max_message_size(&self) -> Option<u16> {
+        match self.get_option(DhcpOptionType::MaxMessageSize) {
+            Some(DhcpOptionValue::MaxMessageSize(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_vendor_class(&self) -> Option<Vec<u8>> {
+        match self.get_option(DhcpOptionType::VendorClass) {
+            Some(DhcpOptionValue::VendorClass(bytes)) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn get_client_id(&self) -> Option<Vec<u8>> {
+        match self.get_option(DhcpOptionType::ClientId) {
+            Some(DhcpOptionValue::ClientId(id)) => Some(id),
+            _ => None,
+        }
+    }
+
+    pub fn get_tftp_server_name(&self) -> Option<String> {
+        match self.get_option(DhcpOptionType::TftpServerName) {
+            Some(DhcpOptionValue::TftpServerName(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn get_boot_file_name(&self) -> Option<String> {
+        match self.get_option(DhcpOptionType::BootFileName) {
+            Some(DhcpOptionValue::BootFileName(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn get_smtp_servers(&self) -> Vec<Ipv4Addr> {
+        match self.get_option(DhcpOptionType::SmtpServers) {
+            Some(DhcpOptionValue::SmtpServers(ips)) => ips,
+            _ => vec![],
+        }
+    }
+
+    pub fn is_rapid_commit(&self) -> bool {
+        matches!(
+            self.get_option(DhcpOptionType::RapidCommit),
+            Some(DhcpOptionValue::RapidCommit)
+        )
+    }
+
+    pub fn get_client_fqdn(&self) -> Option<ClientFqdn> {
+        match self.get_option(DhcpOptionType::ClientFqdn) {
+            Some(DhcpOptionValue::ClientFqdn(fqdn)) => Some(fqdn),
+            _ => None,
+        }
+    }
+
+    pub fn get_domain_search(&self) -> Vec<String> {
+        match self.get_option(DhcpOptionType::DomainSearch) {
+            Some(DhcpOptionValue::DomainSearch(names)) => names,
+            _ => vec![],
+        }
+    }
+
+    // sname and file hold null terminated strings, unused when empty
+    fn get_field_string(field: &[u8]) -> Option<String> {
+        let len = field.iter().position(|x| *x == 0).unwrap_or(field.len());
+        if len == 0 {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&field[..len]).into_owned())
+    }
+
+    // Spreads the options over the options area and, when they do not fit, the unused
+    // file and sname fields, see RFC 2131 section 4.1.
+    // Returns the encoded options area, file and sname
+    fn layout_options(&self) -> (Vec<u8>, [u8; FILE_SIZE], [u8; SNAME_SIZE]) {
+        let end = DhcpOptionType::End as u8;
+        let encoded: Vec<Vec<u8>> = self
+            .options
+            .iter()
+            .filter(|x| x.id != DhcpOptionType::OptionOverload as u8)
+            .flat_map(|x| x.split())
+            .map(|x| x.serialize())
+            .collect();
+        let total_size: usize = encoded.iter().map(|x| x.len()).sum();
+        let file_free = self.file.iter().all(|x| *x == 0);
+        let sname_free = self.sname.iter().all(|x| *x == 0);
+        if total_size < OPTIONS_SIZE || !(file_free || sname_free) {
+            let mut main = encoded.concat();
+            main.push(end);
+            return (main, self.file, self.sname);
+        }
+
+        // Each area keeps a byte for its End option, the main area also fits the overload option
+        let mut remaining = encoded.into_iter().peekable();
+        let mut areas = vec![];
+        for capacity in [
+            OPTIONS_SIZE - 4,
+            if file_free { FILE_SIZE - 1 } else { 0 },
+            if sname_free { SNAME_SIZE - 1 } else { 0 },
+        ] {
+            let mut area = vec![];
+            while let Some(next) = remaining.peek() {
+                if area.len() + next.len() > capacity {
+                    break;
+                }
+                area.extend(remaining.next().unwrap());
+            }
+            areas.push(area);
+        }
+        // Anything that still does not fit makes for an oversized message
+        areas[0].extend(remaining.flatten());
+
+        let mut overload = 0;
+        let mut file = self.file;
+        let mut sname = self.sname;
+        if !areas[1].is_empty() {
+            overload |= OVERLOAD_FILE;
+            file[..areas[1].len()].copy_from_slice(&areas[1]);
+            file[areas[1].len()] = end;
+        }
+        if !areas[2].is_empty() {
+            overload |= OVERLOAD_SNAME;
+            sname[..areas[2].len()].copy_from_slice(&areas[2]);
+            sname[areas[2].len()] = end;
+        }
+        let mut main = vec![];
+        if overload != 0 {
+            main.extend(
+                DhcpOption::new(DhcpOptionType::OptionOverload as u8, vec![overload]).serialize(),
+            );
+        }
+        main.extend(&areas[0]);
+        main.push(end);
+        (main, file, sname)
+    }
+
+    pub fn get_option(&self, otype: DhcpOptionType) -> Option<DhcpOptionValue> {
+        self.options
+            .iter()
+            .find(|x| x.id == otype as u8)
+            .map(DhcpOptionValue::decode)
+    }
+
+    pub fn get_options(&self) -> Vec<DhcpOptionValue> {
+        self.options.iter().map(DhcpOptionValue::decode).collect()
+    }
 }
 
 impl Default for DhcpPacket {
@@ -182,13 +749,13 @@ impl Serialize for DhcpPacket {
         buffer.extend_from_slice(&self.siaddr);
         buffer.extend_from_slice(&self.giaddr);
         buffer.extend_from_slice(&self.chaddr);
-        buffer.extend_from_slice(&self.cookie);
 
-        self.options
-            .iter()
-            .for_each(|x| buffer.extend_from_slice(&x.serialize()));
         // Options list needs to finish with the END option
-        buffer.push(DhcpOptionType::End as u8);
+        let (options, file, sname) = self.layout_options();
+        buffer.extend_from_slice(&sname);
+        buffer.extend_from_slice(&file);
+        buffer.extend_from_slice(&self.cookie);
+        buffer.extend_from_slice(&options);
 
         // Align buffer to 32 bytes
         let buffer_byte_len = buffer.len();
@@ -200,7 +767,14 @@ impl Serialize for DhcpPacket {
 
 impl Deserialize for DhcpPacket {
     type Out = Self;
-    fn deserialize(data: &[u8]) -> Option<Self::Out> {
+    type Error = DhcpParseError;
+    fn deserialize(data: &[u8]) -> Result<Self::Out, Self::Error> {
+        if data.len() < HEADER_SIZE {
+            return Err(DhcpParseError::TruncatedHeader {
+                offset: data.len(),
+                expected: HEADER_SIZE,
+            });
+        }
         // All of these try intos are to make unsized &[u8] into fixed sized [u8, n]
         // failure will only be possible if changing the data structure of DhcpPacket
         let op = data[0];
@@ -214,11 +788,38 @@ impl Deserialize for DhcpPacket {
         let yiaddr = data[16..20].try_into().unwrap();
         let siaddr = data[20..24].try_into().unwrap();
         let giaddr = data[24..28].try_into().unwrap();
-        let chaddr = data[28..236].try_into().unwrap();
+        let chaddr = data[28..SNAME_OFFSET].try_into().unwrap();
+        let mut sname: [u8; SNAME_SIZE] = data[SNAME_OFFSET..FILE_OFFSET].try_into().unwrap();
+        let mut file: [u8; FILE_SIZE] = data[FILE_OFFSET..236].try_into().unwrap();
         let cookie = data[236..240].try_into().unwrap();
-        let options = DhcpOption::deserialize(&data[240..]).unwrap();
+        if cookie != MAGIC_COOKIE {
+            return Err(DhcpParseError::BadMagicCookie {
+                offset: 236,
+                cookie,
+            });
+        }
+        let (mut options, ended) = DhcpOption::parse_options(&data[HEADER_SIZE..], HEADER_SIZE)?;
+        if !ended {
+            return Err(DhcpParseError::MissingEnd { offset: data.len() });
+        }
 
-        Some(DhcpPacket {
+        // With Option Overload the file and then sname fields carry further options.
+        // Those fields are fixed size, so a missing End there is not an error
+        let overload_id = DhcpOptionType::OptionOverload as u8;
+        if let Some(index) = options.iter().position(|x| x.id == overload_id) {
+            let overload = options.remove(index).body.first().cloned().unwrap_or(0);
+            if overload & OVERLOAD_FILE != 0 {
+                options.extend(DhcpOption::parse_options(&file, FILE_OFFSET)?.0);
+                file = [0x00; FILE_SIZE];
+            }
+            if overload & OVERLOAD_SNAME != 0 {
+                options.extend(DhcpOption::parse_options(&sname, SNAME_OFFSET)?.0);
+                sname = [0x00; SNAME_SIZE];
+            }
+        }
+        let options = DhcpOption::concatenate(options);
+
+        Ok(DhcpPacket {
             op,
             htype,
             hlen,
@@ -231,6 +832,8 @@ impl Deserialize for DhcpPacket {
             siaddr,
             giaddr,
             chaddr,
+            sname,
+            file,
             cookie,
             options,
         })
@@ -241,33 +844,73 @@ impl DhcpOption {
     pub fn new(id: u8, body: Vec<u8>) -> Self {
         DhcpOption { id, body }
     }
-}
 
-impl Serialize for DhcpOption {
-    type Out = Vec<u8>;
-    fn serialize(&self) -> Self::Out {
-        let mut buffer = Vec::new();
+    pub fn get_id(&self) -> u8 {
+        self.id
+    }
 
-        buffer.extend_from_slice(&[self.id]);
-        buffer.extend_from_slice(&[self.body.len().try_into().unwrap()]);
-        buffer.extend_from_slice(&self.body);
-        buffer
+    pub fn get_body(&self) -> &[u8] {
+        &self.body
     }
-}
 
-impl Deserialize for DhcpOption {
-    type Out = Vec<Self>;
-    fn deserialize(data: &[u8]) -> Option<Self::Out> {
-        let data = data.to_vec();
+    // Long options go out as consecutive instances, each at most 255 bytes long
+    fn split(&self) -> Vec<Self> {
+        if self.body.len() <= MAX_OPTION_SIZE {
+            return vec![self.clone()];
+        }
+        self.body
+            .chunks(MAX_OPTION_SIZE)
+            .map(|x| DhcpOption::new(self.id, x.to_vec()))
+            .collect()
+    }
+
+    // Joins every instance of an option into the first one, in the order they were found.
+    // RFC 3396 section 7 asks this of all repeated options, not just the long ones
+    fn concatenate(options: Vec<Self>) -> Vec<Self> {
+        let mut joined: Vec<Self> = vec![];
+        for option in options {
+            match joined.iter_mut().find(|x| x.id == option.id) {
+                Some(first) => first.body.extend(option.body),
+                None => joined.push(option),
+            }
+        }
+        joined
+    }
+
+    // Parses options up to the End option, returning them along with whether End was seen.
+    // `base` is where data sits in the packet, so errors point at the right byte
+    fn parse_options(data: &[u8], base: usize) -> Result<(Vec<Self>, bool), DhcpParseError> {
         let mut cursor = 0;
         let mut option_buffer = vec![];
         while cursor < data.len() {
             let id = data[cursor];
             if id == DhcpOptionType::End as u8 {
-                break;
+                return Ok((option_buffer, true));
+            }
+            // Pad is a lone byte without a length
+            if id == DhcpOptionType::Pad as u8 {
+                cursor += 1;
+                continue;
             }
-            let len = data[cursor + 1] as usize;
-            let body = &data[(cursor + 2)..((cursor + 2) + len)];
+            let len = match data.get(cursor + 1) {
+                Some(len) => *len as usize,
+                None => {
+                    return Err(DhcpParseError::MissingOptionLength {
+                        offset: base + cursor,
+                        id,
+                    })
+                }
+            };
+            let body = match data.get((cursor + 2)..((cursor + 2) + len)) {
+                Some(body) => body,
+                None => {
+                    return Err(DhcpParseError::OptionOverrun {
+                        offset: base + cursor,
+                        id,
+                        length: len,
+                    })
+                }
+            };
 
             option_buffer.push(DhcpOption {
                 id,
@@ -275,13 +918,37 @@ impl Deserialize for DhcpOption {
             });
             cursor = cursor + 2 + len;
         }
-        Some(option_buffer)
+        Ok((option_buffer, false))
+    }
+}
+
+impl Serialize for DhcpOption {
+    type Out = Vec<u8>;
+    fn serialize(&self) -> Self::Out {
+        let mut buffer = Vec::new();
+
+        for part in self.split() {
+            buffer.extend_from_slice(&[part.id]);
+            buffer.extend_from_slice(&[part.body.len() as u8]);
+            buffer.extend_from_slice(&part.body);
+        }
+        buffer
+    }
+}
+
+impl Deserialize for DhcpOption {
+    type Out = Vec<Self>;
+    type Error = DhcpParseError;
+    fn deserialize(data: &[u8]) -> Result<Self::Out, Self::Error> {
+        let (options, _) = Self::parse_options(data, 0)?;
+        Ok(options)
     }
 }
 
 #[cfg(test)]
 mod dhcp_packet {
     use super::*;
+    use num_traits::FromPrimitive;
     use pretty_assertions::assert_eq;
     use rand::random;
 
@@ -344,21 +1011,10 @@ mod dhcp_packet {
             giaddr: [0; 4],
             chaddr: [
                 0x10, 0x7b, 0x44, 0x93, 0xe6, 0xd0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00,
             ],
+            sname: [0; 64],
+            file: [0; 128],
             cookie: [0x63, 0x82, 0x53, 0x63],
             options: vec![
                 DhcpOption::new(53, vec![1]),
@@ -439,21 +1095,10 @@ mod dhcp_packet {
             giaddr: [0; 4],
             chaddr: [
                 0x10, 0x7b, 0x44, 0x93, 0xe6, 0xd0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00,
             ],
+            sname: [0; 64],
+            file: [0; 128],
             cookie: [0x63, 0x82, 0x53, 0x63],
             options: vec![
                 DhcpOption::new(53, vec![1]),
@@ -484,8 +1129,10 @@ mod dhcp_packet {
                 yiaddr: random(),
                 siaddr: random(),
                 giaddr: random(),
-                chaddr: [0; 208], // Random cant generated arrays this big, so skip this for now
-                cookie: random(),
+                chaddr: random(),
+                sname: [0; 64], // Random cant generated arrays this big, so skip this for now
+                file: [0; 128],
+                cookie: MAGIC_COOKIE,
                 options: vec![], // TODO Need a good way to generate valid options
             };
 
@@ -519,4 +1166,309 @@ mod dhcp_packet {
         assert!(packet_b.is_transaction(&token_b));
         assert!(!packet_b.is_transaction(&token_a));
     }
+
+    #[test]
+    fn test_packet_timers() {
+        let packet = DhcpPacket::new()
+            .with_option(DhcpOption::new(51, vec![0, 0, 0x0e, 0x10]))
+            .with_option(DhcpOption::new(58, vec![0, 0, 0x07, 0x08]))
+            .with_option(DhcpOption::new(59, vec![0, 0, 0x0c, 0x4e]));
+
+        assert_eq!(packet.get_lease_time(), Some(Duration::from_secs(3600)));
+        assert_eq!(packet.get_renewal_time(), Some(Duration::from_secs(1800)));
+        assert_eq!(packet.get_rebinding_time(), Some(Duration::from_secs(3150)));
+        assert_eq!(DhcpPacket::new().get_renewal_time(), None);
+    }
+
+    #[test]
+    fn test_packet_current_ip() {
+        let ip = Ipv4Addr::new(192, 168, 1, 99);
+        let packet = DhcpPacket::new()
+            .with_current_ip(&ip)
+            .with_option(DhcpOption::new(53, vec![7]));
+
+        assert!(packet.is_type(DhcpMessageType::Release));
+        assert_eq!(packet.get_current_ip(), ip);
+        assert_eq!(packet.get_client_ip(), Ipv4Addr::UNSPECIFIED);
+    }
+
+    #[test]
+    fn test_packet_name_servers() {
+        let packet = DhcpPacket::new()
+            .with_option(DhcpOption::new(6, vec![1, 1, 1, 1, 8, 8, 8, 8]))
+            .with_option(DhcpOption::new(15, b"example.com".to_vec()))
+            .with_option(DhcpOption::new(42, vec![192, 168, 1, 1]));
+
+        assert_eq!(
+            packet.get_dns_servers(),
+            vec![Ipv4Addr::new(1, 1, 1, 1), Ipv4Addr::new(8, 8, 8, 8)]
+        );
+        assert_eq!(packet.get_domain_name(), Some("example.com".to_string()));
+        assert_eq!(
+            packet.get_ntp_servers(),
+            vec![Ipv4Addr::new(192, 168, 1, 1)]
+        );
+        assert!(DhcpPacket::new().get_dns_servers().is_empty());
+    }
+
+    #[test]
+    fn test_packet_typed_options() {
+        let packet = DhcpPacket::new()
+            .with_option(DhcpOptionValue::MessageType(DhcpMessageType::Offer))
+            .with_option(DhcpOption::new(3, vec![192, 168, 1, 1, 192, 168, 1, 2]))
+            .with_option(DhcpOption::new(1, vec![255, 255]));
+
+        assert_eq!(
+            packet.get_options(),
+            vec![
+                DhcpOptionValue::MessageType(DhcpMessageType::Offer),
+                DhcpOptionValue::RouterIp(vec![
+                    Ipv4Addr::new(192, 168, 1, 1),
+                    Ipv4Addr::new(192, 168, 1, 2)
+                ]),
+                DhcpOptionValue::Unknown(1, vec![255, 255]),
+            ]
+        );
+        assert_eq!(packet.get_router_ip(), Some(Ipv4Addr::new(192, 168, 1, 1)));
+        // A malformed subnet mask is ignored rather than panicking
+        assert_eq!(packet.get_subnet(), None);
+    }
+
+    #[test]
+    fn test_packet_rfc2132_accessors() {
+        let packet = DhcpPacket::new()
+            .with_option(DhcpOptionValue::TimeOffset(3600))
+            .with_option(DhcpOptionValue::InterfaceMtu(1400))
+            .with_option(DhcpOptionValue::BroadcastAddress(Ipv4Addr::new(
+                192, 168, 1, 255,
+            )))
+            .with_option(DhcpOptionValue::StaticRoutes(vec![(
+                Ipv4Addr::new(10, 0, 0, 0),
+                Ipv4Addr::new(192, 168, 1, 254),
+            )]))
+            .with_option(DhcpOptionValue::TftpServerName("tftp".to_string()))
+            .with_option(DhcpOptionValue::Message("hello".to_string()));
+
+        assert_eq!(packet.get_time_offset(), Some(3600));
+        assert_eq!(packet.get_interface_mtu(), Some(1400));
+        assert_eq!(
+            packet.get_broadcast_address(),
+            Some(Ipv4Addr::new(192, 168, 1, 255))
+        );
+        assert_eq!(
+            packet.get_static_routes(),
+            vec![(Ipv4Addr::new(10, 0, 0, 0), Ipv4Addr::new(192, 168, 1, 254))]
+        );
+        assert_eq!(packet.get_tftp_server_name(), Some("tftp".to_string()));
+        assert_eq!(packet.get_message(), Some("hello".to_string()));
+        assert!(packet.get_smtp_servers().is_empty());
+        assert_eq!(packet.get_vendor_class(), None);
+    }
+
+    #[test]
+    fn test_option_type_names() {
+        let otype: DhcpOptionType = FromPrimitive::from_u8(26).unwrap();
+
+        assert_eq!(otype, DhcpOptionType::InterfaceMtu);
+        assert_eq!(otype.get_name(), "Interface MTU");
+        assert_eq!(otype.to_string(), "Interface MTU (26)");
+        assert_eq!(DhcpOptionType::RouterIp.get_name(), "Router");
+        assert!(<DhcpOptionType as FromPrimitive>::from_u8(62).is_none());
+    }
+
+    #[test]
+    fn test_deserialize_pad_option() {
+        assert_eq!(
+            DhcpOption::deserialize(&[0x00, 0x00, 0x35, 0x01, 0x01, 0xff]).unwrap(),
+            vec![DhcpOption::new(53, vec![1])]
+        );
+    }
+
+    #[test]
+    fn test_deserialize_truncated_header() {
+        let packet = DhcpPacket::new().serialize();
+
+        assert_eq!(
+            DhcpPacket::deserialize(&packet[..100]),
+            Err(DhcpParseError::TruncatedHeader {
+                offset: 100,
+                expected: 240
+            })
+        );
+        assert_eq!(
+            DhcpPacket::deserialize(&[]),
+            Err(DhcpParseError::TruncatedHeader {
+                offset: 0,
+                expected: 240
+            })
+        );
+    }
+
+    #[test]
+    fn test_deserialize_bad_cookie() {
+        let mut packet = DhcpPacket::new().serialize();
+        packet[236] = 0x00;
+
+        let error = DhcpPacket::deserialize(&packet).unwrap_err();
+        assert_eq!(
+            error,
+            DhcpParseError::BadMagicCookie {
+                offset: 236,
+                cookie: [0x00, 0x82, 0x53, 0x63]
+            }
+        );
+        assert_eq!(error.get_offset(), 236);
+    }
+
+    #[test]
+    fn test_deserialize_option_overrun() {
+        let mut packet = DhcpPacket::new().serialize()[..240].to_vec();
+        packet.extend_from_slice(&[0x35, 0x01, 0x01, 0x32, 0x04, 0xc0, 0xa8]);
+
+        assert_eq!(
+            DhcpPacket::deserialize(&packet),
+            Err(DhcpParseError::OptionOverrun {
+                offset: 243,
+                id: 50,
+                length: 4
+            })
+        );
+    }
+
+    #[test]
+    fn test_deserialize_missing_option_length() {
+        assert_eq!(
+            DhcpOption::deserialize(&[0x35, 0x01, 0x01, 0x32]),
+            Err(DhcpParseError::MissingOptionLength { offset: 3, id: 50 })
+        );
+    }
+
+    #[test]
+    fn test_deserialize_missing_end() {
+        let mut packet = DhcpPacket::new().serialize()[..240].to_vec();
+        packet.extend_from_slice(&[0x35, 0x01, 0x01]);
+
+        assert_eq!(
+            DhcpPacket::deserialize(&packet),
+            Err(DhcpParseError::MissingEnd { offset: 243 })
+        );
+    }
+
+    #[test]
+    fn test_deserialize_random_garbage() {
+        // Whatever arrives on the wire must never panic the parser
+        for len in 0..400 {
+            let data: Vec<u8> = (0..len).map(|_| random()).collect();
+            let _ = DhcpPacket::deserialize(&data);
+            let _ = DhcpOption::deserialize(&data);
+        }
+    }
+
+    #[test]
+    fn test_packet_header_fields() {
+        let mac = MacAddress::new([0x10, 0x7b, 0x44, 0x93, 0xe6, 0xd0]);
+        let packet = DhcpPacket::new()
+            .with_mac_address(&mac)
+            .with_seconds(300)
+            .with_next_server_ip(&Ipv4Addr::new(192, 168, 1, 5))
+            .with_server_name("tftp.example.com")
+            .with_boot_file("pxelinux.0");
+
+        let serialized = packet.serialize();
+        assert_eq!(&serialized[8..10], &[0x01, 0x2c]);
+        assert_eq!(&serialized[28..34], &mac.bytes());
+        assert_eq!(&serialized[44..60], b"tftp.example.com");
+        assert_eq!(&serialized[108..118], b"pxelinux.0");
+
+        let parsed = DhcpPacket::deserialize(&serialized).unwrap();
+        assert_eq!(parsed.get_mac_address(), Some(mac));
+        assert_eq!(parsed.get_seconds(), 300);
+        assert_eq!(parsed.get_next_server_ip(), Ipv4Addr::new(192, 168, 1, 5));
+        assert_eq!(
+            parsed.get_server_name(),
+            Some("tftp.example.com".to_string())
+        );
+        assert_eq!(parsed.get_boot_file(), Some("pxelinux.0".to_string()));
+        assert_eq!(DhcpPacket::new().get_server_name(), None);
+        assert_eq!(DhcpPacket::new().get_boot_file(), None);
+    }
+
+    #[test]
+    fn test_deserialize_overloaded_options() {
+        let mut packet = DhcpPacket::new().serialize()[..240].to_vec();
+        // Domain name in file, host name in sname
+        packet[108..113].copy_from_slice(&[15, 3, b'l', b'a', b'n']);
+        packet[113] = 0xff;
+        packet[44..50].copy_from_slice(&[12, 4, b'h', b'o', b's', b't']);
+        packet[50] = 0xff;
+        packet.extend_from_slice(&[0x35, 0x01, 0x02, 0x34, 0x01, 0x03, 0xff]);
+
+        let parsed = DhcpPacket::deserialize(&packet).unwrap();
+        assert_eq!(
+            parsed.get_options(),
+            vec![
+                DhcpOptionValue::MessageType(DhcpMessageType::Offer),
+                DhcpOptionValue::DomainName("lan".to_string()),
+                DhcpOptionValue::HostName("host".to_string()),
+            ]
+        );
+        assert_eq!(parsed.get_server_name(), None);
+        assert_eq!(parsed.get_boot_file(), None);
+    }
+
+    #[test]
+    fn test_serialize_overloaded_options() {
+        // 50 options of 9 bytes each need the options area, file and sname
+        let packet = (0..50).fold(DhcpPacket::new(), |packet, x| {
+            packet.with_option(DhcpOption::new(150 + x, vec![x; 7]))
+        });
+
+        let serialized = packet.serialize();
+        assert_eq!(&serialized[240..243], &[52, 1, 3]);
+        let end = serialized[240..].iter().position(|x| *x == 0xff).unwrap();
+        assert!(end < 308);
+        assert_eq!(DhcpPacket::deserialize(&serialized).unwrap(), packet);
+    }
+
+    #[test]
+    fn test_long_options() {
+        let option = DhcpOption::new(224, (0..600).map(|x| x as u8).collect());
+        let serialized = option.serialize();
+        assert_eq!(serialized.len(), 606);
+        assert_eq!(&serialized[..2], &[224, 255]);
+        assert_eq!(&serialized[257..259], &[224, 255]);
+        assert_eq!(&serialized[514..516], &[224, 90]);
+
+        // Split across the options area and file, and joined again on the way in
+        let packet = DhcpPacket::new()
+            .with_option(DhcpOptionValue::MessageType(DhcpMessageType::Discover))
+            .with_option(DhcpOption::new(224, vec![7; 310]));
+        let serialized = packet.serialize();
+        assert_eq!(&serialized[240..243], &[52, 1, 1]);
+        assert_eq!(&serialized[108..110], &[224, 55]);
+        assert_eq!(DhcpPacket::deserialize(&serialized).unwrap(), packet);
+    }
+
+    #[test]
+    fn test_repeated_options_are_joined() {
+        let mut packet = DhcpPacket::new().serialize()[..240].to_vec();
+        packet.extend_from_slice(&[0x35, 0x01, 0x02, 0x0c, 0x02, b'h', b'o', 0x0c, 0x02]);
+        packet.extend_from_slice(&[b's', b't', 0xff]);
+
+        let parsed = DhcpPacket::deserialize(&packet).unwrap();
+        assert_eq!(parsed.get_host_name(), Some("host".to_string()));
+        assert_eq!(parsed.get_options().len(), 2);
+    }
+
+    #[test]
+    fn test_serialize_keeps_boot_file() {
+        let packet = (0..40).fold(
+            DhcpPacket::new().with_boot_file("pxelinux.0"),
+            |packet, x| packet.with_option(DhcpOption::new(150 + x, vec![x; 7])),
+        );
+
+        let serialized = packet.serialize();
+        assert_eq!(&serialized[240..243], &[52, 1, 2]);
+        assert_eq!(DhcpPacket::deserialize(&serialized).unwrap(), packet);
+    }
 }
@@ -0,0 +1,550 @@
+use std::{convert::TryInto, net::Ipv4Addr};
+
+#[cfg(target_os = "linux")]
+pub(crate) use linux::PacketSocket;
+#[cfg(target_os = "linux")]
+pub use linux::RawTransport;
+
+const ETHERNET_HEADER_SIZE: usize = 14;
+const IPV4_HEADER_SIZE: usize = 20;
+const UDP_HEADER_SIZE: usize = 8;
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const PROTOCOL_UDP: u8 = 17;
+const DEFAULT_TTL: u8 = 64;
+const BROADCAST_MAC: [u8; 6] = [0xff; 6];
+
+// A UDP datagram together with the link and network addresses it travelled with
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct UdpFrame {
+    src_mac: [u8; 6],
+    dst_mac: [u8; 6],
+    src_ip: Ipv4Addr,
+    dst_ip: Ipv4Addr,
+    src_port: u16,
+    dst_port: u16,
+    payload: Vec<u8>,
+}
+
+impl UdpFrame {
+    fn encode(&self) -> Vec<u8> {
+        let udp_length = UDP_HEADER_SIZE + self.payload.len();
+        let ip_length = IPV4_HEADER_SIZE + udp_length;
+
+        let mut ip_header = vec![0x45, 0x00];
+        ip_header.extend_from_slice(&(ip_length as u16).to_be_bytes());
+        // Identification, then flags and fragment offset, all unused
+        ip_header.extend_from_slice(&[0x00; 4]);
+        ip_header.extend_from_slice(&[DEFAULT_TTL, PROTOCOL_UDP, 0x00, 0x00]);
+        ip_header.extend_from_slice(&self.src_ip.octets());
+        ip_header.extend_from_slice(&self.dst_ip.octets());
+        let checksum = checksum(&[&ip_header]);
+        ip_header[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+        let mut udp = vec![];
+        udp.extend_from_slice(&self.src_port.to_be_bytes());
+        udp.extend_from_slice(&self.dst_port.to_be_bytes());
+        udp.extend_from_slice(&(udp_length as u16).to_be_bytes());
+        udp.extend_from_slice(&[0x00, 0x00]);
+        udp.extend_from_slice(&self.payload);
+        let checksum = match self.udp_checksum(&udp) {
+            // Zero means no checksum was computed, so it is sent as all ones instead
+            0 => 0xffff,
+            checksum => checksum,
+        };
+        udp[6..8].copy_from_slice(&checksum.to_be_bytes());
+
+        let mut frame = Vec::with_capacity(ETHERNET_HEADER_SIZE + ip_length);
+        frame.extend_from_slice(&self.dst_mac);
+        frame.extend_from_slice(&self.src_mac);
+        frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        frame.extend(ip_header);
+        frame.extend(udp);
+        frame
+    }
+
+    // Anything other than an intact, unfragmented IPv4 UDP datagram is ignored
+    fn decode(data: &[u8]) -> Option<Self> {
+        let ethernet = data.get(..ETHERNET_HEADER_SIZE)?;
+        if u16::from_be_bytes([ethernet[12], ethernet[13]]) != ETHERTYPE_IPV4 {
+            return None;
+        }
+
+        let ip = &data[ETHERNET_HEADER_SIZE..];
+        let header_length = (*ip.first()? & 0x0f) as usize * 4;
+        if ip[0] >> 4 != 4 || header_length < IPV4_HEADER_SIZE {
+            return None;
+        }
+        let ip_header = ip.get(..header_length)?;
+        let total_length = u16::from_be_bytes([ip_header[2], ip_header[3]]) as usize;
+        let fragmented = u16::from_be_bytes([ip_header[6], ip_header[7]]) & 0x3fff != 0;
+        if ip_header[9] != PROTOCOL_UDP || fragmented || checksum(&[ip_header]) != 0 {
+            return None;
+        }
+        let udp = ip.get(header_length..total_length)?;
+        let udp_length = u16::from_be_bytes([*udp.get(4)?, *udp.get(5)?]) as usize;
+        let udp = udp.get(..udp_length)?;
+        if udp_length < UDP_HEADER_SIZE {
+            return None;
+        }
+
+        let frame = UdpFrame {
+            src_mac: ethernet[6..12].try_into().ok()?,
+            dst_mac: ethernet[..6].try_into().ok()?,
+            src_ip: Ipv4Addr::new(ip_header[12], ip_header[13], ip_header[14], ip_header[15]),
+            dst_ip: Ipv4Addr::new(ip_header[16], ip_header[17], ip_header[18], ip_header[19]),
+            src_port: u16::from_be_bytes([udp[0], udp[1]]),
+            dst_port: u16::from_be_bytes([udp[2], udp[3]]),
+            payload: udp[UDP_HEADER_SIZE..].to_vec(),
+        };
+        let sent_checksum = u16::from_be_bytes([udp[6], udp[7]]);
+        if sent_checksum != 0 && frame.udp_checksum(udp) != 0 {
+            return None;
+        }
+        Some(frame)
+    }
+
+    // Covers the pseudo header of addresses, protocol and length, then the datagram itself
+    fn udp_checksum(&self, udp: &[u8]) -> u16 {
+        let mut pseudo_header = vec![];
+        pseudo_header.extend_from_slice(&self.src_ip.octets());
+        pseudo_header.extend_from_slice(&self.dst_ip.octets());
+        pseudo_header.extend_from_slice(&[0x00, PROTOCOL_UDP]);
+        pseudo_header.extend_from_slice(&(udp.len() as u16).to_be_bytes());
+        checksum(&[&pseudo_header, udp])
+    }
+}
+
+// Internet checksum, see RFC 1071. Every part but the last must be of even length
+fn checksum(parts: &[&[u8]]) -> u16 {
+    let mut sum: u32 = 0;
+    for part in parts {
+        for pair in part.chunks(2) {
+            let word = match pair {
+                [high, low] => u16::from_be_bytes([*high, *low]),
+                [high] => u16::from_be_bytes([*high, 0x00]),
+                _ => 0,
+            };
+            sum += word as u32;
+        }
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::*;
+    use crate::dhcp::{
+        packet::DhcpPacket,
+        traits::{Deserialize, Serialize},
+        transport::{DhcpTransport, CLIENT_PORT, SERVER_PORT},
+    };
+    use log::warn;
+    use mac_address::MacAddress;
+    use std::{
+        collections::HashMap,
+        ffi::CString,
+        io, mem,
+        os::unix::io::RawFd,
+        time::{Duration, Instant},
+    };
+
+    const BUFFER_SIZE: usize = 1536;
+    // Packet type of frames we sent ourselves, from linux/if_packet.h
+    const PACKET_OUTGOING: u8 = 4;
+
+    // Sends and receives DHCP over a named interface, whether or not it has an address yet
+    pub struct RawTransport {
+        socket: PacketSocket,
+        mac_address: [u8; 6],
+        local_port: u16,
+        remote_port: u16,
+        // Hardware addresses of the servers heard from, so unicasts can reach them
+        neighbours: HashMap<Ipv4Addr, [u8; 6]>,
+    }
+
+    impl RawTransport {
+        pub fn open(interface: &str, mac_address: &MacAddress) -> io::Result<Self> {
+            let socket = PacketSocket::open(interface, libc::SOCK_RAW, libc::ETH_P_IP as u16)?;
+            Ok(RawTransport {
+                socket,
+                mac_address: mac_address.bytes(),
+                local_port: CLIENT_PORT,
+                remote_port: SERVER_PORT,
+                neighbours: HashMap::new(),
+            })
+        }
+
+        // Swapping the ports turns this into the server side of the link
+        pub fn with_ports(mut self, local_port: u16, remote_port: u16) -> Self {
+            self.local_port = local_port;
+            self.remote_port = remote_port;
+            self
+        }
+    }
+
+    impl DhcpTransport for RawTransport {
+        fn send(&mut self, destination: Ipv4Addr, packet: &DhcpPacket) -> io::Result<()> {
+            // Unknown neighbours are still reached by a link layer broadcast
+            let dst_mac = self
+                .neighbours
+                .get(&destination)
+                .copied()
+                .unwrap_or(BROADCAST_MAC);
+            let frame = UdpFrame {
+                src_mac: self.mac_address,
+                dst_mac,
+                src_ip: packet.get_current_ip(),
+                dst_ip: destination,
+                src_port: self.local_port,
+                dst_port: self.remote_port,
+                payload: packet.serialize(),
+            };
+            self.socket.send_to(&frame.encode(), dst_mac)
+        }
+
+        fn receive(&mut self, timeout: Option<Duration>) -> io::Result<Option<DhcpPacket>> {
+            let deadline = timeout.map(|x| Instant::now() + x);
+            let mut buffer = [0; BUFFER_SIZE];
+            loop {
+                let size = match self.socket.recv(&mut buffer, deadline)? {
+                    Some(size) => size,
+                    None => return Ok(None),
+                };
+                let frame = match UdpFrame::decode(&buffer[..size]) {
+                    Some(frame) if frame.dst_port == self.local_port => frame,
+                    _ => continue,
+                };
+                match DhcpPacket::deserialize(&frame.payload) {
+                    Ok(packet) => {
+                        if !frame.src_ip.is_unspecified() {
+                            self.neighbours.insert(frame.src_ip, frame.src_mac);
+                        }
+                        return Ok(Some(packet));
+                    }
+                    Err(e) => warn!("Dropping unparseable packet: {}", e),
+                }
+            }
+        }
+    }
+
+    // An AF_PACKET socket bound to one interface
+    pub(crate) struct PacketSocket {
+        fd: RawFd,
+        ifindex: i32,
+        protocol: u16,
+    }
+
+    impl PacketSocket {
+        // SOCK_RAW frames carry the Ethernet header, SOCK_DGRAM frames start after it
+        pub(crate) fn open(interface: &str, kind: i32, protocol: u16) -> io::Result<Self> {
+            let name = CString::new(interface)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Bad interface name"))?;
+            let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) } as i32;
+            if ifindex == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let fd = unsafe { libc::socket(libc::AF_PACKET, kind, protocol.to_be() as i32) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let socket = PacketSocket {
+                fd,
+                ifindex,
+                protocol,
+            };
+
+            let addr = socket.link_address([0x00; 6]);
+            let result = unsafe {
+                libc::bind(
+                    fd,
+                    &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                    mem::size_of::<libc::sockaddr_ll>() as u32,
+                )
+            };
+            if result < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(socket)
+        }
+
+        fn link_address(&self, maddr: [u8; 6]) -> libc::sockaddr_ll {
+            let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+            addr.sll_family = libc::AF_PACKET as u16;
+            addr.sll_protocol = self.protocol.to_be();
+            addr.sll_ifindex = self.ifindex;
+            addr.sll_halen = 6;
+            addr.sll_addr[..6].copy_from_slice(&maddr);
+            addr
+        }
+
+        pub(crate) fn send_to(&self, data: &[u8], maddr: [u8; 6]) -> io::Result<()> {
+            let addr = self.link_address(maddr);
+            let result = unsafe {
+                libc::sendto(
+                    self.fd,
+                    data.as_ptr() as *const libc::c_void,
+                    data.len(),
+                    0,
+                    &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                    mem::size_of::<libc::sockaddr_ll>() as u32,
+                )
+            };
+            if result < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        // Waits for the next frame received from the wire, skipping our own outgoing ones.
+        // Returns Ok(None) once the deadline passes
+        pub(crate) fn recv(
+            &self,
+            buffer: &mut [u8],
+            deadline: Option<Instant>,
+        ) -> io::Result<Option<usize>> {
+            loop {
+                let timeout = match deadline {
+                    Some(deadline) => {
+                        let timeout = deadline.saturating_duration_since(Instant::now());
+                        if timeout == Duration::from_secs(0) {
+                            return Ok(None);
+                        }
+                        timeout.as_millis().clamp(1, i32::MAX as u128) as i32
+                    }
+                    None => -1,
+                };
+                let mut pfd = libc::pollfd {
+                    fd: self.fd,
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                let ready = unsafe { libc::poll(&mut pfd, 1, timeout) };
+                if ready < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if ready == 0 {
+                    continue;
+                }
+
+                let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+                let mut addr_len = mem::size_of::<libc::sockaddr_ll>() as u32;
+                let result = unsafe {
+                    libc::recvfrom(
+                        self.fd,
+                        buffer.as_mut_ptr() as *mut libc::c_void,
+                        buffer.len(),
+                        0,
+                        &mut addr as *mut libc::sockaddr_ll as *mut libc::sockaddr,
+                        &mut addr_len,
+                    )
+                };
+                if result < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if addr.sll_pkttype != PACKET_OUTGOING {
+                    return Ok(Some(result as usize));
+                }
+            }
+        }
+    }
+
+    impl Drop for PacketSocket {
+        fn drop(&mut self) {
+            unsafe { libc::close(self.fd) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod raw_frame {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn test_frame() -> UdpFrame {
+        UdpFrame {
+            src_mac: [0x10, 0x7b, 0x44, 0x93, 0xe6, 0xd0],
+            dst_mac: BROADCAST_MAC,
+            src_ip: Ipv4Addr::UNSPECIFIED,
+            dst_ip: Ipv4Addr::BROADCAST,
+            src_port: 68,
+            dst_port: 67,
+            payload: vec![0x01, 0x02, 0x03],
+        }
+    }
+
+    #[test]
+    fn test_checksum() {
+        // Worked example from RFC 1071 section 3
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(checksum(&[&data]), !0xddf2);
+        assert_eq!(checksum(&[&[0x01]]), !0x0100);
+    }
+
+    #[test]
+    fn test_encode_frame() {
+        let frame = test_frame().encode();
+
+        assert_eq!(frame.len(), 14 + 20 + 8 + 3);
+        assert_eq!(
+            &frame[..14],
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x10, 0x7b, 0x44, 0x93, 0xe6, 0xd0, 0x08, 0x00]
+        );
+        assert_eq!(
+            &frame[14..24],
+            &[0x45, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00, 0x00, 0x40, 0x11]
+        );
+        assert_eq!(&frame[26..34], &[0, 0, 0, 0, 255, 255, 255, 255]);
+        assert_eq!(&frame[34..40], &[0x00, 0x44, 0x00, 0x43, 0x00, 0x0b]);
+        // Both checksums verify to zero
+        assert_eq!(checksum(&[&frame[14..34]]), 0);
+        assert_ne!(&frame[40..42], &[0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_decode_frame() {
+        let frame = test_frame();
+        let mut data = frame.encode();
+
+        assert_eq!(UdpFrame::decode(&data), Some(frame));
+        // Trailing link layer padding is ignored
+        data.extend_from_slice(&[0x00; 10]);
+        assert!(UdpFrame::decode(&data).is_some());
+    }
+
+    #[test]
+    fn test_decode_rejects_damaged_frames() {
+        let data = test_frame().encode();
+
+        let mut bad_ip_checksum = data.clone();
+        bad_ip_checksum[24] ^= 0xff;
+        let mut bad_udp_checksum = data.clone();
+        bad_udp_checksum[42] ^= 0xff;
+        let mut not_ipv4 = data.clone();
+        not_ipv4[12] = 0x86;
+        let mut not_udp = data.clone();
+        not_udp[23] = 6;
+
+        assert_eq!(UdpFrame::decode(&bad_ip_checksum), None);
+        assert_eq!(UdpFrame::decode(&bad_udp_checksum), None);
+        assert_eq!(UdpFrame::decode(&not_ipv4), None);
+        assert_eq!(UdpFrame::decode(&not_udp), None);
+        assert_eq!(UdpFrame::decode(&data[..40]), None);
+    }
+
+    #[test]
+    fn test_decode_without_udp_checksum() {
+        let frame = test_frame();
+        let mut data = frame.encode();
+        data[40..42].copy_from_slice(&[0x00, 0x00]);
+
+        assert_eq!(UdpFrame::decode(&data), Some(frame));
+    }
+}
+
+// Needs root to create a veth pair, run with `cargo test -- --ignored`.
+// Moving one end into a network namespace with `ip netns` works just the same
+#[cfg(all(test, target_os = "linux"))]
+mod raw_transport {
+    use super::*;
+    use crate::dhcp::{
+        client::{ClientAction, ClientEvent, ClientState, DhcpClient},
+        testing::MockServer,
+        transport::{DhcpTransport, CLIENT_PORT, SERVER_PORT},
+    };
+    use mac_address::MacAddress;
+    use pretty_assertions::assert_eq;
+    use std::{
+        fs,
+        process::Command,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    struct VethPair {
+        client: String,
+        server: String,
+    }
+
+    impl VethPair {
+        fn create() -> Self {
+            let id = std::process::id() % 10000;
+            let pair = VethPair {
+                client: format!("dhcpc{}", id),
+                server: format!("dhcps{}", id),
+            };
+            let ip = |args: &[&str]| {
+                let status = Command::new("ip").args(args).status().unwrap();
+                assert!(status.success(), "ip {:?} failed", args);
+            };
+            ip(&[
+                "link",
+                "add",
+                &pair.client,
+                "type",
+                "veth",
+                "peer",
+                "name",
+                &pair.server,
+            ]);
+            ip(&["link", "set", &pair.client, "up"]);
+            ip(&["link", "set", &pair.server, "up"]);
+            pair
+        }
+
+        fn mac_address(interface: &str) -> MacAddress {
+            fs::read_to_string(format!("/sys/class/net/{}/address", interface))
+                .unwrap()
+                .trim()
+                .parse()
+                .unwrap()
+        }
+    }
+
+    impl Drop for VethPair {
+        fn drop(&mut self) {
+            let _ = Command::new("ip")
+                .args(["link", "del", &self.client])
+                .status();
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn test_exchange_over_veth() {
+        let veth = VethPair::create();
+        let client_mac = VethPair::mac_address(&veth.client);
+        let server_mac = VethPair::mac_address(&veth.server);
+
+        let mut server_end = RawTransport::open(&veth.server, &server_mac)
+            .unwrap()
+            .with_ports(SERVER_PORT, CLIENT_PORT);
+        let server = thread::spawn(move || {
+            let mut server = MockServer::new(Ipv4Addr::new(10, 0, 0, 1));
+            while server
+                .serve(&mut server_end, Some(Duration::from_secs(5)))
+                .unwrap()
+            {}
+        });
+
+        let mut transport = RawTransport::open(&veth.client, &client_mac).unwrap();
+        let mut client = DhcpClient::new(client_mac);
+        let mut actions = client.handle(ClientEvent::Start, Instant::now());
+        let lease = loop {
+            let remaining = transport.perform(actions).unwrap();
+            if let Some(ClientAction::Bound(lease)) = remaining.into_iter().next() {
+                break lease;
+            }
+            let event = transport.wait_for_event(client.get_timeout()).unwrap();
+            assert!(!matches!(event, ClientEvent::TimerFired), "no reply");
+            actions = client.handle(event, Instant::now());
+        };
+
+        assert_eq!(client.get_state(), ClientState::Bound);
+        assert_eq!(lease.get_client_ip(), Ipv4Addr::new(10, 0, 0, 2));
+        drop(transport);
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+use crate::dhcp::{
+    error::DhcpMessageError,
+    packet::{DhcpMessageType, DhcpOptionType, DhcpPacket},
+};
+use num_traits::FromPrimitive;
+use std::fmt;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum OptionRequirement {
+    Must,
+    May,
+    MustNot,
+}
+
+// The messages a client sends. DHCPREQUEST is split up as the options it carries depend on
+// the state it is sent from, see RFC 2131 section 4.3.2
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ClientMessage {
+    Discover,
+    SelectingRequest,
+    RebootRequest,
+    RenewalRequest,
+    Decline,
+    Release,
+    Inform,
+}
+
+impl ClientMessage {
+    pub fn get_type(&self) -> DhcpMessageType {
+        match self {
+            ClientMessage::Discover => DhcpMessageType::Discover,
+            ClientMessage::SelectingRequest
+            | ClientMessage::RebootRequest
+            | ClientMessage::RenewalRequest => DhcpMessageType::Request,
+            ClientMessage::Decline => DhcpMessageType::Decline,
+            ClientMessage::Release => DhcpMessageType::Release,
+            ClientMessage::Inform => DhcpMessageType::Inform,
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            ClientMessage::Discover => "DHCPDISCOVER",
+            ClientMessage::SelectingRequest => "DHCPREQUEST in SELECTING",
+            ClientMessage::RebootRequest => "DHCPREQUEST in INIT-REBOOT",
+            ClientMessage::RenewalRequest => "DHCPREQUEST in RENEWING or REBINDING",
+            ClientMessage::Decline => "DHCPDECLINE",
+            ClientMessage::Release => "DHCPRELEASE",
+            ClientMessage::Inform => "DHCPINFORM",
+        }
+    }
+
+    // Table 5 of RFC 2131, where SHOULD NOT is taken as MAY
+    pub fn get_requirement(&self, option: DhcpOptionType) -> OptionRequirement {
+        use ClientMessage::*;
+        use OptionRequirement::*;
+        match option {
+            DhcpOptionType::MessageType => Must,
+            DhcpOptionType::ClientId | DhcpOptionType::Message => May,
+            DhcpOptionType::RequestedIp => match self {
+                SelectingRequest | RebootRequest | Decline => Must,
+                Discover => May,
+                RenewalRequest | Release | Inform => MustNot,
+            },
+            DhcpOptionType::DhcpServerIp => match self {
+                SelectingRequest | Decline | Release => Must,
+                Discover | RebootRequest | RenewalRequest | Inform => MustNot,
+            },
+            DhcpOptionType::LeaseTime if matches!(self, Inform) => MustNot,
+            _ => self.get_other_requirement(),
+        }
+    }
+
+    // The last row of the table, which also covers options of unknown type
+    fn get_other_requirement(&self) -> OptionRequirement {
+        match self {
+            ClientMessage::Decline | ClientMessage::Release => OptionRequirement::MustNot,
+            _ => OptionRequirement::May,
+        }
+    }
+
+    pub fn check(&self, packet: &DhcpPacket) -> Result<(), DhcpMessageError> {
+        if packet.get_type() != Some(self.get_type()) {
+            return Err(DhcpMessageError::WrongType { message: *self });
+        }
+        let present: Vec<u8> = packet.get_options().iter().map(|x| x.get_id()).collect();
+        for id in present.iter() {
+            let requirement = match FromPrimitive::from_u8(*id) {
+                Some(option) => self.get_requirement(option),
+                None => self.get_other_requirement(),
+            };
+            if requirement == OptionRequirement::MustNot {
+                return Err(DhcpMessageError::UnexpectedOption {
+                    message: *self,
+                    id: *id,
+                });
+            }
+        }
+        for option in [DhcpOptionType::RequestedIp, DhcpOptionType::DhcpServerIp] {
+            if self.get_requirement(option) == OptionRequirement::Must
+                && !present.contains(&(option as u8))
+            {
+                return Err(DhcpMessageError::MissingOption {
+                    message: *self,
+                    id: option as u8,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ClientMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.get_name())
+    }
+}
+
+#[cfg(test)]
+mod client_message {
+    use super::*;
+    use crate::dhcp::options::DhcpOptionValue;
+    use pretty_assertions::assert_eq;
+    use std::{net::Ipv4Addr, time::Duration};
+
+    fn packet(message: ClientMessage) -> DhcpPacket {
+        DhcpPacket::new().with_option(DhcpOptionValue::MessageType(message.get_type()))
+    }
+
+    #[test]
+    fn test_request_depends_on_state() {
+        let ip = Ipv4Addr::new(192, 168, 1, 42);
+        let reboot = packet(ClientMessage::RebootRequest)
+            .with_option(DhcpOptionValue::RequestedIp(ip))
+            .with_option(DhcpOptionValue::LeaseTime(Duration::from_secs(600)));
+        let selecting = reboot
+            .clone()
+            .with_option(DhcpOptionValue::DhcpServerIp(ip));
+
+        assert_eq!(ClientMessage::RebootRequest.check(&reboot), Ok(()));
+        assert_eq!(
+            ClientMessage::RebootRequest.check(&selecting),
+            Err(DhcpMessageError::UnexpectedOption {
+                message: ClientMessage::RebootRequest,
+                id: 54
+            })
+        );
+        assert_eq!(ClientMessage::SelectingRequest.check(&selecting), Ok(()));
+        assert_eq!(
+            ClientMessage::SelectingRequest.check(&reboot),
+            Err(DhcpMessageError::MissingOption {
+                message: ClientMessage::SelectingRequest,
+                id: 54
+            })
+        );
+        assert_eq!(
+            ClientMessage::RenewalRequest.check(&reboot),
+            Err(DhcpMessageError::UnexpectedOption {
+                message: ClientMessage::RenewalRequest,
+                id: 50
+            })
+        );
+    }
+
+    #[test]
+    fn test_notices_carry_nothing_else() {
+        let ip = Ipv4Addr::new(192, 168, 1, 42);
+        let release = packet(ClientMessage::Release)
+            .with_option(DhcpOptionValue::DhcpServerIp(ip))
+            .with_option(DhcpOptionValue::Message("bye".to_string()));
+        assert_eq!(ClientMessage::Release.check(&release), Ok(()));
+
+        let release = release.with_option(DhcpOptionValue::ParameterRequest(vec![1, 3]));
+        assert_eq!(
+            ClientMessage::Release.check(&release),
+            Err(DhcpMessageError::UnexpectedOption {
+                message: ClientMessage::Release,
+                id: 55
+            })
+        );
+        assert_eq!(
+            ClientMessage::Decline.check(&release),
+            Err(DhcpMessageError::WrongType {
+                message: ClientMessage::Decline
+            })
+        );
+        assert_eq!(
+            ClientMessage::Inform.get_requirement(DhcpOptionType::LeaseTime),
+            OptionRequirement::MustNot
+        );
+        assert_eq!(
+            ClientMessage::Discover.get_requirement(DhcpOptionType::RequestedIp),
+            OptionRequirement::May
+        );
+    }
+}
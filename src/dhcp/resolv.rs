@@ -0,0 +1,229 @@
+use crate::dhcp::{packet::DhcpPacket, store::write_atomically};
+use std::{
+    fs, io,
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+};
+
+// First line of every file we write, so our own output is never taken for the original
+const HEADER: &str = "# Generated by dchp_client";
+const BACKUP_SUFFIX: &str = ".dchp_client";
+
+// The resolver settings a lease hands out
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ResolvConf {
+    nameservers: Vec<Ipv4Addr>,
+    domain: Option<String>,
+    search: Vec<String>,
+}
+
+impl ResolvConf {
+    pub fn from_packet(packet: &DhcpPacket) -> Self {
+        ResolvConf {
+            nameservers: packet.get_dns_servers(),
+            domain: packet.get_domain_name(),
+            search: packet.get_domain_search(),
+        }
+    }
+
+    pub fn get_nameservers(&self) -> &[Ipv4Addr] {
+        &self.nameservers
+    }
+
+    pub fn get_domain(&self) -> Option<&str> {
+        self.domain.as_deref()
+    }
+
+    pub fn get_search(&self) -> &[String] {
+        &self.search
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nameservers.is_empty() && self.domain.is_none() && self.search.is_empty()
+    }
+
+    // The resolver keeps whichever of domain and search comes last, so only one is written.
+    // The search list wins as it already covers the domain
+    pub fn render(&self) -> String {
+        let mut lines = vec![HEADER.to_string()];
+        if !self.search.is_empty() {
+            lines.push(format!("search {}", self.search.join(" ")));
+        } else if let Some(domain) = &self.domain {
+            lines.push(format!("search {}", domain));
+        }
+        for nameserver in self.nameservers.iter() {
+            lines.push(format!("nameserver {}", nameserver));
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+// Writes resolv.conf, or a resolvconf fragment, keeping the original to put back later
+pub struct ResolvConfWriter {
+    path: PathBuf,
+    backup: PathBuf,
+}
+
+impl ResolvConfWriter {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let mut backup = path.clone().into_os_string();
+        backup.push(BACKUP_SUFFIX);
+        ResolvConfWriter {
+            path,
+            backup: backup.into(),
+        }
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write(&self, conf: &ResolvConf) -> io::Result<()> {
+        self.backup_original()?;
+        write_atomically(&self.path, conf.render().as_bytes())
+    }
+
+    // Puts the original back, or removes the file if there was none
+    pub fn restore(&self) -> io::Result<()> {
+        if fs::symlink_metadata(&self.backup).is_ok() {
+            return fs::rename(&self.backup, &self.path);
+        }
+        match fs::read_to_string(&self.path) {
+            Ok(contents) if contents.starts_with(HEADER) => fs::remove_file(&self.path),
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Only done once, later writes find our own file in place. Symlinks, such as the one to
+    // systemd-resolved's stub, are kept as links
+    fn backup_original(&self) -> io::Result<()> {
+        if fs::symlink_metadata(&self.backup).is_ok() {
+            return Ok(());
+        }
+        let metadata = match fs::symlink_metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if metadata.file_type().is_symlink() {
+            return backup_link(&self.path, &self.backup);
+        }
+        let contents = fs::read(&self.path)?;
+        if contents.starts_with(HEADER.as_bytes()) {
+            return Ok(());
+        }
+        write_atomically(&self.backup, &contents)
+    }
+}
+
+#[cfg(unix)]
+fn backup_link(path: &Path, backup: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(path)?, backup)
+}
+
+// Links are not recreated elsewhere, what they point at is kept instead
+#[cfg(not(unix))]
+fn backup_link(path: &Path, backup: &Path) -> io::Result<()> {
+    write_atomically(backup, &fs::read(path)?)
+}
+
+#[cfg(test)]
+mod resolv_conf {
+    use super::*;
+    use crate::dhcp::options::DhcpOptionValue;
+    use pretty_assertions::assert_eq;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "dchp_client-{}-resolv-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn test_conf() -> ResolvConf {
+        let ack = DhcpPacket::new()
+            .with_option(DhcpOptionValue::DnsServerIp(vec![
+                Ipv4Addr::new(192, 168, 1, 1),
+                Ipv4Addr::new(1, 1, 1, 1),
+            ]))
+            .with_option(DhcpOptionValue::DomainName("example.org".to_string()));
+        ResolvConf::from_packet(&ack)
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            test_conf().render(),
+            "# Generated by dchp_client\n\
+             search example.org\n\
+             nameserver 192.168.1.1\n\
+             nameserver 1.1.1.1\n"
+        );
+
+        let ack = DhcpPacket::new()
+            .with_option(DhcpOptionValue::DomainName("example.org".to_string()))
+            .with_option(DhcpOptionValue::DomainSearch(vec![
+                "lab.example.org".to_string(),
+                "example.org".to_string(),
+            ]));
+        let conf = ResolvConf::from_packet(&ack);
+        assert!(conf.get_nameservers().is_empty());
+        assert_eq!(
+            conf.render(),
+            "# Generated by dchp_client\nsearch lab.example.org example.org\n"
+        );
+        assert!(ResolvConf::from_packet(&DhcpPacket::new()).is_empty());
+    }
+
+    #[test]
+    fn test_write_and_restore() {
+        let dir = test_dir("restore");
+        let path = dir.join("resolv.conf");
+        fs::write(&path, "nameserver 9.9.9.9\n").unwrap();
+        let writer = ResolvConfWriter::new(&path);
+
+        writer.write(&test_conf()).unwrap();
+        writer.write(&test_conf()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), test_conf().render());
+
+        writer.restore().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "nameserver 9.9.9.9\n");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restore_symlink_and_missing() {
+        use std::os::unix::fs::symlink;
+
+        let dir = test_dir("symlink");
+        let link = dir.join("resolv.conf");
+        symlink("stub-resolv.conf", &link).unwrap();
+        let writer = ResolvConfWriter::new(&link);
+
+        writer.write(&test_conf()).unwrap();
+        assert!(!fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        writer.restore().unwrap();
+        assert_eq!(
+            fs::read_link(&link).unwrap(),
+            PathBuf::from("stub-resolv.conf")
+        );
+
+        let fragment = ResolvConfWriter::new(dir.join("eth0.dchp_client"));
+        fragment.write(&test_conf()).unwrap();
+        fragment.restore().unwrap();
+        assert!(!fragment.get_path().exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
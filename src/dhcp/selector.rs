@@ -0,0 +1,133 @@
+use crate::dhcp::packet::DhcpPacket;
+use std::{cmp::Reverse, net::Ipv4Addr, time::Duration};
+
+// Picks which of the offers collected while SELECTING to request.
+// Offers are in order of arrival and all carry a server identifier
+pub trait OfferSelector {
+    // The index of the chosen offer, None to reject them all and keep discovering
+    fn select(&self, offers: &[DhcpPacket]) -> Option<usize>;
+}
+
+// Takes whichever offer arrived first
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FirstOffer;
+
+impl OfferSelector for FirstOffer {
+    fn select(&self, offers: &[DhcpPacket]) -> Option<usize> {
+        if offers.is_empty() {
+            None
+        } else {
+            Some(0)
+        }
+    }
+}
+
+// Goes back to an address held before when any server offers it
+#[derive(Clone, Copy, Debug)]
+pub struct PreferAddress {
+    address: Ipv4Addr,
+}
+
+impl PreferAddress {
+    pub fn new(address: &Ipv4Addr) -> Self {
+        PreferAddress { address: *address }
+    }
+}
+
+impl OfferSelector for PreferAddress {
+    fn select(&self, offers: &[DhcpPacket]) -> Option<usize> {
+        offers
+            .iter()
+            .position(|x| x.get_client_ip() == self.address)
+            .or_else(|| FirstOffer.select(offers))
+    }
+}
+
+// Sticks with one server when it answers, any other otherwise
+#[derive(Clone, Copy, Debug)]
+pub struct PreferServer {
+    server_ip: Ipv4Addr,
+}
+
+impl PreferServer {
+    pub fn new(server_ip: &Ipv4Addr) -> Self {
+        PreferServer {
+            server_ip: *server_ip,
+        }
+    }
+}
+
+impl OfferSelector for PreferServer {
+    fn select(&self, offers: &[DhcpPacket]) -> Option<usize> {
+        offers
+            .iter()
+            .position(|x| x.get_server_ip() == Some(self.server_ip))
+            .or_else(|| FirstOffer.select(offers))
+    }
+}
+
+// The longest lease wins, the earliest offer among equals
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LongestLease;
+
+impl OfferSelector for LongestLease {
+    fn select(&self, offers: &[DhcpPacket]) -> Option<usize> {
+        offers
+            .iter()
+            .enumerate()
+            .max_by_key(|(i, x)| (x.get_lease_time().unwrap_or(Duration::ZERO), Reverse(*i)))
+            .map(|(i, _)| i)
+    }
+}
+
+#[cfg(test)]
+mod offer_selector {
+    use super::*;
+    use crate::dhcp::{options::DhcpOptionValue, packet::DhcpMessageType};
+    use pretty_assertions::assert_eq;
+
+    fn offer(server: u8, ip: u8, lease_time: u64) -> DhcpPacket {
+        DhcpPacket::new()
+            .with_client_ip(&Ipv4Addr::new(192, 168, 1, ip))
+            .with_option(DhcpOptionValue::MessageType(DhcpMessageType::Offer))
+            .with_option(DhcpOptionValue::DhcpServerIp(Ipv4Addr::new(
+                192, 168, 1, server,
+            )))
+            .with_option(DhcpOptionValue::LeaseTime(Duration::from_secs(lease_time)))
+    }
+
+    fn offers() -> Vec<DhcpPacket> {
+        vec![offer(1, 10, 600), offer(2, 20, 3600), offer(3, 30, 3600)]
+    }
+
+    #[test]
+    fn test_first_offer() {
+        assert_eq!(FirstOffer.select(&offers()), Some(0));
+        assert_eq!(FirstOffer.select(&[]), None);
+    }
+
+    #[test]
+    fn test_prefer_address() {
+        let held = PreferAddress::new(&Ipv4Addr::new(192, 168, 1, 30));
+        let gone = PreferAddress::new(&Ipv4Addr::new(192, 168, 1, 99));
+
+        assert_eq!(held.select(&offers()), Some(2));
+        assert_eq!(gone.select(&offers()), Some(0));
+        assert_eq!(held.select(&[]), None);
+    }
+
+    #[test]
+    fn test_prefer_server() {
+        let known = PreferServer::new(&Ipv4Addr::new(192, 168, 1, 2));
+        let unknown = PreferServer::new(&Ipv4Addr::new(192, 168, 1, 9));
+
+        assert_eq!(known.select(&offers()), Some(1));
+        assert_eq!(unknown.select(&offers()), Some(0));
+    }
+
+    #[test]
+    fn test_longest_lease() {
+        assert_eq!(LongestLease.select(&offers()), Some(1));
+        assert_eq!(LongestLease.select(&[]), None);
+    }
+}
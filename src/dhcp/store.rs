@@ -0,0 +1,402 @@
+use crate::dhcp::{
+    client::Lease,
+    packet::DhcpPacket,
+    traits::{Deserialize, Serialize},
+};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, Write},
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+const HEADER: &str =
+    "# interface address server acquired lease renewal rebinding packet original-mtu";
+
+// A lease as it outlives the process, so times are wall clock rather than Instants
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct StoredLease {
+    interface: String,
+    client_ip: Ipv4Addr,
+    server_ip: Ipv4Addr,
+    acquired: SystemTime,
+    lease_time: Duration,
+    renewal_time: Duration,
+    rebinding_time: Duration,
+    ack: DhcpPacket,
+    original_mtu: Option<u16>,
+}
+
+impl StoredLease {
+    // `now` pairs the monotonic and wall clocks so the acquisition time can be carried over
+    pub fn from_lease(interface: &str, lease: &Lease, now: (Instant, SystemTime)) -> Self {
+        let (instant, system_time) = now;
+        let age = instant.saturating_duration_since(lease.get_acquired());
+        StoredLease {
+            interface: interface.to_string(),
+            client_ip: lease.get_client_ip(),
+            server_ip: lease.get_server_ip(),
+            acquired: system_time - age,
+            lease_time: lease.get_lease_time(),
+            renewal_time: lease.get_renewal_time(),
+            rebinding_time: lease.get_rebinding_time(),
+            ack: lease.get_packet().clone(),
+            original_mtu: None,
+        }
+    }
+
+    // The interface's MTU before the lease changed it
+    pub fn with_original_mtu(mut self, mtu: Option<u16>) -> Self {
+        self.original_mtu = mtu;
+        self
+    }
+
+    // Back to a Lease the client can resume, the inverse of from_lease
+    pub fn to_lease(&self, now: (Instant, SystemTime)) -> Lease {
+        let (instant, system_time) = now;
+        let age = system_time
+            .duration_since(self.acquired)
+            .unwrap_or_default();
+        Lease::from_parts(
+            self.ack.clone(),
+            self.client_ip,
+            self.server_ip,
+            self.lease_time,
+            self.renewal_time,
+            self.rebinding_time,
+            instant.checked_sub(age).unwrap_or(instant),
+        )
+    }
+
+    pub fn get_interface(&self) -> &str {
+        &self.interface
+    }
+
+    pub fn get_client_ip(&self) -> Ipv4Addr {
+        self.client_ip
+    }
+
+    pub fn get_server_ip(&self) -> Ipv4Addr {
+        self.server_ip
+    }
+
+    pub fn get_acquired(&self) -> SystemTime {
+        self.acquired
+    }
+
+    pub fn get_lease_time(&self) -> Duration {
+        self.lease_time
+    }
+
+    pub fn get_renewal_time(&self) -> Duration {
+        self.renewal_time
+    }
+
+    pub fn get_rebinding_time(&self) -> Duration {
+        self.rebinding_time
+    }
+
+    pub fn get_renewal_deadline(&self) -> SystemTime {
+        self.acquired + self.renewal_time
+    }
+
+    pub fn get_rebinding_deadline(&self) -> SystemTime {
+        self.acquired + self.rebinding_time
+    }
+
+    pub fn get_expiry(&self) -> SystemTime {
+        self.acquired + self.lease_time
+    }
+
+    // The ACK the lease came from, holding every option the server sent
+    pub fn get_packet(&self) -> &DhcpPacket {
+        &self.ack
+    }
+
+    pub fn get_original_mtu(&self) -> Option<u16> {
+        self.original_mtu
+    }
+
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        now >= self.get_expiry()
+    }
+
+    fn encode(&self) -> String {
+        let acquired = self
+            .acquired
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let packet: String = self
+            .ack
+            .serialize()
+            .iter()
+            .map(|x| format!("{:02x}", x))
+            .collect();
+        let original_mtu = match self.original_mtu {
+            Some(mtu) => mtu.to_string(),
+            None => "-".to_string(),
+        };
+        format!(
+            "{} {} {} {} {} {} {} {} {}",
+            self.interface,
+            self.client_ip,
+            self.server_ip,
+            acquired,
+            self.lease_time.as_secs(),
+            self.renewal_time.as_secs(),
+            self.rebinding_time.as_secs(),
+            packet,
+            original_mtu
+        )
+    }
+
+    fn decode(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        // Stores written before the MTU was kept have one field less
+        let (interface, client_ip, server_ip, acquired, lease, renewal, rebinding, packet, mtu) =
+            match fields[..] {
+                [a, b, c, d, e, f, g, h] => (a, b, c, d, e, f, g, h, "-"),
+                [a, b, c, d, e, f, g, h, i] => (a, b, c, d, e, f, g, h, i),
+                _ => return None,
+            };
+        let original_mtu = match mtu {
+            "-" => None,
+            mtu => Some(mtu.parse().ok()?),
+        };
+        let seconds = |x: &str| x.parse().ok().map(Duration::from_secs);
+        let bytes = (0..packet.len())
+            .step_by(2)
+            .map(|x| u8::from_str_radix(packet.get(x..x + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        Some(StoredLease {
+            interface: interface.to_string(),
+            client_ip: client_ip.parse().ok()?,
+            server_ip: server_ip.parse().ok()?,
+            acquired: UNIX_EPOCH + seconds(acquired)?,
+            lease_time: seconds(lease)?,
+            renewal_time: seconds(renewal)?,
+            rebinding_time: seconds(rebinding)?,
+            ack: DhcpPacket::deserialize(&bytes).ok()?,
+            original_mtu,
+        })
+    }
+}
+
+// Leases by interface, saved to a file after every change
+pub struct LeaseStore {
+    path: PathBuf,
+    leases: BTreeMap<String, StoredLease>,
+}
+
+impl LeaseStore {
+    // A missing file is an empty store
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut leases = BTreeMap::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let lease = StoredLease::decode(line).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{} is not a valid lease", path.display(), number + 1),
+                )
+            })?;
+            leases.insert(lease.interface.clone(), lease);
+        }
+        Ok(LeaseStore { path, leases })
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get_lease(&self, interface: &str) -> Option<&StoredLease> {
+        self.leases.get(interface)
+    }
+
+    pub fn get_leases(&self) -> impl Iterator<Item = &StoredLease> {
+        self.leases.values()
+    }
+
+    // Replaces any lease held before on the same interface
+    pub fn insert_lease(&mut self, lease: StoredLease) -> io::Result<()> {
+        self.leases.insert(lease.interface.clone(), lease);
+        self.save()
+    }
+
+    pub fn remove_lease(&mut self, interface: &str) -> io::Result<Option<StoredLease>> {
+        let removed = self.leases.remove(interface);
+        if removed.is_some() {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let mut contents = format!("{}\n", HEADER);
+        for lease in self.leases.values() {
+            contents.push_str(&lease.encode());
+            contents.push('\n');
+        }
+        write_atomically(&self.path, contents.as_bytes())
+    }
+}
+
+// Written to a temporary file first and renamed over the old one, so a crash never
+// leaves a half written file behind
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut temporary = path.to_path_buf().into_os_string();
+    temporary.push(".tmp");
+
+    let mut file = File::create(&temporary)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod lease_store {
+    use super::*;
+    use crate::dhcp::{options::DhcpOptionValue, packet::DhcpMessageType};
+    use pretty_assertions::assert_eq;
+
+    fn store_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "dchp_client-{}-{}/leases",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn test_lease(interface: &str, acquired: SystemTime) -> StoredLease {
+        let ack = DhcpPacket::new()
+            .with_client_ip(&Ipv4Addr::new(192, 168, 1, 99))
+            .with_option(DhcpOptionValue::MessageType(DhcpMessageType::Ack))
+            .with_option(DhcpOptionValue::DnsServerIp(vec![Ipv4Addr::new(
+                1, 1, 1, 1,
+            )]));
+        StoredLease {
+            interface: interface.to_string(),
+            client_ip: Ipv4Addr::new(192, 168, 1, 99),
+            server_ip: Ipv4Addr::new(192, 168, 1, 1),
+            acquired,
+            lease_time: Duration::from_secs(3600),
+            renewal_time: Duration::from_secs(1800),
+            rebinding_time: Duration::from_secs(3150),
+            ack,
+            original_mtu: None,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let path = store_path("round_trip");
+        let acquired = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let mut store = LeaseStore::open(&path).unwrap();
+        assert_eq!(store.get_leases().count(), 0);
+
+        store.insert_lease(test_lease("eth0", acquired)).unwrap();
+        let with_mtu = test_lease("eth1", acquired).with_original_mtu(Some(9000));
+        store.insert_lease(with_mtu.clone()).unwrap();
+        assert_eq!(
+            LeaseStore::open(&path).unwrap().get_lease("eth1"),
+            Some(&with_mtu)
+        );
+        store.remove_lease("eth1").unwrap();
+
+        let reopened = LeaseStore::open(&path).unwrap();
+        let lease = reopened.get_lease("eth0").unwrap();
+        assert_eq!(lease, &test_lease("eth0", acquired));
+        assert_eq!(
+            lease.get_packet().get_dns_servers(),
+            vec![Ipv4Addr::new(1, 1, 1, 1)]
+        );
+        assert_eq!(reopened.get_lease("eth1"), None);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_expiry() {
+        let acquired = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let lease = test_lease("eth0", acquired);
+
+        assert_eq!(
+            lease.get_renewal_deadline(),
+            acquired + Duration::from_secs(1800)
+        );
+        assert!(!lease.is_expired(acquired + Duration::from_secs(3599)));
+        assert!(lease.is_expired(acquired + Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn test_to_lease() {
+        let acquired = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let stored = test_lease("eth0", acquired);
+        let instant = Instant::now();
+
+        let lease = stored.to_lease((instant, acquired + Duration::from_secs(600)));
+
+        assert_eq!(lease.get_client_ip(), stored.get_client_ip());
+        assert_eq!(lease.get_server_ip(), stored.get_server_ip());
+        assert_eq!(lease.get_packet(), stored.get_packet());
+        assert_eq!(
+            lease.get_renewal_deadline(),
+            instant + Duration::from_secs(1200)
+        );
+        assert_eq!(
+            StoredLease::from_lease(
+                "eth0",
+                &lease,
+                (instant, acquired + Duration::from_secs(600))
+            ),
+            stored
+        );
+    }
+
+    #[test]
+    fn test_store_without_mtu() {
+        let path = store_path("without_mtu");
+        let lease = test_lease("eth0", UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+        let line = lease.encode();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(
+            &path,
+            format!("# header\n{}\n", line.trim_end_matches(" -")),
+        )
+        .unwrap();
+
+        let store = LeaseStore::open(&path).unwrap();
+
+        assert_eq!(store.get_lease("eth0"), Some(&lease));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_store() {
+        let path = store_path("corrupt");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "# header\neth0 192.168.1.99 nonsense\n").unwrap();
+
+        let error = LeaseStore::open(&path).err().unwrap();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
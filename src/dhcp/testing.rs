@@ -0,0 +1,454 @@
+use crate::dhcp::{
+    options::DhcpOptionValue,
+    packet::{DhcpMessageType, DhcpOption, DhcpPacket},
+    transport::{DhcpTransport, MemoryTransport},
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    net::Ipv4Addr,
+    time::Duration,
+};
+
+const DEFAULT_LEASE_TIME: Duration = Duration::from_secs(3600);
+
+// Misbehaviour applied to the next client message of a given type
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Fault {
+    Nak,
+    Silence,
+    DuplicateOffer,
+}
+
+// A DHCP server small enough to run inside tests, handing out addresses from a fixed pool
+pub struct MockServer {
+    server_ip: Ipv4Addr,
+    pool: Vec<Ipv4Addr>,
+    lease_time: Duration,
+    options: Vec<DhcpOption>,
+    faults: VecDeque<(DhcpMessageType, Fault)>,
+    rapid_commit: bool,
+    vendor_options: HashMap<Vec<u8>, Vec<u8>>,
+    // Addresses are keyed by client identifier, or hardware address for clients without one
+    offered: HashMap<Vec<u8>, Ipv4Addr>,
+    leases: HashMap<Vec<u8>, Ipv4Addr>,
+    declined: HashSet<Ipv4Addr>,
+    received: Vec<DhcpPacket>,
+}
+
+impl MockServer {
+    pub fn new(server_ip: Ipv4Addr) -> Self {
+        let first = u32::from(server_ip) + 1;
+        MockServer {
+            server_ip,
+            pool: (first..first + 100).map(Ipv4Addr::from).collect(),
+            lease_time: DEFAULT_LEASE_TIME,
+            options: vec![],
+            faults: VecDeque::new(),
+            rapid_commit: false,
+            vendor_options: HashMap::new(),
+            offered: HashMap::new(),
+            leases: HashMap::new(),
+            declined: HashSet::new(),
+            received: vec![],
+        }
+    }
+
+    // Both ends are inclusive
+    pub fn with_pool(mut self, first: Ipv4Addr, last: Ipv4Addr) -> Self {
+        self.pool = (u32::from(first)..=u32::from(last))
+            .map(Ipv4Addr::from)
+            .collect();
+        self
+    }
+
+    pub fn with_lease_time(mut self, lease_time: Duration) -> Self {
+        self.lease_time = lease_time;
+        self
+    }
+
+    // Added to every OFFER and ACK
+    pub fn with_option<T: Into<DhcpOption>>(mut self, option: T) -> Self {
+        self.options.push(option.into());
+        self
+    }
+
+    // Faults are used up in the order they were added
+    pub fn with_fault(mut self, mtype: DhcpMessageType, fault: Fault) -> Self {
+        self.faults.push_back((mtype, fault));
+        self
+    }
+
+    // Answer a DISCOVER carrying Rapid Commit with an ACK straight away
+    pub fn with_rapid_commit(mut self, enabled: bool) -> Self {
+        self.rapid_commit = enabled;
+        self
+    }
+
+    // Option 43 for clients sending this vendor class, left out for everyone else
+    pub fn with_vendor_options(mut self, vendor_class: &[u8], body: &[u8]) -> Self {
+        self.vendor_options
+            .insert(vendor_class.to_vec(), body.to_vec());
+        self
+    }
+
+    pub fn get_server_ip(&self) -> Ipv4Addr {
+        self.server_ip
+    }
+
+    pub fn get_lease(&self, client: &[u8]) -> Option<Ipv4Addr> {
+        self.leases.get(client).copied()
+    }
+
+    pub fn get_received(&self) -> &[DhcpPacket] {
+        &self.received
+    }
+
+    // Answers a single client message, returning each reply with where it should go
+    pub fn respond(&mut self, packet: &DhcpPacket) -> Vec<(Ipv4Addr, DhcpPacket)> {
+        self.received.push(packet.clone());
+        let mtype = match packet.get_type() {
+            Some(mtype) => mtype,
+            None => return vec![],
+        };
+        let fault = self.take_fault(mtype);
+        if fault == Some(Fault::Silence) {
+            return vec![];
+        }
+        if fault == Some(Fault::Nak) {
+            return vec![(Ipv4Addr::BROADCAST, self.nak(packet))];
+        }
+
+        match mtype {
+            DhcpMessageType::Discover if self.rapid_commit && packet.is_rapid_commit() => {
+                match self.offer(packet) {
+                    Some(offer) => self.commit(packet, offer.get_client_ip()),
+                    None => vec![],
+                }
+            }
+            DhcpMessageType::Discover => match self.offer(packet) {
+                Some(offer) if fault == Some(Fault::DuplicateOffer) => vec![
+                    (Ipv4Addr::BROADCAST, offer.clone()),
+                    (Ipv4Addr::BROADCAST, offer),
+                ],
+                Some(offer) => vec![(Ipv4Addr::BROADCAST, offer)],
+                None => vec![],
+            },
+            DhcpMessageType::Request => self.request(packet),
+            DhcpMessageType::Decline => {
+                let client = client_key(packet);
+                self.leases.remove(&client);
+                self.offered.remove(&client);
+                if let Some(ip) = packet.get_requested_ip() {
+                    self.declined.insert(ip);
+                }
+                vec![]
+            }
+            DhcpMessageType::Release => {
+                self.leases.remove(&client_key(packet));
+                vec![]
+            }
+            DhcpMessageType::Inform => {
+                let ack = self
+                    .reply(packet, DhcpMessageType::Ack)
+                    .with_broadcast_flag(false);
+                vec![(packet.get_current_ip(), ack)]
+            }
+            _ => vec![],
+        }
+    }
+
+    // Waits for one client message and sends the replies back.
+    // Returns false when nothing arrived before the timeout
+    pub fn serve<T: DhcpTransport>(
+        &mut self,
+        transport: &mut T,
+        timeout: Option<Duration>,
+    ) -> io::Result<bool> {
+        let packet = match transport.receive(timeout)? {
+            Some(packet) => packet,
+            None => return Ok(false),
+        };
+        for (destination, reply) in self.respond(&packet) {
+            transport.send(destination, &reply)?;
+        }
+        Ok(true)
+    }
+
+    // Serves everything the client has sent so far without blocking
+    pub fn serve_pending(&mut self, transport: &mut MemoryTransport) -> io::Result<usize> {
+        let mut served = 0;
+        while self.serve(transport, Some(Duration::from_secs(0)))? {
+            served += 1;
+        }
+        Ok(served)
+    }
+
+    fn take_fault(&mut self, mtype: DhcpMessageType) -> Option<Fault> {
+        let position = self.faults.iter().position(|(x, _)| *x == mtype)?;
+        self.faults.remove(position).map(|(_, fault)| fault)
+    }
+
+    fn offer(&mut self, discover: &DhcpPacket) -> Option<DhcpPacket> {
+        let client = client_key(discover);
+        let ip = match self.leases.get(&client) {
+            Some(ip) => *ip,
+            None => discover
+                .get_requested_ip()
+                .filter(|x| self.is_available(x, &client))
+                .or_else(|| {
+                    self.pool
+                        .iter()
+                        .copied()
+                        .find(|x| self.is_available(x, &client))
+                })?,
+        };
+        self.offered.insert(client, ip);
+        Some(self.lease_reply(discover, DhcpMessageType::Offer, &ip))
+    }
+
+    fn request(&mut self, request: &DhcpPacket) -> Vec<(Ipv4Addr, DhcpPacket)> {
+        let client = client_key(request);
+        // The client picked another server's offer
+        if let Some(server_ip) = request.get_server_ip() {
+            if server_ip != self.server_ip {
+                self.offered.remove(&client);
+                return vec![];
+            }
+        }
+
+        let current_ip = request.get_current_ip();
+        let ip = match request.get_requested_ip() {
+            Some(ip) => ip,
+            None if !current_ip.is_unspecified() => current_ip,
+            None => return vec![(Ipv4Addr::BROADCAST, self.nak(request))],
+        };
+        if !self.is_available(&ip, &client) {
+            return vec![(Ipv4Addr::BROADCAST, self.nak(request))];
+        }
+
+        self.commit(request, ip)
+    }
+
+    // Turns the offer into a lease and acknowledges it
+    fn commit(&mut self, request: &DhcpPacket, ip: Ipv4Addr) -> Vec<(Ipv4Addr, DhcpPacket)> {
+        let client = client_key(request);
+        self.offered.remove(&client);
+        self.leases.insert(client, ip);
+        let mut ack = self.lease_reply(request, DhcpMessageType::Ack, &ip);
+        if request.is_type(DhcpMessageType::Discover) {
+            ack = ack.with_option(DhcpOptionValue::RapidCommit);
+        }
+        let current_ip = request.get_current_ip();
+        // Renewing clients already have their address, everyone else hears a broadcast
+        match current_ip.is_unspecified() {
+            true => vec![(Ipv4Addr::BROADCAST, ack)],
+            false => vec![(current_ip, ack.with_broadcast_flag(false))],
+        }
+    }
+
+    fn is_available(&self, ip: &Ipv4Addr, client: &[u8]) -> bool {
+        let taken_by_other = |bindings: &HashMap<Vec<u8>, Ipv4Addr>| {
+            bindings
+                .iter()
+                .any(|(owner, x)| x == ip && owner.as_slice() != client)
+        };
+        self.pool.contains(ip)
+            && !self.declined.contains(ip)
+            && !taken_by_other(&self.leases)
+            && !taken_by_other(&self.offered)
+    }
+
+    fn lease_reply(
+        &self,
+        request: &DhcpPacket,
+        mtype: DhcpMessageType,
+        ip: &Ipv4Addr,
+    ) -> DhcpPacket {
+        self.reply(request, mtype)
+            .with_client_ip(ip)
+            .with_option(DhcpOptionValue::LeaseTime(self.lease_time))
+    }
+
+    fn reply(&self, request: &DhcpPacket, mtype: DhcpMessageType) -> DhcpPacket {
+        let mut reply = DhcpPacket::new()
+            .with_transaction(&request.get_transaction())
+            .with_hardware_address(request.get_hardware_address())
+            .with_option(DhcpOptionValue::MessageType(mtype))
+            .with_option(DhcpOptionValue::DhcpServerIp(self.server_ip));
+        // Echoed back as RFC 6842 asks
+        if let Some(client_id) = request.get_client_id() {
+            reply = reply.with_option(DhcpOptionValue::ClientId(client_id));
+        }
+        for option in &self.options {
+            reply = reply.with_option(option.clone());
+        }
+        let vendor_options = request
+            .get_vendor_class()
+            .and_then(|x| self.vendor_options.get(&x));
+        if let Some(body) = vendor_options {
+            reply = reply.with_option(DhcpOptionValue::VendorSpecific(body.clone()));
+        }
+        reply
+    }
+
+    fn nak(&self, request: &DhcpPacket) -> DhcpPacket {
+        DhcpPacket::new()
+            .with_transaction(&request.get_transaction())
+            .with_hardware_address(request.get_hardware_address())
+            .with_option(DhcpOptionValue::MessageType(DhcpMessageType::Nak))
+            .with_option(DhcpOptionValue::DhcpServerIp(self.server_ip))
+    }
+}
+
+fn client_key(packet: &DhcpPacket) -> Vec<u8> {
+    packet
+        .get_client_id()
+        .unwrap_or_else(|| packet.get_hardware_address().to_vec())
+}
+
+#[cfg(test)]
+mod mock_server {
+    use super::*;
+    use mac_address::MacAddress;
+    use pretty_assertions::assert_eq;
+
+    fn server_ip() -> Ipv4Addr {
+        Ipv4Addr::new(192, 168, 1, 1)
+    }
+
+    fn test_mac() -> MacAddress {
+        MacAddress::new([0x10, 0x7b, 0x44, 0x93, 0xe6, 0xd0])
+    }
+
+    fn message(mtype: DhcpMessageType) -> DhcpPacket {
+        DhcpPacket::new()
+            .with_transaction(&[1, 2, 3, 4])
+            .with_mac_address(&test_mac())
+            .with_option(DhcpOptionValue::MessageType(mtype))
+    }
+
+    fn request(ip: Ipv4Addr) -> DhcpPacket {
+        message(DhcpMessageType::Request)
+            .with_option(DhcpOptionValue::RequestedIp(ip))
+            .with_option(DhcpOptionValue::DhcpServerIp(server_ip()))
+    }
+
+    #[test]
+    fn test_offer_and_ack() {
+        let mut server = MockServer::new(server_ip())
+            .with_pool(
+                Ipv4Addr::new(192, 168, 1, 50),
+                Ipv4Addr::new(192, 168, 1, 51),
+            )
+            .with_lease_time(Duration::from_secs(600))
+            .with_option(DhcpOptionValue::RouterIp(vec![server_ip()]));
+
+        let replies = server.respond(&message(DhcpMessageType::Discover));
+        let (destination, offer) = &replies[0];
+        assert_eq!(*destination, Ipv4Addr::BROADCAST);
+        assert!(offer.is_type(DhcpMessageType::Offer));
+        assert!(offer.is_transaction(&[1, 2, 3, 4]));
+        assert_eq!(offer.get_mac_address(), Some(test_mac()));
+        assert_eq!(offer.get_client_ip(), Ipv4Addr::new(192, 168, 1, 50));
+        assert_eq!(offer.get_lease_time(), Some(Duration::from_secs(600)));
+        assert_eq!(offer.get_router_ip(), Some(server_ip()));
+
+        let replies = server.respond(&request(offer.get_client_ip()));
+        assert!(replies[0].1.is_type(DhcpMessageType::Ack));
+        assert_eq!(
+            server.get_lease(&test_mac().bytes()),
+            Some(Ipv4Addr::new(192, 168, 1, 50))
+        );
+        assert_eq!(server.get_received().len(), 2);
+    }
+
+    #[test]
+    fn test_rapid_commit() {
+        let mut server = MockServer::new(server_ip()).with_rapid_commit(true);
+        let discover = message(DhcpMessageType::Discover).with_option(DhcpOptionValue::RapidCommit);
+
+        let replies = server.respond(&discover);
+        let (_, ack) = &replies[0];
+        assert!(ack.is_type(DhcpMessageType::Ack));
+        assert!(ack.is_rapid_commit());
+        assert_eq!(
+            server.get_lease(&test_mac().bytes()),
+            Some(ack.get_client_ip())
+        );
+
+        // Clients that did not ask still get an offer
+        let mut server = MockServer::new(server_ip()).with_rapid_commit(true);
+        let replies = server.respond(&message(DhcpMessageType::Discover));
+        assert!(replies[0].1.is_type(DhcpMessageType::Offer));
+    }
+
+    #[test]
+    fn test_vendor_options() {
+        let mut server =
+            MockServer::new(server_ip()).with_vendor_options(b"ExamplePhone", &[1, 1, 7]);
+        let discover = message(DhcpMessageType::Discover)
+            .with_option(DhcpOptionValue::VendorClass(b"ExamplePhone".to_vec()));
+        let replies = server.respond(&discover);
+        assert_eq!(replies[0].1.get_vendor_specific(), Some(vec![1, 1, 7]));
+
+        let discover = message(DhcpMessageType::Discover)
+            .with_option(DhcpOptionValue::VendorClass(b"OtherVendor".to_vec()));
+        let replies = server.respond(&discover);
+        assert_eq!(replies[0].1.get_vendor_specific(), None);
+    }
+
+    #[test]
+    fn test_request_outside_pool_is_naked() {
+        let mut server = MockServer::new(server_ip());
+
+        let replies = server.respond(&request(Ipv4Addr::new(10, 0, 0, 5)));
+
+        assert!(replies[0].1.is_type(DhcpMessageType::Nak));
+        assert_eq!(server.get_lease(&test_mac().bytes()), None);
+    }
+
+    #[test]
+    fn test_declined_address_is_not_offered_again() {
+        let first = Ipv4Addr::new(192, 168, 1, 2);
+        let mut server = MockServer::new(server_ip());
+        server.respond(&request(first));
+
+        server.respond(
+            &message(DhcpMessageType::Decline).with_option(DhcpOptionValue::RequestedIp(first)),
+        );
+        let replies = server.respond(&message(DhcpMessageType::Discover));
+
+        assert_eq!(replies[0].1.get_client_ip(), Ipv4Addr::new(192, 168, 1, 3));
+    }
+
+    #[test]
+    fn test_scripted_faults() {
+        let mut server = MockServer::new(server_ip())
+            .with_fault(DhcpMessageType::Discover, Fault::Silence)
+            .with_fault(DhcpMessageType::Discover, Fault::DuplicateOffer)
+            .with_fault(DhcpMessageType::Request, Fault::Nak);
+        let discover = message(DhcpMessageType::Discover);
+
+        assert!(server.respond(&discover).is_empty());
+        assert_eq!(server.respond(&discover).len(), 2);
+        assert_eq!(server.respond(&discover).len(), 1);
+        let replies = server.respond(&request(Ipv4Addr::new(192, 168, 1, 2)));
+        assert!(replies[0].1.is_type(DhcpMessageType::Nak));
+    }
+
+    #[test]
+    fn test_inform_is_unicast() {
+        let ip = Ipv4Addr::new(192, 168, 1, 200);
+        let mut server = MockServer::new(server_ip())
+            .with_option(DhcpOptionValue::DnsServerIp(vec![server_ip()]));
+
+        let replies = server.respond(&message(DhcpMessageType::Inform).with_current_ip(&ip));
+        let (destination, ack) = &replies[0];
+
+        assert_eq!(*destination, ip);
+        assert!(ack.is_type(DhcpMessageType::Ack));
+        assert_eq!(ack.get_lease_time(), None);
+        assert_eq!(ack.get_dns_servers(), vec![server_ip()]);
+    }
+}
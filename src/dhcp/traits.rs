@@ -5,7 +5,8 @@ pub trait Serialize {
 
 pub trait Deserialize {
     type Out;
-    fn deserialize(data: &[u8]) -> Option<Self::Out>
+    type Error;
+    fn deserialize(data: &[u8]) -> Result<Self::Out, Self::Error>
     where
         Self::Out: Sized;
 }
@@ -0,0 +1,271 @@
+use crate::dhcp::{
+    client::{ClientAction, ClientEvent},
+    packet::DhcpPacket,
+    traits::{Deserialize, Serialize},
+};
+use log::warn;
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+    time::{Duration, Instant},
+};
+
+pub const SERVER_PORT: u16 = 67;
+pub const CLIENT_PORT: u16 = 68;
+const BUFFER_SIZE: usize = 1500;
+
+// Packets addressed to Ipv4Addr::BROADCAST are broadcast, anything else is unicast
+pub trait DhcpTransport {
+    fn send(&mut self, destination: Ipv4Addr, packet: &DhcpPacket) -> io::Result<()>;
+
+    // Waits up to `timeout` for the next packet, forever when None.
+    // Returns Ok(None) once the timeout passes without one arriving
+    fn receive(&mut self, timeout: Option<Duration>) -> io::Result<Option<DhcpPacket>>;
+
+    // Sends the packets among the actions, handing the rest back to the caller
+    fn perform(&mut self, actions: Vec<ClientAction>) -> io::Result<Vec<ClientAction>> {
+        let mut remaining = vec![];
+        for action in actions {
+            match action {
+                ClientAction::Broadcast(packet) => self.send(Ipv4Addr::BROADCAST, &packet)?,
+                ClientAction::Unicast(addr, packet) => self.send(addr, &packet)?,
+                other => remaining.push(other),
+            }
+        }
+        Ok(remaining)
+    }
+
+    // Blocks until a packet arrives or the client's timer is due
+    fn wait_for_event(&mut self, deadline: Option<Instant>) -> io::Result<ClientEvent> {
+        let timeout = deadline.map(|x| x.saturating_duration_since(Instant::now()));
+        let event = match self.receive(timeout)? {
+            Some(packet) => ClientEvent::PacketReceived(packet),
+            None => ClientEvent::TimerFired,
+        };
+        Ok(event)
+    }
+}
+
+pub struct UdpTransport {
+    socket: UdpSocket,
+    server_port: u16,
+}
+
+impl UdpTransport {
+    // Listens on the DHCP client port of every interface
+    pub fn new() -> io::Result<Self> {
+        Self::bind(
+            SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, CLIENT_PORT),
+            SERVER_PORT,
+        )
+    }
+
+    pub fn bind(local: SocketAddrV4, server_port: u16) -> io::Result<Self> {
+        let socket = UdpSocket::bind(local)?;
+        socket.set_broadcast(true)?;
+        Ok(Self {
+            socket,
+            server_port,
+        })
+    }
+
+    pub fn get_local_addr(&self) -> io::Result<SocketAddrV4> {
+        match self.socket.local_addr()? {
+            SocketAddr::V4(addr) => Ok(addr),
+            SocketAddr::V6(_) => Err(io::ErrorKind::AddrNotAvailable.into()),
+        }
+    }
+}
+
+impl DhcpTransport for UdpTransport {
+    fn send(&mut self, destination: Ipv4Addr, packet: &DhcpPacket) -> io::Result<()> {
+        self.socket
+            .send_to(&packet.serialize(), (destination, self.server_port))?;
+        Ok(())
+    }
+
+    fn receive(&mut self, timeout: Option<Duration>) -> io::Result<Option<DhcpPacket>> {
+        let deadline = timeout.map(|x| Instant::now() + x);
+        let mut buffer = [0; BUFFER_SIZE];
+        loop {
+            let remaining = deadline.map(|x| x.saturating_duration_since(Instant::now()));
+            // A zero read timeout is rejected by the socket, so treat it as expired here
+            if remaining == Some(Duration::from_secs(0)) {
+                return Ok(None);
+            }
+            self.socket.set_read_timeout(remaining)?;
+
+            let size = match self.socket.recv(&mut buffer) {
+                Ok(size) => size,
+                Err(e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    return Ok(None)
+                }
+                Err(e) => return Err(e),
+            };
+            match DhcpPacket::deserialize(&buffer[..size]) {
+                Ok(packet) => return Ok(Some(packet)),
+                Err(e) => warn!("Dropping unparseable packet: {}", e),
+            }
+        }
+    }
+}
+
+// One end of an in-process link, packets sent on one end are received by the other
+pub struct MemoryTransport {
+    sender: Sender<(Ipv4Addr, DhcpPacket)>,
+    receiver: Receiver<(Ipv4Addr, DhcpPacket)>,
+}
+
+impl MemoryTransport {
+    pub fn pair() -> (Self, Self) {
+        let (left_sender, right_receiver) = channel();
+        let (right_sender, left_receiver) = channel();
+        let left = Self {
+            sender: left_sender,
+            receiver: left_receiver,
+        };
+        let right = Self {
+            sender: right_sender,
+            receiver: right_receiver,
+        };
+        (left, right)
+    }
+
+    // Like receive, but also returns where the peer addressed the packet
+    pub fn receive_addressed(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> io::Result<Option<(Ipv4Addr, DhcpPacket)>> {
+        let disconnected = || io::Error::new(io::ErrorKind::BrokenPipe, "peer disconnected");
+        match timeout {
+            Some(timeout) => match self.receiver.recv_timeout(timeout) {
+                Ok(received) => Ok(Some(received)),
+                Err(RecvTimeoutError::Timeout) => Ok(None),
+                Err(RecvTimeoutError::Disconnected) => Err(disconnected()),
+            },
+            None => self.receiver.recv().map(Some).map_err(|_| disconnected()),
+        }
+    }
+}
+
+impl DhcpTransport for MemoryTransport {
+    fn send(&mut self, destination: Ipv4Addr, packet: &DhcpPacket) -> io::Result<()> {
+        self.sender
+            .send((destination, packet.clone()))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "peer disconnected"))
+    }
+
+    fn receive(&mut self, timeout: Option<Duration>) -> io::Result<Option<DhcpPacket>> {
+        Ok(self.receive_addressed(timeout)?.map(|(_, packet)| packet))
+    }
+}
+
+#[cfg(test)]
+mod dhcp_transport {
+    use super::*;
+    use crate::dhcp::{
+        client::{ClientState, DhcpClient},
+        options::DhcpOptionValue,
+        packet::DhcpMessageType,
+    };
+    use mac_address::MacAddress;
+    use pretty_assertions::assert_eq;
+
+    fn reply(request: &DhcpPacket, mtype: DhcpMessageType) -> DhcpPacket {
+        DhcpPacket::new()
+            .with_transaction(&request.get_transaction())
+            .with_client_ip(&Ipv4Addr::new(192, 168, 1, 99))
+            .with_option(DhcpOptionValue::MessageType(mtype))
+            .with_option(DhcpOptionValue::DhcpServerIp(Ipv4Addr::new(192, 168, 1, 1)))
+            .with_option(DhcpOptionValue::LeaseTime(Duration::from_secs(3600)))
+    }
+
+    #[test]
+    fn test_memory_exchange() {
+        let (mut transport, mut server) = MemoryTransport::pair();
+        let mut client = DhcpClient::new(MacAddress::new([0x10, 0x7b, 0x44, 0x93, 0xe6, 0xd0]));
+
+        let actions = client.handle(ClientEvent::Start, Instant::now());
+        assert!(transport.perform(actions).unwrap().is_empty());
+        let (destination, discover) = server.receive_addressed(None).unwrap().unwrap();
+        assert_eq!(destination, Ipv4Addr::BROADCAST);
+        assert!(discover.is_type(DhcpMessageType::Discover));
+
+        server
+            .send(
+                Ipv4Addr::BROADCAST,
+                &reply(&discover, DhcpMessageType::Offer),
+            )
+            .unwrap();
+        let event = transport.wait_for_event(client.get_timeout()).unwrap();
+        transport
+            .perform(client.handle(event, Instant::now()))
+            .unwrap();
+        let request = server.receive(None).unwrap().unwrap();
+        assert!(request.is_type(DhcpMessageType::Request));
+
+        server
+            .send(Ipv4Addr::BROADCAST, &reply(&request, DhcpMessageType::Ack))
+            .unwrap();
+        let event = transport.wait_for_event(client.get_timeout()).unwrap();
+        let actions = transport
+            .perform(client.handle(event, Instant::now()))
+            .unwrap();
+        assert!(matches!(actions[..], [ClientAction::Bound(_)]));
+        assert_eq!(client.get_state(), ClientState::Bound);
+    }
+
+    #[test]
+    fn test_memory_timeout() {
+        let (mut transport, _server) = MemoryTransport::pair();
+
+        assert_eq!(
+            transport.receive(Some(Duration::from_millis(10))).unwrap(),
+            None
+        );
+        assert!(matches!(
+            transport.wait_for_event(Some(Instant::now())).unwrap(),
+            ClientEvent::TimerFired
+        ));
+    }
+
+    #[test]
+    fn test_memory_disconnected() {
+        let (mut transport, server) = MemoryTransport::pair();
+        drop(server);
+
+        assert!(transport
+            .send(Ipv4Addr::BROADCAST, &DhcpPacket::new())
+            .is_err());
+        assert!(transport.receive(None).is_err());
+    }
+
+    #[test]
+    fn test_udp_loopback() {
+        let loopback = |port| SocketAddrV4::new(Ipv4Addr::LOCALHOST, port);
+        let mut server = UdpTransport::bind(loopback(0), 0).unwrap();
+        let server_port = server.get_local_addr().unwrap().port();
+        let mut client = UdpTransport::bind(loopback(0), server_port).unwrap();
+        server.server_port = client.get_local_addr().unwrap().port();
+
+        let packet =
+            DhcpPacket::new().with_option(DhcpOptionValue::MessageType(DhcpMessageType::Discover));
+        client.send(Ipv4Addr::LOCALHOST, &packet).unwrap();
+        let received = server.receive(Some(Duration::from_secs(1))).unwrap();
+        assert_eq!(received, Some(packet));
+
+        // Garbage is dropped and the wait runs out
+        client
+            .socket
+            .send_to(&[1, 2, 3], loopback(server_port))
+            .unwrap();
+        assert_eq!(
+            server.receive(Some(Duration::from_millis(50))).unwrap(),
+            None
+        );
+    }
+}
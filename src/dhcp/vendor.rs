@@ -0,0 +1,250 @@
+use crate::dhcp::{
+    error::DhcpParseError,
+    options::{decode_bool, decode_ip, decode_ip_list, decode_string, decode_u16, decode_u8},
+    packet::{DhcpOption, DhcpPacket},
+    traits::Deserialize,
+};
+use std::{collections::HashMap, convert::TryInto, fmt, net::Ipv4Addr};
+
+// A decoded sub-option of the Vendor Specific Information option
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum VendorValue {
+    Ip(Ipv4Addr),
+    IpList(Vec<Ipv4Addr>),
+    Text(String),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    Bool(bool),
+    // Sub-options without a decoder, or whose body did not fit the expected format
+    Bytes(Vec<u8>),
+}
+
+impl fmt::Display for VendorValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VendorValue::Ip(ip) => write!(f, "{}", ip),
+            VendorValue::IpList(ips) => {
+                let ips: Vec<String> = ips.iter().map(|x| x.to_string()).collect();
+                write!(f, "{}", ips.join(" "))
+            }
+            VendorValue::Text(text) => write!(f, "{}", text),
+            VendorValue::U8(value) => write!(f, "{}", value),
+            VendorValue::U16(value) => write!(f, "{}", value),
+            VendorValue::U32(value) => write!(f, "{}", value),
+            VendorValue::Bool(value) => write!(f, "{}", value),
+            VendorValue::Bytes(bytes) => {
+                let bytes: Vec<String> = bytes.iter().map(|x| format!("{:02x}", x)).collect();
+                write!(f, "{}", bytes.join(":"))
+            }
+        }
+    }
+}
+
+// The layout of a sub-option body, for vendors whose sub-options are plain values
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum VendorFormat {
+    Ip,
+    IpList,
+    Text,
+    U8,
+    U16,
+    U32,
+    Bool,
+    Bytes,
+}
+
+impl VendorFormat {
+    pub fn decode(&self, body: &[u8]) -> Option<VendorValue> {
+        let value = match self {
+            VendorFormat::Ip => VendorValue::Ip(decode_ip(body)?),
+            VendorFormat::IpList => VendorValue::IpList(decode_ip_list(body)?),
+            VendorFormat::Text => VendorValue::Text(decode_string(body)?),
+            VendorFormat::U8 => VendorValue::U8(decode_u8(body)?),
+            VendorFormat::U16 => VendorValue::U16(decode_u16(body)?),
+            VendorFormat::U32 => VendorValue::U32(u32::from_be_bytes(body.try_into().ok()?)),
+            VendorFormat::Bool => VendorValue::Bool(decode_bool(body)?),
+            VendorFormat::Bytes => VendorValue::Bytes(body.to_vec()),
+        };
+        Some(value)
+    }
+}
+
+// Turns the body of one sub-option into a typed value, None leaves it as raw bytes
+pub trait VendorDecoder {
+    fn decode(&self, code: u8, body: &[u8]) -> Option<VendorValue>;
+}
+
+impl<F: Fn(u8, &[u8]) -> Option<VendorValue>> VendorDecoder for F {
+    fn decode(&self, code: u8, body: &[u8]) -> Option<VendorValue> {
+        self(code, body)
+    }
+}
+
+// A decoder built from a table of sub-option codes and their formats
+#[derive(Clone, Default, Debug)]
+pub struct VendorSchema {
+    formats: HashMap<u8, VendorFormat>,
+}
+
+impl VendorSchema {
+    pub fn new() -> Self {
+        VendorSchema::default()
+    }
+
+    pub fn with_sub_option(mut self, code: u8, format: VendorFormat) -> Self {
+        self.formats.insert(code, format);
+        self
+    }
+}
+
+impl VendorDecoder for VendorSchema {
+    fn decode(&self, code: u8, body: &[u8]) -> Option<VendorValue> {
+        self.formats.get(&code)?.decode(body)
+    }
+}
+
+// Splits option 43 into its sub-options, which follow the same code, length, value layout
+// as the options themselves, see RFC 2132 section 8.4
+pub fn parse_sub_options(body: &[u8]) -> Result<Vec<DhcpOption>, DhcpParseError> {
+    DhcpOption::deserialize(body)
+}
+
+// What option 43 means is up to each vendor, so decoders are looked up by the Vendor Class
+// Identifier the client sent
+#[derive(Default)]
+pub struct VendorRegistry {
+    decoders: HashMap<Vec<u8>, Box<dyn VendorDecoder + Send + Sync>>,
+}
+
+impl VendorRegistry {
+    pub fn new() -> Self {
+        VendorRegistry::default()
+    }
+
+    pub fn with_decoder<D: VendorDecoder + Send + Sync + 'static>(
+        mut self,
+        vendor_class: &[u8],
+        decoder: D,
+    ) -> Self {
+        self.decoders
+            .insert(vendor_class.to_vec(), Box::new(decoder));
+        self
+    }
+
+    pub fn is_registered(&self, vendor_class: &[u8]) -> bool {
+        self.decoders.contains_key(vendor_class)
+    }
+
+    // The sub-options in the order the server sent them. Unknown vendor classes and
+    // sub-options are kept as raw bytes, a body that is not encapsulated at all gives None
+    pub fn decode(&self, vendor_class: &[u8], body: &[u8]) -> Option<Vec<(u8, VendorValue)>> {
+        let sub_options = parse_sub_options(body).ok()?;
+        let decoder = self.decoders.get(vendor_class);
+        let values = sub_options
+            .iter()
+            .map(|x| {
+                let value = decoder
+                    .and_then(|decoder| decoder.decode(x.get_id(), x.get_body()))
+                    .unwrap_or_else(|| VendorValue::Bytes(x.get_body().to_vec()));
+                (x.get_id(), value)
+            })
+            .collect();
+        Some(values)
+    }
+
+    // Servers do not have to repeat option 60 in their replies, so the class the client
+    // sent is what picks the decoder
+    pub fn decode_packet(
+        &self,
+        vendor_class: &[u8],
+        packet: &DhcpPacket,
+    ) -> Option<Vec<(u8, VendorValue)>> {
+        self.decode(vendor_class, &packet.get_vendor_specific()?)
+    }
+}
+
+#[cfg(test)]
+mod vendor_registry {
+    use super::*;
+    use crate::dhcp::{options::DhcpOptionValue, traits::Serialize};
+    use pretty_assertions::assert_eq;
+
+    const PHONE: &[u8] = b"ExamplePhone 1.0";
+
+    fn sub_options() -> Vec<u8> {
+        [
+            DhcpOption::new(1, vec![10, 0, 0, 5]),
+            DhcpOption::new(2, b"provision.example.org".to_vec()),
+            DhcpOption::new(3, vec![0, 0, 0x0e, 0x10]),
+            DhcpOption::new(9, vec![0xde, 0xad]),
+        ]
+        .iter()
+        .flat_map(|x| x.serialize())
+        .collect()
+    }
+
+    fn registry() -> VendorRegistry {
+        let schema = VendorSchema::new()
+            .with_sub_option(1, VendorFormat::Ip)
+            .with_sub_option(2, VendorFormat::Text)
+            .with_sub_option(3, VendorFormat::U32);
+        VendorRegistry::new().with_decoder(PHONE, schema)
+    }
+
+    #[test]
+    fn test_schema_decoder() {
+        assert_eq!(
+            registry().decode(PHONE, &sub_options()),
+            Some(vec![
+                (1, VendorValue::Ip(Ipv4Addr::new(10, 0, 0, 5))),
+                (2, VendorValue::Text("provision.example.org".to_string())),
+                (3, VendorValue::U32(3600)),
+                (9, VendorValue::Bytes(vec![0xde, 0xad])),
+            ])
+        );
+        // A body that does not match the format is left alone
+        assert_eq!(
+            registry().decode(PHONE, &DhcpOption::new(1, vec![10, 0]).serialize()),
+            Some(vec![(1, VendorValue::Bytes(vec![10, 0]))])
+        );
+    }
+
+    #[test]
+    fn test_keyed_on_vendor_class() {
+        let registry =
+            registry().with_decoder(b"OtherVendor", |code: u8, body: &[u8]| match code {
+                1 => Some(VendorValue::Text(String::from_utf8_lossy(body).into())),
+                _ => None,
+            });
+        assert!(registry.is_registered(PHONE));
+        assert_eq!(
+            registry.decode(b"OtherVendor", &sub_options()).unwrap()[0],
+            (1, VendorValue::Text("\n\0\0\u{5}".to_string()))
+        );
+        assert_eq!(
+            registry.decode(b"Unknown", &sub_options()).unwrap()[0],
+            (1, VendorValue::Bytes(vec![10, 0, 0, 5]))
+        );
+    }
+
+    #[test]
+    fn test_decode_packet() {
+        let body = [&[0u8][..], &sub_options(), &[255, 0, 0]].concat();
+        let packet = DhcpPacket::new().with_option(DhcpOptionValue::VendorSpecific(body));
+        assert_eq!(registry().decode_packet(PHONE, &packet).unwrap().len(), 4);
+        assert_eq!(registry().decode_packet(PHONE, &DhcpPacket::new()), None);
+        // Opaque vendor data that is not split into sub-options
+        assert_eq!(registry().decode(PHONE, &[1, 9, 0]), None);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            VendorValue::IpList(vec![Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2)])
+                .to_string(),
+            "10.0.0.1 10.0.0.2"
+        );
+        assert_eq!(VendorValue::Bytes(vec![0xde, 0xad]).to_string(), "de:ad");
+    }
+}
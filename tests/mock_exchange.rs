@@ -0,0 +1,283 @@
+use dchp_client::dhcp::{
+    testing::{Fault, MockServer},
+    ClientAction, ClientEvent, ClientIdentifier, ClientState, DhcpClient, DhcpMessageType,
+    DhcpOptionValue, DhcpTransport, Duid, Lease, MemoryTransport, Serialize, VendorFormat,
+    VendorRegistry, VendorSchema, VendorValue,
+};
+use mac_address::MacAddress;
+use pretty_assertions::assert_eq;
+use std::{
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+fn test_mac() -> MacAddress {
+    MacAddress::new([0x10, 0x7b, 0x44, 0x93, 0xe6, 0xd0])
+}
+
+// Runs the client against the server until it binds or gives up, jumping the clock
+// forward to the next timer whenever the server stays silent
+fn run(client: &mut DhcpClient, server: &mut MockServer) -> Vec<ClientAction> {
+    let (mut transport, mut server_end) = MemoryTransport::pair();
+    let mut now = Instant::now();
+    let mut seen = vec![];
+    let mut actions = client.handle(ClientEvent::Start, now);
+    for _ in 0..20 {
+        let remaining = transport.perform(actions).unwrap();
+        let done = remaining
+            .iter()
+            .any(|x| matches!(x, ClientAction::Bound(_) | ClientAction::TimedOut(_)));
+        seen.extend(remaining);
+        if done {
+            break;
+        }
+
+        server.serve_pending(&mut server_end).unwrap();
+        let event = match transport.receive(Some(Duration::from_secs(0))).unwrap() {
+            Some(packet) => ClientEvent::PacketReceived(packet),
+            None => {
+                now = client.get_timeout().unwrap_or(now);
+                ClientEvent::TimerFired
+            }
+        };
+        actions = client.handle(event, now);
+    }
+    seen
+}
+
+#[test]
+fn test_discover_to_ack() {
+    let router = Ipv4Addr::new(192, 168, 1, 1);
+    let mut server = MockServer::new(router)
+        .with_lease_time(Duration::from_secs(600))
+        .with_option(DhcpOptionValue::RouterIp(vec![router]));
+    let mut client = DhcpClient::new(test_mac());
+
+    let actions = run(&mut client, &mut server);
+
+    assert_eq!(client.get_state(), ClientState::Bound);
+    let lease = client.get_lease().unwrap();
+    assert_eq!(lease.get_client_ip(), Ipv4Addr::new(192, 168, 1, 2));
+    assert_eq!(lease.get_server_ip(), router);
+    assert_eq!(lease.get_lease_time(), Duration::from_secs(600));
+    assert_eq!(lease.get_packet().get_router_ip(), Some(router));
+    assert!(matches!(actions[..], [ClientAction::Bound(_)]));
+    assert_eq!(
+        server.get_lease(&test_mac().bytes()),
+        Some(lease.get_client_ip())
+    );
+}
+
+#[test]
+fn test_rapid_commit() {
+    let mut server = MockServer::new(Ipv4Addr::new(192, 168, 1, 1)).with_rapid_commit(true);
+    let mut client = DhcpClient::new(test_mac()).with_rapid_commit(true);
+
+    let actions = run(&mut client, &mut server);
+
+    assert!(matches!(actions[..], [ClientAction::Bound(_)]));
+    let received: Vec<_> = server
+        .get_received()
+        .iter()
+        .map(|x| x.get_type().unwrap())
+        .collect();
+    assert_eq!(received, vec![DhcpMessageType::Discover]);
+}
+
+#[test]
+fn test_rapid_commit_without_server_support() {
+    let mut server = MockServer::new(Ipv4Addr::new(192, 168, 1, 1));
+    let mut client = DhcpClient::new(test_mac()).with_rapid_commit(true);
+
+    let actions = run(&mut client, &mut server);
+
+    assert!(matches!(actions[..], [ClientAction::Bound(_)]));
+    assert_eq!(server.get_received().len(), 2);
+}
+
+#[test]
+fn test_init_reboot_keeps_address() {
+    let previous_ip = Ipv4Addr::new(192, 168, 1, 42);
+    let mut server = MockServer::new(Ipv4Addr::new(192, 168, 1, 1));
+    let mut client = DhcpClient::new(test_mac()).with_previous_ip(&previous_ip);
+
+    let actions = run(&mut client, &mut server);
+
+    assert!(matches!(actions[..], [ClientAction::Bound(_)]));
+    assert_eq!(client.get_lease().unwrap().get_client_ip(), previous_ip);
+    assert_eq!(server.get_received().len(), 1);
+}
+
+#[test]
+fn test_init_reboot_on_another_network() {
+    let mut server = MockServer::new(Ipv4Addr::new(10, 0, 0, 1));
+    let mut client = DhcpClient::new(test_mac()).with_previous_ip(&Ipv4Addr::new(192, 168, 1, 42));
+
+    let actions = run(&mut client, &mut server);
+
+    assert!(matches!(
+        actions[..],
+        [ClientAction::Nak(_), ClientAction::Bound(_)]
+    ));
+    assert_eq!(
+        client.get_lease().unwrap().get_client_ip(),
+        Ipv4Addr::new(10, 0, 0, 2)
+    );
+}
+
+#[test]
+fn test_client_id_survives_nic_swap() {
+    let client_id = ClientIdentifier::new(1, Duid::Uuid([7; 16]));
+    let mut server = MockServer::new(Ipv4Addr::new(192, 168, 1, 1));
+    let mut client = DhcpClient::new(test_mac()).with_client_id(&client_id);
+    run(&mut client, &mut server);
+    let first_ip = client.get_lease().unwrap().get_client_ip();
+
+    let new_mac = MacAddress::new([0x10, 0x7b, 0x44, 0x93, 0xe6, 0xff]);
+    let mut client = DhcpClient::new(new_mac).with_client_id(&client_id);
+    let actions = run(&mut client, &mut server);
+
+    assert!(matches!(actions[..], [ClientAction::Bound(_)]));
+    assert_eq!(client.get_lease().unwrap().get_client_ip(), first_ip);
+    assert_eq!(server.get_lease(&client_id.serialize()), Some(first_ip));
+    assert_eq!(server.get_lease(&test_mac().bytes()), None);
+}
+
+#[test]
+fn test_vendor_options_decoded_by_class() {
+    let vendor_class = b"ExamplePhone 1.0";
+    // Provisioning server, then a port, in the vendor's own sub-option space
+    let body = [1, 4, 10, 0, 0, 5, 2, 2, 0x1f, 0x90, 255];
+    let mut server =
+        MockServer::new(Ipv4Addr::new(192, 168, 1, 1)).with_vendor_options(vendor_class, &body);
+    let mut client = DhcpClient::new(test_mac()).with_vendor_class(vendor_class);
+    run(&mut client, &mut server);
+
+    let schema = VendorSchema::new()
+        .with_sub_option(1, VendorFormat::Ip)
+        .with_sub_option(2, VendorFormat::U16);
+    let registry = VendorRegistry::new().with_decoder(vendor_class, schema);
+    let ack = client.get_lease().unwrap().get_packet();
+    assert_eq!(
+        registry.decode_packet(client.get_vendor_class().unwrap(), ack),
+        Some(vec![
+            (1, VendorValue::Ip(Ipv4Addr::new(10, 0, 0, 5))),
+            (2, VendorValue::U16(8080)),
+        ])
+    );
+}
+
+// Unicasts the RENEWING request straight to the server that handed out the lease
+fn renew_held_lease(server: &mut MockServer, lease: &Lease) -> Vec<ClientAction> {
+    let now = Instant::now();
+    let mut client = DhcpClient::new(test_mac()).with_held_lease(lease);
+    let request = match &client.handle(ClientEvent::Start, now)[..] {
+        [ClientAction::Unicast(destination, request)] => {
+            assert_eq!(*destination, server.get_server_ip());
+            request.clone()
+        }
+        other => panic!("Expected a unicast request, got {:?}", other),
+    };
+    assert_eq!(request.get_current_ip(), lease.get_client_ip());
+    server
+        .respond(&request)
+        .into_iter()
+        .flat_map(|(_, reply)| client.handle(ClientEvent::PacketReceived(reply), now))
+        .collect()
+}
+
+#[test]
+fn test_renew_held_lease() {
+    let mut server = MockServer::new(Ipv4Addr::new(192, 168, 1, 1));
+    let mut client = DhcpClient::new(test_mac());
+    run(&mut client, &mut server);
+    let lease = client.get_lease().unwrap().clone();
+
+    let actions = renew_held_lease(&mut server, &lease);
+    assert!(
+        matches!(&actions[..], [ClientAction::Renewed(x)] if x.get_client_ip() == lease.get_client_ip())
+    );
+
+    let mut server = server.with_fault(DhcpMessageType::Request, Fault::Nak);
+    let actions = renew_held_lease(&mut server, &lease);
+    assert_eq!(
+        actions,
+        vec![ClientAction::Nak(Some(server.get_server_ip()))]
+    );
+}
+
+#[test]
+fn test_recovers_from_nak() {
+    let mut server = MockServer::new(Ipv4Addr::new(192, 168, 1, 1))
+        .with_fault(DhcpMessageType::Request, Fault::Nak);
+    let mut client = DhcpClient::new(test_mac());
+
+    let actions = run(&mut client, &mut server);
+
+    assert!(matches!(
+        actions[..],
+        [ClientAction::Nak(_), ClientAction::Bound(_)]
+    ));
+    assert_eq!(client.get_state(), ClientState::Bound);
+}
+
+#[test]
+fn test_duplicate_offer_is_ignored() {
+    let mut server = MockServer::new(Ipv4Addr::new(192, 168, 1, 1))
+        .with_fault(DhcpMessageType::Discover, Fault::DuplicateOffer);
+    let mut client = DhcpClient::new(test_mac());
+
+    run(&mut client, &mut server);
+
+    assert_eq!(client.get_state(), ClientState::Bound);
+    let requests = server
+        .get_received()
+        .iter()
+        .filter(|x| x.is_type(DhcpMessageType::Request))
+        .count();
+    assert_eq!(requests, 1);
+}
+
+#[test]
+fn test_retransmits_after_silence() {
+    let mut server = MockServer::new(Ipv4Addr::new(192, 168, 1, 1))
+        .with_fault(DhcpMessageType::Discover, Fault::Silence)
+        .with_fault(DhcpMessageType::Request, Fault::Silence);
+    let mut client = DhcpClient::new(test_mac());
+
+    let actions = run(&mut client, &mut server);
+
+    assert!(matches!(actions[..], [ClientAction::Bound(_)]));
+    let received: Vec<_> = server
+        .get_received()
+        .iter()
+        .map(|x| (x.get_type().unwrap(), x.get_transaction()))
+        .collect();
+    let transaction = client.get_transaction();
+    assert_eq!(
+        received,
+        vec![
+            (DhcpMessageType::Discover, transaction),
+            (DhcpMessageType::Discover, transaction),
+            (DhcpMessageType::Request, transaction),
+            (DhcpMessageType::Request, transaction),
+        ]
+    );
+}
+
+#[test]
+fn test_silent_server_times_out() {
+    let mut server = MockServer::new(Ipv4Addr::new(192, 168, 1, 1));
+    for _ in 0..3 {
+        server = server.with_fault(DhcpMessageType::Discover, Fault::Silence);
+    }
+    let mut client = DhcpClient::new(test_mac()).with_retries(2, 2);
+
+    let actions = run(&mut client, &mut server);
+
+    assert_eq!(
+        actions,
+        vec![ClientAction::TimedOut(ClientState::Selecting)]
+    );
+    assert_eq!(server.get_received().len(), 3);
+}